@@ -0,0 +1,223 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// The detection expects the background to be exactly one color. Instead of taking the top left
+/// pixel we estimate the background from the borders of the photo (median color and spread of
+/// the pixels) which also works for colored mats, dark cloths or slightly patterned surfaces.
+/// All the pixels matching the model and connected to the border of the photo are then repainted
+/// with the median color, the pixels inside the pieces are kept even if they match the model.
+
+//load external
+extern crate image;
+
+//std
+use std::collections::VecDeque;
+
+//extern
+use image::{RgbaImage,Rgba};
+
+//internal
+use common;
+
+/// Model of the background color.
+#[derive(Debug,Clone,Copy)]
+pub struct BackgroundModel {
+	/// Median color of the border pixels, used as the exact background after normalization.
+	pub color: Rgba<u8>,
+	/// Maximal distance on one channel to consider a pixel as background.
+	pub tolerance: u8,
+	/// Ratio of the non background pixels which are close to the tolerance, so ambiguous.
+	pub ambiguous: f32,
+}
+
+/// Distance between two colors as the max difference on one channel.
+fn calc_color_dist(a: &Rgba<u8>,b: &Rgba<u8>) -> u8 {
+	(0..3).map(|c| (a.data[c] as i32 - b.data[c] as i32).abs() as u8).max().unwrap()
+}
+
+/// Extract the pixels from the border strips of the photo.
+fn extract_border_pixels(img: &RgbaImage) -> Vec<Rgba<u8>> {
+	let (w,h) = img.dimensions();
+	let border = ((w.min(h) as f32 * common::BACKGROUND_BORDER_RATIO) as u32).max(1);
+	img.enumerate_pixels()
+		.filter(|&(x,y,_)| x < border || y < border || x + border >= w || y + border >= h)
+		.map(|(_,_,p)| *p)
+		.collect()
+}
+
+impl BackgroundModel {
+	/// Model taking the exact color of the top left pixel as before.
+	pub fn from_corner(img: &RgbaImage) -> Self {
+		Self {
+			color: *img.get_pixel(0,0),
+			tolerance: 0,
+			ambiguous: 0.0,
+		}
+	}
+
+	/// Estimate the model from the borders of the photo. The tolerance is taken from the
+	/// distribution of the border pixels around the median so it follows the noise and the
+	/// patterns of the background.
+	pub fn estimate(img: &RgbaImage) -> Self {
+		let mut model = Self::from_border_pixels(&extract_border_pixels(img));
+
+		//check how many non background pixels are close to the limit
+		let mut other = 0;
+		let mut close = 0;
+		for p in img.pixels() {
+			if !model.is_background(p) {
+				other += 1;
+				if model.is_ambiguous(p) {
+					close += 1;
+				}
+			}
+		}
+		model.ambiguous = if other == 0 {0.0} else {close as f32 / other as f32};
+		model
+	}
+
+	/// Build the model from the pixels of the borders, without computing the ambiguity.
+	pub fn from_border_pixels(pixels: &Vec<Rgba<u8>>) -> Self {
+		//median per channel
+		let mut color = [0u8,0,0,255];
+		for c in 0..3 {
+			let mut values: Vec<u8> = pixels.iter().map(|p| p.data[c]).collect();
+			values.sort();
+			color[c] = values[values.len() / 2];
+		}
+		let color = Rgba(color);
+
+		//tolerance
+		let mut dists: Vec<u8> = pixels.iter().map(|p| calc_color_dist(p,&color)).collect();
+		dists.sort();
+		let percentile = dists[((dists.len() - 1) as f32 * common::BACKGROUND_PERCENTILE) as usize];
+		let tolerance = (percentile as f32 * common::BACKGROUND_TOLERANCE_FACTOR).max(common::BACKGROUND_MIN_TOLERANCE as f32).min(255.0) as u8;
+
+		Self {
+			color: color,
+			tolerance: tolerance,
+			ambiguous: 0.0,
+		}
+	}
+
+	/// Check if a non background pixel is close to the tolerance.
+	pub fn is_ambiguous(self: &Self,p: &Rgba<u8>) -> bool {
+		let d = calc_color_dist(p,&self.color) as u32;
+		d > self.tolerance as u32 && d <= 2 * self.tolerance as u32
+	}
+
+	/// Check if a pixel match the background.
+	pub fn is_background(self: &Self,p: &Rgba<u8>) -> bool {
+		calc_color_dist(p,&self.color) <= self.tolerance
+	}
+
+	/// Check if the pieces can be separated from the background.
+	pub fn is_separable(self: &Self) -> bool {
+		self.ambiguous <= common::BACKGROUND_MAX_AMBIGUOUS
+	}
+
+	/// Repaint the pixels matching the model with the exact background color. We grow from the
+	/// border of the photo so the pixels of the pieces close to the background color are kept.
+	/// Return the number of pixels changed.
+	pub fn normalize(self: &Self,img: &mut RgbaImage) -> u32 {
+		//vars
+		let (w,h) = img.dimensions();
+		let mut seen = vec![false;(w * h) as usize];
+		let mut todo = VecDeque::new();
+
+		//start from the border
+		for (x,y,p) in img.enumerate_pixels() {
+			if (x == 0 || y == 0 || x + 1 == w || y + 1 == h) && self.is_background(p) {
+				seen[(y * w + x) as usize] = true;
+				todo.push_back((x,y));
+			}
+		}
+
+		//grow into the background
+		let mut cnt = 0;
+		while let Some((x,y)) = todo.pop_front() {
+			if *img.get_pixel(x,y) != self.color {
+				img.put_pixel(x,y,self.color);
+				cnt += 1;
+			}
+			for &(dx,dy) in [(-1i64,0i64),(1,0),(0,-1),(0,1)].iter() {
+				let (nx,ny) = (x as i64 + dx,y as i64 + dy);
+				if nx < 0 || ny < 0 || nx >= w as i64 || ny >= h as i64 {
+					continue;
+				}
+				let (nx,ny) = (nx as u32,ny as u32);
+				let id = (ny * w + nx) as usize;
+				if !seen[id] && self.is_background(img.get_pixel(nx,ny)) {
+					seen[id] = true;
+					todo.push_back((nx,ny));
+				}
+			}
+		}
+		cnt
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use image::{Rgba,RgbaImage};
+	use background::*;
+	use generator;
+	use step1_detect;
+
+	#[test]
+	fn estimate_green_mat() {
+		//noisy green mat
+		let mut config = generator::GeneratorConfig::default();
+		config.background = Rgba([40,110,60,255]);
+		config.noise = 6;
+		let (mut img,pieces) = generator::generate(&config);
+
+		//model
+		let model = BackgroundModel::estimate(&img);
+		assert!(calc_color_dist(&model.color,&config.background) <= 3);
+		assert!(model.is_separable());
+		model.normalize(&mut img);
+
+		//all pieces are found
+		let mut cnt = 0;
+		loop {
+			let first = step1_detect::find_first_non_bg_pixel(&img,&model.color);
+			if first == (0,0) {
+				break;
+			}
+			let square = step1_detect::find_square_non_bg(&img,&model.color,first);
+			if square.2 * square.3 > 600 {
+				cnt += 1;
+			}
+			step1_detect::paint_square(&mut img,&model.color,square);
+		}
+		assert_eq!(cnt,pieces.len());
+	}
+
+	#[test]
+	fn normalize_keep_pieces() {
+		let back = Rgba([100,100,100,255]);
+		let model = BackgroundModel{color:back,tolerance:4,ambiguous:0.0};
+		let mut img = RgbaImage::from_pixel(20,20,back);
+		for y in 5..15 {
+			for x in 5..15 {
+				img.put_pixel(x,y,Rgba([200,50,50,255]));
+			}
+		}
+
+		//noise on the background and a piece pixel close to the background color
+		let noise = Rgba([102,99,101,255]);
+		img.put_pixel(2,3,noise);
+		img.put_pixel(10,10,noise);
+
+		//check
+		assert_eq!(model.normalize(&mut img),1);
+		assert_eq!(*img.get_pixel(2,3),back);
+		assert_eq!(*img.get_pixel(10,10),noise);
+	}
+}
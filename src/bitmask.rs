@@ -0,0 +1,312 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// Binary mask packing one pixel per bit in rows of u64 words. Moving, combining and counting
+/// the pixels of two masks is done 64 pixels at a time instead of calling get_pixel() on a
+/// GrayImage, which is what the matching does for each pair of faces and each offset. The pieces
+/// keep their GrayImage mask as the other steps paint and read it pixel by pixel, the matching
+/// packs it once per piece.
+
+//load external
+extern crate image;
+
+//extern
+use image::{GrayImage,Luma};
+
+//std
+use std::u32;
+
+//internal
+use common;
+
+/// Bit packed binary mask, the bits after the width in the last word of a row are always 0.
+#[derive(Debug,Clone,PartialEq)]
+pub struct BitMask {
+	width: u32,
+	height: u32,
+	/// Number of words in each row.
+	stride: usize,
+	words: Vec<u64>,
+}
+
+/// Index of the word holding the given bit, rounding down for the negative ones.
+fn floor_div64(bit: i64) -> i64 {
+	if bit >= 0 {bit / 64} else {(bit - 63) / 64}
+}
+
+/// Read 64 bits of the row starting at the given bit (which can be out of the row).
+fn read_bits(row: &[u64],start: i64) -> u64 {
+	let word = |i: i64| if i >= 0 && (i as usize) < row.len() {row[i as usize]} else {0};
+	let q = floor_div64(start);
+	let r = (start - q * 64) as u32;
+	if r == 0 {
+		word(q)
+	} else {
+		(word(q) >> r) | (word(q + 1) << (64 - r))
+	}
+}
+
+impl BitMask {
+	/// Build an empty mask.
+	pub fn new(width: u32,height: u32) -> Self {
+		let stride = ((width + 63) / 64) as usize;
+		Self {
+			width: width,
+			height: height,
+			stride: stride,
+			words: vec![0;stride * height as usize],
+		}
+	}
+
+	/// Build from the pixels of the image having the given value.
+	pub fn from_gray(img: &GrayImage,value: u8) -> Self {
+		let (w,h) = img.dimensions();
+		let mut ret = Self::new(w,h);
+		for (x,y,p) in img.enumerate_pixels() {
+			if p.data[0] == value {
+				ret.set(x,y);
+			}
+		}
+		ret
+	}
+
+	/// Convert into an image with the given value for the bits set.
+	pub fn to_gray(self: &Self,value: u8) -> GrayImage {
+		GrayImage::from_fn(self.width,self.height,|x,y| {
+			if self.get(x,y) {
+				Luma([value])
+			} else {
+				Luma([common::MASK_BACKGROUND])
+			}
+		})
+	}
+
+	pub fn dimensions(self: &Self) -> (u32,u32) {
+		(self.width,self.height)
+	}
+
+	pub fn get(self: &Self,x: u32,y: u32) -> bool {
+		let word = self.words[y as usize * self.stride + (x / 64) as usize];
+		(word >> (x % 64)) & 1 == 1
+	}
+
+	pub fn set(self: &mut Self,x: u32,y: u32) {
+		assert!(x < self.width && y < self.height);
+		self.words[y as usize * self.stride + (x / 64) as usize] |= 1 << (x % 64);
+	}
+
+	/// Apply the operation word by word on two masks of the same size.
+	fn combine<F: Fn(u64,u64) -> u64>(self: &Self,other: &Self,op: F) -> Self {
+		assert_eq!(self.dimensions(),other.dimensions());
+		let mut ret = self.clone();
+		for (a,b) in ret.words.iter_mut().zip(other.words.iter()) {
+			*a = op(*a,*b);
+		}
+		ret
+	}
+
+	pub fn or(self: &Self,other: &Self) -> Self {
+		self.combine(other,|a,b| a | b)
+	}
+
+	/// Move the mask by (dx,dy) into a new mask of the given size, the pixels going out are lost.
+	pub fn shift(self: &Self,dx: i32,dy: i32,width: u32,height: u32) -> Self {
+		let mut ret = Self::new(width,height);
+		let last = if width % 64 == 0 {!0} else {(1u64 << (width % 64)) - 1};
+		for y in 0..height {
+			let sy = y as i64 - dy as i64;
+			if sy < 0 || sy >= self.height as i64 {
+				continue;
+			}
+			let src = &self.words[sy as usize * self.stride..(sy as usize + 1) * self.stride];
+			let dst = &mut ret.words[y as usize * ret.stride..(y as usize + 1) * ret.stride];
+			for (j,word) in dst.iter_mut().enumerate() {
+				*word = read_bits(src,j as i64 * 64 - dx as i64);
+			}
+			if let Some(word) = dst.last_mut() {
+				*word &= last;
+			}
+		}
+		ret
+	}
+
+	/// Words of the row, empty out of the mask.
+	fn get_row(self: &Self,y: i64) -> &[u64] {
+		if y >= 0 && y < self.height as i64 {
+			&self.words[y as usize * self.stride..(y as usize + 1) * self.stride]
+		} else {
+			&[]
+		}
+	}
+
+	/// Count the pixels of the rect (xmin,ymin,xmax,ymax excluded) for which op() gives 1 on the
+	/// words of this mask and of the other one moved by (dx,dy). The pixels out of the masks are
+	/// read as 0, so the rect can go out of them, and the other mask is read moved instead of
+	/// being built.
+	pub fn count_shifted<F: Fn(u64,u64) -> u64>(self: &Self,other: &Self,dx: i32,dy: i32,rect: (i32,i32,i32,i32),op: F) -> u32 {
+		let (x0,y0,x1,y1) = (rect.0 as i64,rect.1 as i64,rect.2 as i64,rect.3 as i64);
+		let mut cnt = 0;
+		if x0 >= x1 {
+			return cnt;
+		}
+
+		//words of the rect and bits to keep in the first and last ones
+		let (j0,j1) = (floor_div64(x0),floor_div64(x1 - 1));
+		let first = !0u64 << (x0 - j0 * 64);
+		let last = !0u64 >> (63 - (x1 - 1 - j1 * 64));
+
+		//the other mask is read with the same offset for all the words
+		let q = floor_div64(-(dx as i64));
+		let r = (-(dx as i64) - q * 64) as u32;
+		let word = |row: &[u64],i: i64| if i >= 0 && (i as usize) < row.len() {row[i as usize]} else {0};
+
+		for y in y0..y1 {
+			let (dst,src) = (self.get_row(y),other.get_row(y - dy as i64));
+			for j in j0..(j1 + 1) {
+				let moved = if r == 0 {
+					word(src,j + q)
+				} else {
+					(word(src,j + q) >> r) | (word(src,j + q + 1) << (64 - r))
+				};
+				let mut bits = op(word(dst,j),moved);
+				if j == j0 {
+					bits &= first;
+				}
+				if j == j1 {
+					bits &= last;
+				}
+				cnt += bits.count_ones();
+			}
+		}
+		cnt
+	}
+
+	/// Turn by 90 degrees clockwise, like imageops::rotate90().
+	pub fn rotate90(self: &Self) -> Self {
+		let mut ret = Self::new(self.height,self.width);
+		for y in 0..self.height {
+			for j in 0..self.stride {
+				let mut word = self.words[y as usize * self.stride + j];
+				while word != 0 {
+					let x = j as u32 * 64 + word.trailing_zeros();
+					ret.set(self.height - 1 - y,x);
+					word &= word - 1;
+				}
+			}
+		}
+		ret
+	}
+
+	/// Turn by the given number of quarters clockwise.
+	pub fn rotate(self: &Self,quarters: usize) -> Self {
+		let mut ret = self.clone();
+		for _ in 0..(quarters % 4) {
+			ret = ret.rotate90();
+		}
+		ret
+	}
+
+	/// Rectangle surrounding the bits set (xmin,ymin,xmax,ymax), same convention than
+	/// step5_corners::extract_surrounding_rect().
+	pub fn bounding_rect(self: &Self) -> (u32,u32,u32,u32) {
+		let mut rect = (u32::MAX,u32::MAX,0,0);
+		for y in 0..self.height {
+			for j in 0..self.stride {
+				let word = self.words[y as usize * self.stride + j];
+				if word != 0 {
+					let x0 = j as u32 * 64 + word.trailing_zeros();
+					let x1 = j as u32 * 64 + 63 - word.leading_zeros();
+					rect = (rect.0.min(x0),rect.1.min(y),rect.2.max(x1),rect.3.max(y));
+				}
+			}
+		}
+		rect
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,Luma,imageops};
+	use bitmask::*;
+	use common;
+
+	fn build() -> GrayImage {
+		GrayImage::from_fn(150,70,|x,y| {
+			if (x * 7 + y * 3) % 11 < 4 || (x > 60 && x < 130 && y > 20 && y < 40) {
+				Luma([common::MASK_PIECE_PIXEL])
+			} else {
+				Luma([common::MASK_BACKGROUND])
+			}
+		})
+	}
+
+	fn same(a: &GrayImage,b: &GrayImage) -> bool {
+		a.dimensions() == b.dimensions() && a.pixels().eq(b.pixels())
+	}
+
+	#[test]
+	fn rotate_like_image() {
+		let img = build();
+		let mask = BitMask::from_gray(&img,common::MASK_PIECE_PIXEL);
+		assert!(same(&mask.to_gray(common::MASK_PIECE_PIXEL),&img));
+		assert!(same(&mask.rotate90().to_gray(common::MASK_PIECE_PIXEL),&imageops::rotate90(&img)));
+		assert!(same(&mask.rotate(2).to_gray(common::MASK_PIECE_PIXEL),&imageops::rotate180(&img)));
+	}
+
+	#[test]
+	fn shift_and_or() {
+		let img = build();
+		let mask = BitMask::from_gray(&img,common::MASK_PIECE_PIXEL);
+		for &(dx,dy) in [(0,0),(5,-3),(-70,10),(129,1)].iter() {
+			let moved = mask.shift(dx,dy,100,90);
+			for y in 0..90 {
+				for x in 0..100 {
+					let (sx,sy) = (x as i32 - dx,y as i32 - dy);
+					let expect = sx >= 0 && sy >= 0 && sx < 150 && sy < 70 && mask.get(sx as u32,sy as u32);
+					assert_eq!(moved.get(x,y),expect);
+				}
+			}
+		}
+
+		let other = mask.shift(1,0,150,70);
+		let both = mask.or(&other);
+		for y in 0..70 {
+			for x in 0..150 {
+				assert_eq!(both.get(x,y),mask.get(x,y) || other.get(x,y));
+			}
+		}
+		assert_eq!(mask.bounding_rect(),(0,0,149,69));
+	}
+
+	#[test]
+	fn count_moved() {
+		let img = build();
+		let mask = BitMask::from_gray(&img,common::MASK_PIECE_PIXEL);
+		let other = BitMask::from_gray(&imageops::rotate180(&img),common::MASK_PIECE_PIXEL);
+		for &(dx,dy) in [(0,0),(7,-3),(-70,10),(100,1)].iter() {
+			for &rect in [(0,0,150,70),(3,5,130,60),(64,10,128,11),(-70,-5,200,100)].iter() {
+				//same pixels with both masks moved inside a larger one
+				let (mx,my) = (100,10);
+				let big = mask.shift(mx,my,400,120);
+				let moved = other.shift(dx + mx,dy + my,400,120);
+				let (mut both,mut none) = (0,0);
+				for y in (rect.1 + my)..(rect.3 + my) {
+					for x in (rect.0 + mx)..(rect.2 + mx) {
+						match (big.get(x as u32,y as u32),moved.get(x as u32,y as u32)) {
+							(true,true) => both += 1,
+							(false,false) => none += 1,
+							_ => {},
+						}
+					}
+				}
+				assert_eq!(mask.count_shifted(&other,dx,dy,rect,|a,b| a & b),both);
+				assert_eq!(mask.count_shifted(&other,dx,dy,rect,|a,b| !(a | b)),none);
+			}
+		}
+	}
+}
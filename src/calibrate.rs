@@ -0,0 +1,399 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// Wide lenses bend the straight lines near the border of the photo. We estimate the radial
+/// and tangential distortion from photos of a printed checkerboard : the dark squares are
+/// aligned in the real world so we search the coefficients making their centers aligned
+/// again (plumb line method). The coefficients are stored into a profile file which can then
+/// be used to undistort the photos before the detection.
+
+//load external
+extern crate image;
+
+//std
+use std::f32;
+use std::fs::File;
+use std::io::{Read,Write};
+use std::path::Path;
+use std::collections::VecDeque;
+
+//extern
+use image::RgbaImage;
+
+//internal
+use step0_perspective;
+use common;
+
+/// Distortion coefficients (Brown model) working on coordinates normalized by the half
+/// diagonal of the photo around its center.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct DistortionProfile {
+	pub k1: f32,
+	pub k2: f32,
+	pub p1: f32,
+	pub p2: f32,
+}
+
+impl DistortionProfile {
+	/// Profile without any distortion.
+	pub fn new() -> Self {
+		Self {
+			k1: 0.0,
+			k2: 0.0,
+			p1: 0.0,
+			p2: 0.0,
+		}
+	}
+
+	fn get(self: &Self,id: usize) -> f32 {
+		match id {
+			0 => self.k1,
+			1 => self.k2,
+			2 => self.p1,
+			_ => self.p2,
+		}
+	}
+
+	fn set(self: &mut Self,id: usize,value: f32) {
+		match id {
+			0 => self.k1 = value,
+			1 => self.k2 = value,
+			2 => self.p1 = value,
+			_ => self.p2 = value,
+		}
+	}
+
+	/// Apply the distortion on a normalized point (what the lens does).
+	pub fn distort_point(self: &Self,p: (f32,f32)) -> (f32,f32) {
+		let (x,y) = p;
+		let r2 = x * x + y * y;
+		let radial = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+		(
+			x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+			y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+		)
+	}
+
+	/// Remove the distortion of a normalized point, the model cannot be inverted directly so
+	/// we iterate from the distorted position.
+	pub fn undistort_point(self: &Self,p: (f32,f32)) -> (f32,f32) {
+		let mut ret = p;
+		for _ in 0..common::CALIBRATE_UNDISTORT_ITERATIONS {
+			let (x,y) = ret;
+			let r2 = x * x + y * y;
+			let radial = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+			let dx = 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+			let dy = self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+			ret = ((p.0 - dx) / radial,(p.1 - dy) / radial);
+		}
+		ret
+	}
+
+	/// Save the profile as a simple text file with one coefficient per line.
+	pub fn save(self: &Self,fname: &str) {
+		let mut file = File::create(fname).unwrap();
+		write!(file,"k1 {}\nk2 {}\np1 {}\np2 {}\n",self.k1,self.k2,self.p1,self.p2).unwrap();
+	}
+
+	/// Load a profile saved by save().
+	pub fn load(fname: &str) -> Self {
+		let mut content = String::new();
+		File::open(&Path::new(fname)).unwrap().read_to_string(&mut content).unwrap();
+
+		let mut ret = Self::new();
+		for line in content.lines() {
+			let parts: Vec<&str> = line.split_whitespace().collect();
+			if parts.len() != 2 {
+				continue;
+			}
+			let value: f32 = parts[1].parse().expect("Invalid value in distortion profile !");
+			match parts[0] {
+				"k1" => ret.k1 = value,
+				"k2" => ret.k2 = value,
+				"p1" => ret.p1 = value,
+				"p2" => ret.p2 = value,
+				_ => panic!("Invalid key {} in distortion profile !",parts[0]),
+			}
+		}
+		ret
+	}
+}
+
+/// Center and half diagonal used to normalize the coordinates of a photo.
+fn get_normalization(size: (u32,u32)) -> ((f32,f32),f32) {
+	let (w,h) = (size.0 as f32,size.1 as f32);
+	((w / 2.0,h / 2.0),(w * w + h * h).sqrt() / 2.0)
+}
+
+/// Search the center of the dark squares of the checkerboard. We ignore the regions touching
+/// the border and the ones with a size far from the median one.
+fn find_dark_squares(img: &RgbaImage) -> Vec<(f32,f32)> {
+	//vars
+	let (w,h) = img.dimensions();
+	let threshold = step0_perspective::calc_otsu_threshold(img);
+	let mut seen = vec![false;(w * h) as usize];
+	let mut regions: Vec<((f32,f32),u32)> = vec!();
+
+	//loop on all dark regions
+	for start in 0..(w * h) {
+		if seen[start as usize] || step0_perspective::get_luminance(img.get_pixel(start % w,start / w)) > threshold {
+			continue;
+		}
+
+		//fill
+		let mut sum = (0.0,0.0);
+		let mut size = 0;
+		let mut border = false;
+		let mut todo = VecDeque::new();
+		seen[start as usize] = true;
+		todo.push_back((start % w,start / w));
+		while let Some((x,y)) = todo.pop_front() {
+			sum = (sum.0 + x as f32,sum.1 + y as f32);
+			size += 1;
+			border |= x == 0 || y == 0 || x == w - 1 || y == h - 1;
+			for &(dx,dy) in [(-1i64,0i64),(1,0),(0,-1),(0,1)].iter() {
+				let (nx,ny) = (x as i64 + dx,y as i64 + dy);
+				if nx >= 0 && ny >= 0 && nx < w as i64 && ny < h as i64 {
+					let id = (ny as u32 * w + nx as u32) as usize;
+					if !seen[id] && step0_perspective::get_luminance(img.get_pixel(nx as u32,ny as u32)) <= threshold {
+						seen[id] = true;
+						todo.push_back((nx as u32,ny as u32));
+					}
+				}
+			}
+		}
+
+		if !border && size >= common::CALIBRATE_MIN_SQUARE_SIZE {
+			regions.push(((sum.0 / size as f32,sum.1 / size as f32),size));
+		}
+	}
+
+	//filter on size
+	if regions.is_empty() {
+		return vec!();
+	}
+	let mut sizes: Vec<u32> = regions.iter().map(|r| r.1).collect();
+	sizes.sort();
+	let median = sizes[sizes.len() / 2] as f32;
+	regions.iter()
+		.filter(|r| (r.1 as f32) > median / common::CALIBRATE_SQUARE_SIZE_RATIO && (r.1 as f32) < median * common::CALIBRATE_SQUARE_SIZE_RATIO)
+		.map(|r| r.0)
+		.collect()
+}
+
+fn calc_dist(a: (f32,f32),b: (f32,f32)) -> f32 {
+	((a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)).sqrt()
+}
+
+/// Search the point closest to the expected position if close enough.
+fn find_closest(points: &Vec<(f32,f32)>,expected: (f32,f32),max: f32) -> Option<usize> {
+	let mut ret = None;
+	let mut best = max;
+	for (i,p) in points.iter().enumerate() {
+		let d = calc_dist(*p,expected);
+		if d < best {
+			best = d;
+			ret = Some(i);
+		}
+	}
+	ret
+}
+
+/// Chain the centers into the lines of the board. The closest dark squares are on the
+/// diagonals so we follow the two main directions of the nearest neighboors.
+fn build_lines(points: &Vec<(f32,f32)>) -> Vec<Vec<(f32,f32)>> {
+	//need at least a small board
+	let mut ret = vec!();
+	if points.len() < common::CALIBRATE_MIN_LINE_POINTS {
+		return ret;
+	}
+
+	//nearest neighboors
+	let mut spacing = vec!();
+	let mut sum = (0.0,0.0);
+	for (i,p) in points.iter().enumerate() {
+		let mut best = (f32::MAX,(0.0,0.0));
+		for (j,q) in points.iter().enumerate() {
+			let d = calc_dist(*p,*q);
+			if i != j && d < best.0 {
+				best = (d,(q.0 - p.0,q.1 - p.1));
+			}
+		}
+		//average the angles modulo 90 degree
+		let angle = 4.0 * (best.1).1.atan2((best.1).0);
+		sum = (sum.0 + angle.cos(),sum.1 + angle.sin());
+		spacing.push(best.0);
+	}
+	spacing.sort_by(|a,b| a.partial_cmp(b).unwrap());
+	let step = spacing[spacing.len() / 2];
+	let angle = sum.1.atan2(sum.0) / 4.0;
+	let tolerance = step * common::CALIBRATE_CHAIN_TOLERANCE;
+
+	//follow the two directions
+	for dir in [angle,angle + f32::consts::PI / 2.0].iter() {
+		let delta = (step * dir.cos(),step * dir.sin());
+		let next: Vec<Option<usize>> = points.iter()
+			.map(|p| find_closest(points,(p.0 + delta.0,p.1 + delta.1),tolerance))
+			.collect();
+		let mut has_prev = vec![false;points.len()];
+		for n in next.iter() {
+			if let Some(n) = n {
+				has_prev[*n] = true;
+			}
+		}
+
+		//chain from the points starting a line
+		for start in 0..points.len() {
+			if has_prev[start] {
+				continue;
+			}
+			let mut line = vec!(points[start]);
+			let mut cur = start;
+			while let Some(n) = next[cur] {
+				if line.len() > points.len() {
+					break;
+				}
+				line.push(points[n]);
+				cur = n;
+			}
+			if line.len() >= common::CALIBRATE_MIN_LINE_POINTS {
+				ret.push(line);
+			}
+		}
+	}
+
+	ret
+}
+
+/// Distance of the points to a straight line once undistorted, normalized by the length of
+/// the line so we cannot reduce it by shrinking everything.
+fn calc_line_cost(profile: &DistortionProfile,line: &Vec<(f32,f32)>) -> f32 {
+	//undistort
+	let points: Vec<(f32,f32)> = line.iter().map(|p| profile.undistort_point(*p)).collect();
+	let n = points.len() as f32;
+	let mean = points.iter().fold((0.0,0.0),|acc,p| (acc.0 + p.0 / n,acc.1 + p.1 / n));
+
+	//covariance
+	let (mut sxx,mut syy,mut sxy) = (0.0,0.0,0.0);
+	for p in points.iter() {
+		let (dx,dy) = (p.0 - mean.0,p.1 - mean.1);
+		sxx += dx * dx;
+		syy += dy * dy;
+		sxy += dx * dy;
+	}
+
+	//smallest eigen value is the sum of square distance to the best line, largest one is the extent
+	let tr = sxx + syy;
+	let disc = ((sxx - syy) * (sxx - syy) + 4.0 * sxy * sxy).sqrt();
+	let small = (tr - disc) / 2.0;
+	let large = (tr + disc) / 2.0;
+	if large <= 0.0 {
+		0.0
+	} else {
+		small / large
+	}
+}
+
+fn calc_cost(profile: &DistortionProfile,lines: &Vec<Vec<(f32,f32)>>) -> f32 {
+	lines.iter().map(|l| calc_line_cost(profile,l)).sum::<f32>() / lines.len().max(1) as f32
+}
+
+/// Search the coefficients making the lines (in normalized coordinates) straight by
+/// coordinate descent.
+pub fn fit_profile(lines: &Vec<Vec<(f32,f32)>>) -> DistortionProfile {
+	let mut ret = DistortionProfile::new();
+	let mut cost = calc_cost(&ret,lines);
+	let mut step = common::CALIBRATE_INITIAL_STEP;
+
+	while step > common::CALIBRATE_MIN_STEP {
+		let mut improved = false;
+		for id in 0..4 {
+			for sign in [-1.0,1.0].iter() {
+				let mut test = ret;
+				test.set(id,ret.get(id) + sign * step);
+				let c = calc_cost(&test,lines);
+				if c < cost {
+					cost = c;
+					ret = test;
+					improved = true;
+				}
+			}
+		}
+		if !improved {
+			step /= 2.0;
+		}
+	}
+
+	println!("Distortion fit : {:?}, cost = {}",ret,cost);
+	ret
+}
+
+/// Extract the lines of the checkerboard on all the photos and fit the distortion profile.
+pub fn calibrate(files: &Vec<String>) -> DistortionProfile {
+	let mut lines = vec!();
+	for file in files.iter() {
+		//load
+		println!("Load calibration image {:?}",file);
+		let img = image::open(&Path::new(file)).unwrap().to_rgba();
+		let (center,radius) = get_normalization(img.dimensions());
+
+		//extract
+		let points = find_dark_squares(&img);
+		let found = build_lines(&points);
+		println!("Found {} squares and {} lines",points.len(),found.len());
+
+		//normalize
+		for l in found.iter() {
+			lines.push(l.iter().map(|p| ((p.0 - center.0) / radius,(p.1 - center.1) / radius)).collect());
+		}
+	}
+
+	if lines.is_empty() {
+		panic!("No checkerboard line found in the calibration pictures !");
+	}
+
+	fit_profile(&lines)
+}
+
+/// Build the undistorted photo, each pixel is taken where the lens put it.
+pub fn undistort_image(img: &RgbaImage,profile: &DistortionProfile) -> RgbaImage {
+	let (w,h) = img.dimensions();
+	let (center,radius) = get_normalization((w,h));
+	RgbaImage::from_fn(w,h,|x,y| {
+		let p = ((x as f32 + 0.5 - center.0) / radius,(y as f32 + 0.5 - center.1) / radius);
+		let d = profile.distort_point(p);
+		let pos = (d.0 * radius + center.0 - 0.5,d.1 * radius + center.1 - 0.5);
+		step0_perspective::get_pixel_bilinear(img,(pos.0 as f64,pos.1 as f64))
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use calibrate::*;
+
+	#[test]
+	fn fit_barrel_distortion() {
+		let lens = DistortionProfile{k1: -0.12,k2: 0.02,p1: 0.0,p2: 0.0};
+
+		//round trip
+		let p = (0.5,-0.3);
+		let back = lens.undistort_point(lens.distort_point(p));
+		assert!(calc_dist(p,back) < 1e-4);
+
+		//distorted grid lines
+		let mut lines = vec!();
+		for i in 0..7 {
+			let v = -0.6 + 0.2 * i as f32;
+			lines.push((0..9).map(|j| lens.distort_point((-0.6 + 0.15 * j as f32,v))).collect());
+			lines.push((0..9).map(|j| lens.distort_point((v,-0.6 + 0.15 * j as f32))).collect());
+		}
+
+		let fit = fit_profile(&lines);
+		assert!((fit.k1 - lens.k1).abs() < 0.02);
+	}
+}
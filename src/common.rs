@@ -9,27 +9,128 @@
 //setup colors
 pub const MASK_BACKGROUND: u8 = 0u8;
 pub const MASK_PIECE_PIXEL: u8 = 127u8;
-pub const MASK_SURROUND_RECT: u8 = 255u8;
-pub const MASK_HIDDEN_BUMP: u8 = 64u8;
-pub const MASK_POINTS_INTREST: u8 = 254u8;
-pub const MASK_POINTS_BACK: u8 = 253u8;
 pub const MASK_IGNORE_SUPERP: u8 = 16u8;
 
+//setup colors of the debug overlay
+pub const OVERLAY_NONE: u8 = 0u8;
+pub const OVERLAY_SURROUND_RECT: u8 = 255u8;
+pub const OVERLAY_HIDDEN_BUMP: u8 = 64u8;
+pub const OVERLAY_POINTS_INTREST: u8 = 254u8;
+pub const OVERLAY_POINTS_BACK: u8 = 253u8;
+
 //quality mark
 pub const QUALITY_CORNER_CUT1: f32 = 5.0;
 pub const QUALITY_CORNER_CUT2: f32 = 10.0;
+pub const QUALITY_CORNER_MIN_CONFIDENCE: f32 = 0.4;
 pub const QUALITY_BUMP_HOLE_CUT1: f32 = 5.0;
 pub const QUALITY_BUMP_HOLE_CUT2: f32 = 10.0;
 pub const QUALITY_BUMP_HOLE_CUT3: f32 = 12.0;
 pub const QUALITY_BUMP_HOLE_CUT4: f32 = 18.0;
+pub const QUALITY_TAB_MAX_FIT_ERROR: f32 = 1.5;
+pub const QUALITY_TAB_OFFSET_CUT: f32 = 15.0;
 
 //fix
-pub const FIX_MIN_QUALITY: u32 = 16;
+pub const FIX_MIN_QUALITY: u32 = 18;
+pub const FIX_RESCORE_ANGLES: usize = 4;
 
 //consts
 pub const ROTATE_ANGLE_RESOLUTION: u32 = 1;
+pub const ROTATE_MARGIN: u32 = 10;
 pub const FIX_ROTATE_ANGLE_RESOLUTION: u32 = 2;
 
+//corners
+pub const CORNER_SPAN_RATIO: f32 = 0.1;
+pub const CORNER_MIN_SPAN: usize = 4;
+pub const CORNER_MAX_STRAIGHT_ERR: f32 = 1.5;
+pub const CORNER_MIN_CONFIDENCE: f32 = 0.3;
+pub const CORNER_REFINE_SKIP: usize = 2;
+
+//tabs
+pub const TAB_MIN_DEPTH_RATIO: f32 = 0.05;
+pub const TAB_HEAD_LEVEL: f32 = 0.6;
+pub const TAB_NECK_MIN_LEVEL: f32 = 0.15;
+
 //matching
 pub const MATCH_MASK_OFFET: i32 = 3;
-pub const MATCH_MASK_OFFSET_STEP:i32 = 3;
\ No newline at end of file
+pub const MATCH_MASK_OFFSET_STEP:i32 = 3;
+pub const MATCH_TAB_COUNT_PENALTY: f32 = 10.0;
+pub const MATCH_MIN_SIDE_CONFIDENCE: f32 = 0.3;
+
+//reference
+pub const REFERENCE_SIGN_CELLS: u32 = 4;
+pub const REFERENCE_OUT_COST: f32 = 1.0;
+pub const REFERENCE_WEIGHT: f32 = 2.0;
+pub const REFERENCE_MATCH_KEEP: usize = 3;
+
+
+//draw
+pub const DRAW_MAX_PLACEMENT_ANGLE: f32 = 10.0;
+pub const DRAW_SVG_SIMPLIFY: f32 = 0.7;
+
+//perspective
+pub const PERSPECTIVE_MIN_SHEET_RATIO: f32 = 0.2;
+pub const PERSPECTIVE_MIN_SHIFT: f32 = 4.0;
+pub const PERSPECTIVE_SHEET_MARGIN: u32 = 4;
+
+//calibration
+pub const CALIBRATE_UNDISTORT_ITERATIONS: u32 = 20;
+pub const CALIBRATE_MIN_SQUARE_SIZE: u32 = 30;
+pub const CALIBRATE_SQUARE_SIZE_RATIO: f32 = 2.5;
+pub const CALIBRATE_MIN_LINE_POINTS: usize = 4;
+pub const CALIBRATE_CHAIN_TOLERANCE: f32 = 0.3;
+pub const CALIBRATE_INITIAL_STEP: f32 = 0.05;
+pub const CALIBRATE_MIN_STEP: f32 = 0.00001;
+
+//shadow
+pub const SHADOW_MIN_RATIO: f32 = 0.35;
+pub const SHADOW_MAX_CHROMA: f32 = 0.04;
+pub const SHADOW_MAX_STEP: i32 = 30;
+pub const SHADOW_MAX_WIDTH: u32 = 20;
+pub const SHADOW_GUIDE_TOLERANCE: i32 = 12;
+
+//background
+pub const BACKGROUND_BORDER_RATIO: f32 = 0.05;
+pub const BACKGROUND_PERCENTILE: f32 = 0.98;
+pub const BACKGROUND_TOLERANCE_FACTOR: f32 = 1.5;
+pub const BACKGROUND_MIN_TOLERANCE: u8 = 6;
+pub const BACKGROUND_MAX_AMBIGUOUS: f32 = 0.2;
+
+//split
+pub const SPLIT_MIN_PIECES: usize = 3;
+pub const SPLIT_AREA_RATIO: f32 = 1.6;
+pub const SPLIT_MARKER_MIN_DIST: f32 = 0.4;
+pub const SPLIT_SEAM_WIDTH: u32 = 2;
+pub const SPLIT_SEAM_CONTRAST: i32 = 40;
+pub const SPLIT_CLUSTER_SEAM_RATIO: f32 = 0.5;
+
+//tiled
+pub const TILED_DEFAULT_ROWS: u32 = 512;
+pub const TILED_MIN_AREA: u32 = 600;
+pub const TILED_MAX_BORDER_SAMPLES: u32 = 1000000;
+
+//pyramid
+pub const PYRAMID_COARSE_SIZE: u32 = 256;
+
+//scale
+pub const SCALE_MARKER_MIN_FILL: f32 = 0.9;
+pub const SCALE_MARKER_MIN_ASPECT: f32 = 0.9;
+pub const SCALE_MIN_CHANGE: f32 = 0.02;
+
+//fingerprint
+pub const FINGERPRINT_RADIAL_BINS: usize = 64;
+pub const FINGERPRINT_HARMONICS: usize = 16;
+pub const FINGERPRINT_COLOR_WEIGHT: f32 = 0.5;
+pub const FINGERPRINT_DUPLICATE_DIST: f32 = 0.05;
+pub const FINGERPRINT_KNOWN_DIST: f32 = 0.1;
+
+//front/back
+pub const FRONTBACK_ANGLE_STEP: f32 = 2.0;
+pub const FRONTBACK_MAX_DIST: f32 = 0.1;
+pub const FRONTBACK_MIN_OVERLAP: f32 = 0.8;
+
+//face down
+pub const FACEDOWN_BORDER: u32 = 3;
+pub const FACEDOWN_MAX_STD: f32 = 12.0;
+pub const FACEDOWN_STD_RATIO: f32 = 0.5;
+pub const FACEDOWN_MAX_SATURATION: f32 = 0.45;
+pub const FACEDOWN_MAX_COLOR_DIST: f32 = 30.0;
@@ -0,0 +1,183 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// Extract the outline of a piece from its mask as a list of points. This is used to export
+/// the pieces as vector shapes and to analyse the shape along the border.
+
+//load external
+extern crate image;
+
+//std
+use std::f32;
+
+//internal
+use common;
+
+/// 8 neighboors in clockwise order (y going down) starting on the left.
+const NEIGHBOORS: [(i32,i32);8] = [(-1,0),(-1,-1),(0,-1),(1,-1),(1,0),(1,1),(0,1),(-1,1)];
+
+/// Outline of a piece, closed (last point is connected to the first one).
+pub type Contour = Vec<(u32,u32)>;
+
+fn is_piece(img: &image::GrayImage,x: i32,y: i32) -> bool {
+	let (w,h) = img.dimensions();
+	x >= 0 && y >= 0 && (x as u32) < w && (y as u32) < h && img.get_pixel(x as u32,y as u32).data[0] == common::MASK_PIECE_PIXEL
+}
+
+fn get_direction(dx: i32,dy: i32) -> usize {
+	NEIGHBOORS.iter().position(|d| *d == (dx,dy)).unwrap()
+}
+
+/// Follow the outline of the first piece object found in the mask (Moore neighboor tracing).
+/// Pixels are returned in clockwise order starting from the top left one.
+pub fn extract_contour(img: &image::GrayImage) -> Contour {
+	//search first pixel
+	let start = match img.enumerate_pixels().find(|&(_,_,p)| p.data[0] == common::MASK_PIECE_PIXEL) {
+		Some((x,y,_)) => (x as i32,y as i32),
+		None => return vec!(),
+	};
+
+	//vars
+	let (w,h) = img.dimensions();
+	let mut ret: Contour = vec!((start.0 as u32,start.1 as u32));
+	let mut cur = start;
+	//we come from the left as the first pixel is the left most of its line
+	let mut back = 0;
+	let mut second: Option<(i32,i32)> = None;
+
+	//walk
+	for _ in 0..(4 * w * h) {
+		//search next pixel turning clockwise from the one we come from
+		let mut next = None;
+		for k in 1..9 {
+			let dir = (back + k) % 8;
+			let (x,y) = (cur.0 + NEIGHBOORS[dir].0,cur.1 + NEIGHBOORS[dir].1);
+			if is_piece(img,x,y) {
+				let prev = (cur.0 + NEIGHBOORS[(dir + 7) % 8].0,cur.1 + NEIGHBOORS[(dir + 7) % 8].1);
+				back = get_direction(prev.0 - x,prev.1 - y);
+				next = Some((x,y));
+				break;
+			}
+		}
+
+		//isolated pixel
+		let next = match next {
+			Some(n) => n,
+			None => break,
+		};
+
+		//stop when we come back on the starting move
+		if cur == start && second == Some(next) {
+			break;
+		}
+		if second.is_none() {
+			second = Some(next);
+		}
+
+		//move
+		cur = next;
+		if cur != start {
+			ret.push((cur.0 as u32,cur.1 as u32));
+		}
+	}
+
+	ret
+}
+
+fn calc_dist_to_segment(p: (f32,f32),a: (f32,f32),b: (f32,f32)) -> f32 {
+	let (dx,dy) = (b.0 - a.0,b.1 - a.1);
+	let len2 = dx * dx + dy * dy;
+	if len2 == 0.0 {
+		((p.0 - a.0) * (p.0 - a.0) + (p.1 - a.1) * (p.1 - a.1)).sqrt()
+	} else {
+		((p.0 - a.0) * dy - (p.1 - a.1) * dx).abs() / len2.sqrt()
+	}
+}
+
+fn simplify_segment(points: &[(u32,u32)],epsilon: f32,out: &mut Contour) {
+	//nothing to remove
+	if points.len() < 3 {
+		out.extend_from_slice(&points[..points.len().saturating_sub(1)]);
+		return;
+	}
+
+	//search farest point
+	let a = (points[0].0 as f32,points[0].1 as f32);
+	let b = (points[points.len()-1].0 as f32,points[points.len()-1].1 as f32);
+	let mut max = 0.0;
+	let mut index = 0;
+	for i in 1..(points.len()-1) {
+		let d = calc_dist_to_segment((points[i].0 as f32,points[i].1 as f32),a,b);
+		if d > max {
+			max = d;
+			index = i;
+		}
+	}
+
+	//split or keep only the first point
+	if max > epsilon {
+		simplify_segment(&points[..(index+1)],epsilon,out);
+		simplify_segment(&points[index..],epsilon,out);
+	} else {
+		out.push(points[0]);
+	}
+}
+
+/// Reduce the number of points of the contour keeping the shape at epsilon pixels (Douglas-Peucker).
+pub fn simplify_contour(contour: &Contour,epsilon: f32) -> Contour {
+	//too small
+	if contour.len() < 4 {
+		return contour.clone();
+	}
+
+	//split the closed contour at the farest point from the start
+	let start = (contour[0].0 as f32,contour[0].1 as f32);
+	let mut far = 0;
+	let mut max = 0.0;
+	for (i,p) in contour.iter().enumerate() {
+		let d = (p.0 as f32 - start.0).powi(2) + (p.1 as f32 - start.1).powi(2);
+		if d > max {
+			max = d;
+			far = i;
+		}
+	}
+
+	//apply on both halfs
+	let mut closed = contour.clone();
+	closed.push(contour[0]);
+	let mut ret = vec!();
+	simplify_segment(&closed[..(far+1)],epsilon,&mut ret);
+	simplify_segment(&closed[far..],epsilon,&mut ret);
+	ret
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,Luma};
+	use contour::*;
+	use common;
+
+	#[test]
+	fn extract_contour_square() {
+		let mut img = GrayImage::new(10,10);
+		for y in 2..6 {
+			for x in 3..8 {
+				img.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+
+		let contour = extract_contour(&img);
+		assert_eq!(contour.len(),14);
+		assert_eq!(contour[0],(3,2));
+		assert_eq!(contour[4],(7,2));
+		assert_eq!(contour[7],(7,5));
+
+		let simple = simplify_contour(&contour,0.5);
+		assert_eq!(simple,vec!((3,2),(7,2),(7,5),(3,5)));
+	}
+}
@@ -0,0 +1,167 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// A piece lying picture side down has the shape of a piece but shows the cardboard, which
+/// gives wrong colors to the reference prior and to the rendering. The back of the pieces has
+/// nearly no color variation, a low saturation and all the backs have the same color. We
+/// compare those statistics to the other pieces to mark the pieces face down.
+
+//load external
+extern crate image;
+
+//extern
+use image::{GrayImage,RgbaImage};
+
+//internal
+use piece::PieceVec;
+use common;
+
+/// Color statistics of the inner pixels of a piece.
+#[derive(Debug,Clone,Copy)]
+pub struct ColorStats {
+	pub mean: [f32;3],
+	/// Mean of the standard deviation of the channels.
+	pub std: f32,
+	/// Saturation of the mean color (HSV) in [0,1].
+	pub saturation: f32,
+}
+
+/// Compute the statistics ignoring the pixels close to the border which mix with the background.
+pub fn calc_color_stats(image: &RgbaImage,mask: &GrayImage) -> ColorStats {
+	//select inner pixels
+	let (w,h) = mask.dimensions();
+	let m = common::FACEDOWN_BORDER;
+	let is_piece = |x: u32,y: u32| mask.get_pixel(x,y).data[0] == common::MASK_PIECE_PIXEL;
+	let mut sum = [0.0f64;3];
+	let mut sum2 = [0.0f64;3];
+	let mut cnt = 0;
+	for y in m..(h.saturating_sub(m)) {
+		for x in m..(w.saturating_sub(m)) {
+			if is_piece(x,y) && is_piece(x - m,y) && is_piece(x + m,y) && is_piece(x,y - m) && is_piece(x,y + m) {
+				let p = image.get_pixel(x,y);
+				for c in 0..3 {
+					let v = p.data[c] as f64;
+					sum[c] += v;
+					sum2[c] += v * v;
+				}
+				cnt += 1;
+			}
+		}
+	}
+
+	//compute
+	let n = (cnt as f64).max(1.0);
+	let mut mean = [0.0f32;3];
+	let mut std = 0.0;
+	for c in 0..3 {
+		let avg = sum[c] / n;
+		mean[c] = avg as f32;
+		std += ((sum2[c] / n - avg * avg).max(0.0)).sqrt() as f32 / 3.0;
+	}
+	let max = mean[0].max(mean[1]).max(mean[2]);
+	let min = mean[0].min(mean[1]).min(mean[2]);
+	let saturation = if max <= 0.0 {0.0} else {(max - min) / max};
+
+	ColorStats {
+		mean: mean,
+		std: std,
+		saturation: saturation,
+	}
+}
+
+/// Classify the pieces from their statistics, return the indexes of the ones face down.
+pub fn classify(stats: &Vec<ColorStats>) -> Vec<usize> {
+	//a broken piece can give NaN which cannot be sorted
+	let valid: Vec<usize> = (0..stats.len()).filter(|i| {
+		let s = &stats[*i];
+		s.std.is_finite() && s.saturation.is_finite() && s.mean.iter().all(|v| v.is_finite())
+	}).collect();
+
+	//median of the variation
+	let mut stds: Vec<f32> = valid.iter().map(|i| stats[*i].std).collect();
+	stds.sort_by(|a,b| a.partial_cmp(b).unwrap());
+	let median = match stds.get(stds.len() / 2) {
+		Some(m) => *m,
+		None => return vec!(),
+	};
+
+	//flat and grey pieces
+	let candidates: Vec<usize> = valid.into_iter().filter(|i| {
+		let s = &stats[*i];
+		s.std <= common::FACEDOWN_MAX_STD && s.std <= common::FACEDOWN_STD_RATIO * median && s.saturation <= common::FACEDOWN_MAX_SATURATION
+	}).collect();
+	if candidates.len() < 2 {
+		return candidates;
+	}
+
+	//the backs share the same color, the others are plain colored fronts
+	let mut back = [0.0f32;3];
+	for c in 0..3 {
+		let mut values: Vec<f32> = candidates.iter().map(|i| stats[*i].mean[c]).collect();
+		values.sort_by(|a,b| a.partial_cmp(b).unwrap());
+		back[c] = values[values.len() / 2];
+	}
+	candidates.into_iter().filter(|i| {
+		(0..3).map(|c| (stats[*i].mean[c] - back[c]).abs()).fold(0.0,f32::max) <= common::FACEDOWN_MAX_COLOR_DIST
+	}).collect()
+}
+
+/// Mark the pieces face down and return their ids.
+pub fn detect_face_down(pieces: &PieceVec) -> Vec<usize> {
+	let stats: Vec<ColorStats> = pieces.iter().map(|p| {
+		let p = p.read().unwrap();
+		calc_color_stats(&p.image,&p.mask)
+	}).collect();
+	let ret = classify(&stats);
+	for i in ret.iter() {
+		println!("Piece {} is face down : {:?}",i,stats[*i]);
+		pieces[*i].write().unwrap().face_down = true;
+	}
+	ret
+}
+
+#[cfg(test)]
+mod test {
+	use facedown::*;
+
+	fn build(mean: [f32;3],std: f32) -> ColorStats {
+		let max = mean[0].max(mean[1]).max(mean[2]);
+		let min = mean[0].min(mean[1]).min(mean[2]);
+		ColorStats{mean: mean,std: std,saturation: (max - min) / max}
+	}
+
+	#[test]
+	fn classify_backs() {
+		let stats = vec!(
+			build([120.0,80.0,60.0],40.0),
+			build([40.0,90.0,200.0],6.0),
+			build([190.0,170.0,140.0],5.0),
+			build([60.0,140.0,60.0],35.0),
+			build([185.0,168.0,142.0],4.0),
+			build([200.0,100.0,90.0],45.0),
+			build([90.0,90.0,90.0],5.0),
+			build([30.0,30.0,200.0],50.0),
+			build([220.0,210.0,40.0],38.0),
+			build([100.0,50.0,150.0],42.0),
+		);
+		assert_eq!(classify(&stats),vec!(2,4));
+	}
+
+	#[test]
+	fn classify_ignore_nan() {
+		let stats = vec!(
+			build([120.0,80.0,60.0],40.0),
+			build([190.0,170.0,140.0],5.0),
+			build([0.0,0.0,0.0],0.0),
+			build([60.0,140.0,60.0],35.0),
+			build([185.0,168.0,142.0],4.0),
+			build([200.0,100.0,90.0],f32::NAN),
+		);
+		assert_eq!(classify(&stats),vec!(1,4));
+	}
+}
@@ -0,0 +1,320 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// The ids of the pieces are given by the order of the detection so they change each time
+/// the table is photographed again. We compute a fingerprint of each piece which does not
+/// depend on its rotation : the magnitude of the Fourier transform of the distance from the
+/// center to the contour (a rotation is only a shift of this signal) and a color histogram.
+/// It is used to keep the ids in a small database between the runs and to merge the pieces
+/// seen on several photos.
+
+//load external
+extern crate image;
+
+//std
+use std::f32;
+use std::fs::File;
+use std::io::{BufRead,BufReader,Write};
+
+//extern
+use image::{GrayImage,RgbaImage};
+
+//internal
+use piece::{Piece,PieceVec};
+use common;
+
+/// Rotation invariant signature of a piece.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Fingerprint {
+	/// Magnitude of the first harmonics of the radial signature, normalized by the mean radius.
+	pub shape: Vec<f32>,
+	/// Normalized histogram of the colors of the piece.
+	pub color: Vec<f32>,
+}
+
+/// Fingerprints known from the previous runs with their stable ids.
+pub struct FingerprintDb {
+	pub entries: Vec<(u32,Fingerprint)>,
+}
+
+impl Fingerprint {
+	/// Compute the fingerprint from the image and the mask of the piece before rotation.
+	pub fn compute(image: &RgbaImage,mask: &GrayImage) -> Self {
+		//center
+		let mut sum = (0.0,0.0);
+		let mut cnt = 0;
+		for (x,y,p) in mask.enumerate_pixels() {
+			if p.data[0] == common::MASK_PIECE_PIXEL {
+				sum = (sum.0 + x as f32,sum.1 + y as f32);
+				cnt += 1;
+			}
+		}
+		let cnt = cnt.max(1) as f32;
+		let center = (sum.0 / cnt,sum.1 / cnt);
+
+		//radial signature, farthest pixel for each direction
+		let bins = common::FINGERPRINT_RADIAL_BINS;
+		let mut radius = vec![0.0f32;bins];
+		let mut color = vec![0.0f32;64];
+		for (x,y,p) in mask.enumerate_pixels() {
+			if p.data[0] != common::MASK_PIECE_PIXEL {
+				continue;
+			}
+			let (dx,dy) = (x as f32 - center.0,y as f32 - center.1);
+			let angle = dy.atan2(dx) + f32::consts::PI;
+			let bin = ((angle / (2.0 * f32::consts::PI) * bins as f32) as usize).min(bins - 1);
+			radius[bin] = radius[bin].max((dx * dx + dy * dy).sqrt());
+
+			let c = image.get_pixel(x,y).data;
+			color[(c[0] / 64) as usize * 16 + (c[1] / 64) as usize * 4 + (c[2] / 64) as usize] += 1.0;
+		}
+		for c in color.iter_mut() {
+			*c /= cnt;
+		}
+
+		//magnitude of the harmonics
+		let mean = (radius.iter().sum::<f32>() / bins as f32).max(1.0);
+		let shape = (1..(common::FINGERPRINT_HARMONICS + 1)).map(|k| {
+			let (mut re,mut im) = (0.0,0.0);
+			for (i,r) in radius.iter().enumerate() {
+				let a = 2.0 * f32::consts::PI * (k * i) as f32 / bins as f32;
+				re += r * a.cos();
+				im += r * a.sin();
+			}
+			(re * re + im * im).sqrt() / (mean * bins as f32)
+		}).collect();
+
+		Self {
+			shape: shape,
+			color: color,
+		}
+	}
+
+	/// Distance between two fingerprints.
+	pub fn distance(self: &Self,other: &Self) -> f32 {
+		let color: f32 = self.color.iter().zip(other.color.iter()).map(|(a,b)| (a - b).abs()).sum::<f32>() / 2.0;
+		self.shape_distance(other) + common::FINGERPRINT_COLOR_WEIGHT * color
+	}
+
+	/// Distance between the shapes only, when the colors cannot be compared.
+	pub fn shape_distance(self: &Self,other: &Self) -> f32 {
+		self.shape.iter().zip(other.shape.iter()).map(|(a,b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+	}
+
+	/// Convert into a line of the database.
+	fn to_line(self: &Self,id: u32) -> String {
+		let shape: Vec<String> = self.shape.iter().map(|v| format!("{}",v)).collect();
+		let color: Vec<String> = self.color.iter().map(|v| format!("{}",v)).collect();
+		format!("{} {} ; {}\n",id,shape.join(" "),color.join(" "))
+	}
+
+	/// Parse a line of the database.
+	fn from_line(line: &str) -> Option<(u32,Self)> {
+		let parts: Vec<&str> = line.split(';').collect();
+		if parts.len() != 2 {
+			return None;
+		}
+		let mut first = parts[0].split_whitespace();
+		let id = first.next()?.parse().ok()?;
+		let shape: Result<Vec<f32>,_> = first.map(|v| v.parse()).collect();
+		let color: Result<Vec<f32>,_> = parts[1].split_whitespace().map(|v| v.parse()).collect();
+		Some((id,Self{shape: shape.ok()?,color: color.ok()?}))
+	}
+}
+
+/// Compute the fingerprint of all the pieces.
+pub fn compute_all(pieces: &PieceVec) {
+	for pp in pieces.iter() {
+		let mut p = pp.write().unwrap();
+		let fingerprint = Fingerprint::compute(&p.image,&p.mask);
+		p.fingerprint = Some(fingerprint);
+	}
+}
+
+/// Get the fingerprint of a piece, it should have been computed before.
+fn get(p: &Piece) -> &Fingerprint {
+	p.fingerprint.as_ref().expect("Fingerprint not computed !")
+}
+
+/// Pair the two lists of fingerprints by increasing distance, each one being used once.
+/// Return the pairs of indexes.
+pub fn pair_fingerprints<F: Fn(&Fingerprint,&Fingerprint) -> f32>(a: &Vec<&Fingerprint>,b: &Vec<&Fingerprint>,dist: F,max_dist: f32) -> Vec<(usize,usize)> {
+	let mut dists = vec!();
+	for (i,fa) in a.iter().enumerate() {
+		for (j,fb) in b.iter().enumerate() {
+			//an empty piece gives NaN which cannot be sorted
+			let d = dist(fa,fb);
+			if d.is_finite() && d <= max_dist {
+				dists.push((d,i,j));
+			}
+		}
+	}
+	dists.sort_by(|x,y| x.0.partial_cmp(&y.0).unwrap());
+
+	let mut used_a = vec![false;a.len()];
+	let mut used_b = vec![false;b.len()];
+	let mut ret = vec!();
+	for (_,i,j) in dists {
+		if !used_a[i] && !used_b[j] {
+			used_a[i] = true;
+			used_b[j] = true;
+			ret.push((i,j));
+		}
+	}
+	ret
+}
+
+/// Remove the pieces seen on several photos, keeping the first one. The pieces are renumbered
+/// and the assembled links to the removed ones are dropped. Return the number of removed pieces.
+pub fn merge_duplicates(pieces: &mut PieceVec) -> usize {
+	//search duplicates between each pair of photos
+	let nb_photos = pieces.iter().map(|p| p.read().unwrap().photo + 1).max().unwrap_or(0);
+	let mut removed = vec![false;pieces.len()];
+	for photo1 in 0..nb_photos {
+		for photo2 in (photo1 + 1)..nb_photos {
+			let list1: Vec<usize> = (0..pieces.len()).filter(|i| !removed[*i] && pieces[*i].read().unwrap().photo == photo1).collect();
+			let list2: Vec<usize> = (0..pieces.len()).filter(|i| !removed[*i] && pieces[*i].read().unwrap().photo == photo2).collect();
+			let guards1: Vec<_> = list1.iter().map(|i| pieces[*i].read().unwrap()).collect();
+			let guards2: Vec<_> = list2.iter().map(|i| pieces[*i].read().unwrap()).collect();
+			let f1 = guards1.iter().map(|p| get(p)).collect();
+			let f2 = guards2.iter().map(|p| get(p)).collect();
+			for (i,j) in pair_fingerprints(&f1,&f2,|a,b| a.distance(b),common::FINGERPRINT_DUPLICATE_DIST) {
+				println!("Piece {} of photo {} is the same than piece {} of photo {}",list2[j],photo2,list1[i],photo1);
+				removed[list2[j]] = true;
+			}
+		}
+	}
+
+	//new ids
+	let mut new_id = vec![None;pieces.len()];
+	let mut cnt = 0;
+	for i in 0..pieces.len() {
+		if !removed[i] {
+			new_id[i] = Some(cnt);
+			cnt += 1;
+		}
+	}
+
+	//rebuild
+	let old = pieces.split_off(0);
+	for (i,pp) in old.into_iter().enumerate() {
+		if let Some(id) = new_id[i] {
+			{
+				let mut p = pp.write().unwrap();
+				p.id = id as u32;
+				let links = p.cluster_links.split_off(0);
+				p.cluster_links = links.into_iter().filter_map(|(other,delta)| new_id[other].map(|o| (o,delta))).collect();
+			}
+			pieces.push(pp);
+		}
+	}
+
+	removed.iter().filter(|r| **r).count()
+}
+
+impl FingerprintDb {
+	/// Load the database, empty if the file does not exist yet.
+	pub fn load(fname: &str) -> Self {
+		let mut entries = vec!();
+		if let Ok(file) = File::open(fname) {
+			for line in BufReader::new(file).lines() {
+				let line = line.unwrap();
+				match Fingerprint::from_line(&line) {
+					Some(entry) => entries.push(entry),
+					None => println!("WARNING : invalid fingerprint line in {} : {}",fname,line),
+				}
+			}
+		}
+		Self {
+			entries: entries,
+		}
+	}
+
+	/// Give to each piece the id of its known fingerprint, or a new id, and remember the new ones.
+	pub fn assign_ids(self: &mut Self,pieces: &PieceVec) {
+		//pair with the known pieces
+		let mut assigned = vec![None;pieces.len()];
+		{
+			let guards: Vec<_> = pieces.iter().map(|p| p.read().unwrap()).collect();
+			let current = guards.iter().map(|p| get(p)).collect();
+			let known = self.entries.iter().map(|e| &e.1).collect();
+			for (i,j) in pair_fingerprints(&current,&known,|a,b| a.distance(b),common::FINGERPRINT_KNOWN_DIST) {
+				assigned[i] = Some(self.entries[j].0);
+			}
+		}
+
+		//apply or create
+		let mut next = self.entries.iter().map(|e| e.0 + 1).max().unwrap_or(0);
+		for (pp,id) in pieces.iter().zip(assigned.into_iter()) {
+			let mut p = pp.write().unwrap();
+			p.stable_id = match id {
+				Some(id) => id,
+				None => {
+					self.entries.push((next,get(&p).clone()));
+					next += 1;
+					next - 1
+				}
+			};
+			println!("Piece {} => stable id {}",p.id,p.stable_id);
+		}
+	}
+
+	/// Save the database.
+	pub fn save(self: &Self,fname: &str) {
+		let mut file = File::create(fname).unwrap();
+		for &(id,ref fingerprint) in self.entries.iter() {
+			file.write_all(fingerprint.to_line(id).as_bytes()).unwrap();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,RgbaImage,Luma,Rgba,imageops};
+	use fingerprint::*;
+	use common;
+
+	/// Build an L shape.
+	fn build_shape(with_tab: bool) -> (RgbaImage,GrayImage) {
+		let mut mask = GrayImage::new(80,80);
+		let mut img = RgbaImage::new(80,80);
+		for y in 10..70 {
+			for x in 10..70 {
+				if x < 40 || y > 40 || (with_tab && y > 30) {
+					mask.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+					img.put_pixel(x,y,Rgba([200,(x * 3) as u8,50,255]));
+				}
+			}
+		}
+		(img,mask)
+	}
+
+	#[test]
+	fn rotation_invariant() {
+		let (img,mask) = build_shape(false);
+		let f1 = Fingerprint::compute(&img,&mask);
+		let f2 = Fingerprint::compute(&imageops::rotate90(&img),&imageops::rotate90(&mask));
+		let (img3,mask3) = build_shape(true);
+		let f3 = Fingerprint::compute(&img3,&mask3);
+		assert!(f1.distance(&f2) < common::FINGERPRINT_DUPLICATE_DIST);
+		assert!(f1.distance(&f3) > f1.distance(&f2));
+
+		let line = f1.to_line(12);
+		assert_eq!(Fingerprint::from_line(&line),Some((12,f1)));
+	}
+
+	#[test]
+	fn pair_ignore_nan() {
+		let (img,mask) = build_shape(false);
+		let f1 = Fingerprint::compute(&img,&mask);
+		let f2 = Fingerprint::compute(&imageops::rotate90(&img),&imageops::rotate90(&mask));
+		let empty = Fingerprint{shape: vec![f32::NAN;f1.shape.len()],color: vec![f32::NAN;f1.color.len()]};
+		let pairs = pair_fingerprints(&vec!(&empty,&f1),&vec!(&f2,&empty),|a,b| a.distance(b),f32::MAX);
+		assert_eq!(pairs,vec!((1,0)));
+	}
+}
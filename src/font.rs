@@ -0,0 +1,158 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// Very small bitmap font to write labels (ids, coordinates) on the output pictures without
+/// depending on a font file.
+
+//load external
+extern crate image;
+
+//extern
+use image::{RgbaImage,Rgba};
+
+//consts
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// Return the 5 lines of 3 bits of a char (the unknown chars are drawn as a box).
+fn get_glyph(c: char) -> [u8;5] {
+	match c {
+		'0' => [0b111,0b101,0b101,0b101,0b111],
+		'1' => [0b010,0b110,0b010,0b010,0b111],
+		'2' => [0b111,0b001,0b111,0b100,0b111],
+		'3' => [0b111,0b001,0b111,0b001,0b111],
+		'4' => [0b101,0b101,0b111,0b001,0b001],
+		'5' => [0b111,0b100,0b111,0b001,0b111],
+		'6' => [0b111,0b100,0b111,0b101,0b111],
+		'7' => [0b111,0b001,0b010,0b010,0b010],
+		'8' => [0b111,0b101,0b111,0b101,0b111],
+		'9' => [0b111,0b101,0b111,0b001,0b111],
+		'(' => [0b001,0b010,0b010,0b010,0b001],
+		')' => [0b100,0b010,0b010,0b010,0b100],
+		',' => [0b000,0b000,0b000,0b010,0b100],
+		'.' => [0b000,0b000,0b000,0b000,0b010],
+		':' => [0b000,0b010,0b000,0b010,0b000],
+		'-' => [0b000,0b000,0b111,0b000,0b000],
+		'#' => [0b101,0b111,0b101,0b111,0b101],
+		'p' => [0b111,0b101,0b111,0b100,0b100],
+		'r' => [0b000,0b110,0b101,0b100,0b100],
+		' ' => [0b000,0b000,0b000,0b000,0b000],
+		_ => [0b111,0b101,0b101,0b101,0b111],
+	}
+}
+
+/// Size in pixels of the given text with the given scale.
+pub fn text_size(text: &str,scale: u32) -> (u32,u32) {
+	let nb = text.chars().count() as u32;
+	if nb == 0 {
+		(0,0)
+	} else {
+		((nb * (GLYPH_WIDTH + 1) - 1) * scale,GLYPH_HEIGHT * scale)
+	}
+}
+
+/// Draw the text at the given position (top left), pixels out of the image are ignored.
+pub fn draw_text(img: &mut RgbaImage,text: &str,pos: (i32,i32),scale: u32,color: Rgba<u8>) {
+	let (w,h) = img.dimensions();
+	let scale = scale as i32;
+	for (i,c) in text.chars().enumerate() {
+		let glyph = get_glyph(c);
+		let x0 = pos.0 + i as i32 * (GLYPH_WIDTH as i32 + 1) * scale;
+		for (gy,line) in glyph.iter().enumerate() {
+			for gx in 0..GLYPH_WIDTH {
+				if line & (1 << (GLYPH_WIDTH - 1 - gx)) != 0 {
+					for dy in 0..scale {
+						for dx in 0..scale {
+							let x = x0 + gx as i32 * scale + dx;
+							let y = pos.1 + gy as i32 * scale + dy;
+							if x >= 0 && y >= 0 && (x as u32) < w && (y as u32) < h {
+								img.put_pixel(x as u32,y as u32,color);
+							}
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Draw the text centered on the given point over a filled box to keep it readable.
+pub fn draw_label(img: &mut RgbaImage,text: &str,center: (i32,i32),scale: u32,color: Rgba<u8>,back: Rgba<u8>) {
+	let (w,h) = img.dimensions();
+	let (tw,th) = text_size(text,scale);
+	let margin = scale as i32;
+	let (x0,y0) = (center.0 - tw as i32 / 2,center.1 - th as i32 / 2);
+
+	//box
+	for y in (y0 - margin)..(y0 + th as i32 + margin) {
+		for x in (x0 - margin)..(x0 + tw as i32 + margin) {
+			if x >= 0 && y >= 0 && (x as u32) < w && (y as u32) < h {
+				img.put_pixel(x as u32,y as u32,back);
+			}
+		}
+	}
+
+	//text
+	draw_text(img,text,(x0,y0),scale,color);
+}
+
+///Implement unit tests of the module
+#[cfg(test)]
+mod test {
+	use image::{Rgba,RgbaImage};
+	use font::*;
+
+	#[test]
+	fn text_size_scaled() {
+		assert_eq!(text_size("",3),(0,0));
+		assert_eq!(text_size("1",1),(3,5));
+		assert_eq!(text_size("#12",2),(22,10));
+	}
+
+	#[test]
+	fn draw_text_glyph() {
+		let black = Rgba([0,0,0,255]);
+		let red = Rgba([255,0,0,255]);
+		let mut img = RgbaImage::from_pixel(5,6,black);
+		draw_text(&mut img,"1",(0,0),1,red);
+		assert_eq!(*img.get_pixel(1,0),red);
+		assert_eq!(*img.get_pixel(0,0),black);
+		assert_eq!(*img.get_pixel(0,1),red);
+		assert_eq!(*img.get_pixel(0,4),red);
+		assert_eq!(*img.get_pixel(2,4),red);
+		assert_eq!(*img.get_pixel(3,4),black);
+		assert_eq!(*img.get_pixel(1,5),black);
+	}
+
+	#[test]
+	fn draw_label_centered() {
+		let black = Rgba([0,0,0,255]);
+		let white = Rgba([255,255,255,255]);
+		let red = Rgba([255,0,0,255]);
+		let mut img = RgbaImage::from_pixel(20,20,black);
+		draw_label(&mut img,"1",(10,10),2,red,white);
+
+		//box of 6x10 pixels plus a margin of 2
+		assert_eq!(*img.get_pixel(5,3),white);
+		assert_eq!(*img.get_pixel(14,16),white);
+		assert_eq!(*img.get_pixel(4,3),black);
+		assert_eq!(*img.get_pixel(15,17),black);
+
+		//top of the glyph
+		assert_eq!(*img.get_pixel(7,5),white);
+		assert_eq!(*img.get_pixel(9,5),red);
+		assert_eq!(*img.get_pixel(10,6),red);
+
+		//clipped on the border
+		let mut img = RgbaImage::from_pixel(20,20,black);
+		draw_label(&mut img,"#12",(0,0),2,red,white);
+		assert_eq!(*img.get_pixel(0,0),red);
+		assert_eq!(*img.get_pixel(12,6),white);
+		assert_eq!(*img.get_pixel(13,7),black);
+	}
+}
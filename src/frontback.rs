@@ -0,0 +1,206 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// The shape of the pieces is easier to read on their plain cardboard back while the colors
+/// are on the front. With a photo of each side of the same pieces we pair each back piece with
+/// its front piece by comparing the fingerprint of the mirrored back shape, then the mirrored
+/// back mask is turned to fit the front mask and replaces it.
+
+//load external
+extern crate image;
+
+//extern
+use image::{GrayImage,Luma,imageops};
+
+//internal
+use piece::PieceVec;
+use fingerprint::{Fingerprint,pair_fingerprints};
+use common;
+
+/// Center and number of the pixels of the piece in the mask.
+fn calc_centroid(mask: &GrayImage) -> (f32,f32,u32) {
+	let mut sum = (0.0,0.0);
+	let mut cnt = 0;
+	for (x,y,p) in mask.enumerate_pixels() {
+		if p.data[0] == common::MASK_PIECE_PIXEL {
+			sum = (sum.0 + x as f32,sum.1 + y as f32);
+			cnt += 1;
+		}
+	}
+	let div = cnt.max(1) as f32;
+	(sum.0 / div,sum.1 / div,cnt)
+}
+
+/// Turn the back mask (already mirrored) around its center to best fit the front mask.
+/// Return the back mask in the frame of the front one and the overlap ratio (intersection
+/// over union).
+pub fn align_mask(front: &GrayImage,back: &GrayImage) -> (GrayImage,f32) {
+	//centers
+	let (fx,fy,fcnt) = calc_centroid(front);
+	let (bx,by,bcnt) = calc_centroid(back);
+	let (fw,fh) = front.dimensions();
+	let is_front = |x: f32,y: f32| {
+		let (x,y) = (x.round(),y.round());
+		x >= 0.0 && y >= 0.0 && x < fw as f32 && y < fh as f32 && front.get_pixel(x as u32,y as u32).data[0] == common::MASK_PIECE_PIXEL
+	};
+
+	//search the best angle
+	let points: Vec<(f32,f32)> = back.enumerate_pixels().filter(|&(_,_,p)| p.data[0] == common::MASK_PIECE_PIXEL).map(|(x,y,_)| (x as f32 - bx,y as f32 - by)).collect();
+	let mut best = (0.0,0.0);
+	let mut angle = 0.0;
+	while angle < 360.0 {
+		let (s,c) = (angle as f32).to_radians().sin_cos();
+		let overlap = points.iter().filter(|&&(x,y)| is_front(fx + x * c - y * s,fy + x * s + y * c)).count() as f32;
+		let score = overlap / ((fcnt + bcnt) as f32 - overlap).max(1.0);
+		if score > best.0 {
+			best = (score,angle);
+		}
+		angle += common::FRONTBACK_ANGLE_STEP;
+	}
+
+	//build the mask with the inverse mapping
+	let (s,c) = best.1.to_radians().sin_cos();
+	let (bw,bh) = back.dimensions();
+	let ret = GrayImage::from_fn(fw,fh,|x,y| {
+		let (dx,dy) = (x as f32 - fx,y as f32 - fy);
+		let (sx,sy) = ((bx + dx * c + dy * s).round(),(by - dx * s + dy * c).round());
+		if sx >= 0.0 && sy >= 0.0 && sx < bw as f32 && sy < bh as f32 && back.get_pixel(sx as u32,sy as u32).data[0] == common::MASK_PIECE_PIXEL {
+			Luma([common::MASK_PIECE_PIXEL])
+		} else {
+			Luma([common::MASK_BACKGROUND])
+		}
+	});
+
+	(ret,best.0)
+}
+
+/// Pair the pieces of the back photo with the front ones and use their shape. Return the
+/// number of pieces paired.
+pub fn combine_front_back(front: &PieceVec,back: &PieceVec) -> usize {
+	//fingerprints of the mirrored backs, the colors cannot be compared
+	let mirrored: Vec<GrayImage> = back.iter().map(|p| imageops::flip_horizontal(&p.read().unwrap().mask)).collect();
+	let back_fingerprints: Vec<Fingerprint> = back.iter().zip(mirrored.iter()).map(|(p,m)| {
+		Fingerprint::compute(&imageops::flip_horizontal(&p.read().unwrap().image),m)
+	}).collect();
+	let front_fingerprints: Vec<Fingerprint> = front.iter().map(|p| {
+		let p = p.read().unwrap();
+		Fingerprint::compute(&p.image,&p.mask)
+	}).collect();
+
+	//pair
+	let pairs = pair_fingerprints(&front_fingerprints.iter().collect(),&back_fingerprints.iter().collect(),|a,b| a.shape_distance(b),common::FRONTBACK_MAX_DIST);
+	let mut cnt = 0;
+	for (i,j) in pairs {
+		let mut p = front[i].write().unwrap();
+		let (shape,score) = align_mask(&p.mask,&mirrored[j]);
+		if score >= common::FRONTBACK_MIN_OVERLAP {
+			println!("Piece {} takes the shape of back piece {} (overlap {:.2})",i,j,score);
+			p.set_shape(shape);
+			cnt += 1;
+		} else {
+			println!("WARNING : back piece {} does not fit piece {} (overlap {:.2})",j,i,score);
+		}
+	}
+
+	//report
+	if cnt < front.len() {
+		println!("WARNING : only {} of the {} pieces were found on the back photo",cnt,front.len());
+	}
+	cnt
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,RgbaImage,Rgba,Luma,imageops};
+	use std::sync::{Arc,RwLock};
+	use frontback::*;
+	use piece::Piece;
+	use common;
+
+	fn calc_area(mask: &GrayImage) -> u32 {
+		mask.pixels().filter(|p| p.data[0] == common::MASK_PIECE_PIXEL).count() as u32
+	}
+
+	fn paint(photo: &mut RgbaImage,mask: &GrayImage,ox: u32) {
+		for (x,y,p) in mask.enumerate_pixels() {
+			if p.data[0] == common::MASK_PIECE_PIXEL {
+				photo.put_pixel(ox + x,y,Rgba([200,50,50,255]));
+			}
+		}
+	}
+
+	#[test]
+	fn align_mirrored_shape() {
+		//L shape with a tab
+		let mut front = GrayImage::new(80,80);
+		for y in 10..70 {
+			for x in 10..70 {
+				if x < 35 || y > 45 || (y > 20 && y < 30 && x < 60) {
+					front.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+				}
+			}
+		}
+
+		//back is mirrored and turned
+		let back = imageops::rotate90(&imageops::flip_horizontal(&front));
+		let (aligned,score) = align_mask(&front,&imageops::flip_horizontal(&back));
+		assert!(score > 0.95);
+		assert_eq!(*aligned.get_pixel(50,25),Luma([common::MASK_PIECE_PIXEL]));
+		assert_eq!(*aligned.get_pixel(50,35),Luma([common::MASK_BACKGROUND]));
+	}
+
+	#[test]
+	fn pair_front_back() {
+		//L shape with a tab and a square with a bump
+		let a = GrayImage::from_fn(80,80,|x,y| {
+			let inside = x >= 10 && x < 70 && y >= 10 && y < 70 && (x < 35 || y > 45 || (y > 20 && y < 30 && x < 60));
+			Luma([if inside {common::MASK_PIECE_PIXEL} else {common::MASK_BACKGROUND}])
+		});
+		let b = GrayImage::from_fn(80,80,|x,y| {
+			let (dx,dy) = (x as i32 - 60,y as i32 - 40);
+			let inside = (x >= 20 && x < 55 && y >= 20 && y < 60) || dx * dx + dy * dy < 100;
+			Luma([if inside {common::MASK_PIECE_PIXEL} else {common::MASK_BACKGROUND}])
+		});
+
+		//front loses a hole in the L where its colors are close to the background
+		let white = Rgba([255,255,255,255]);
+		let mut front_photo = RgbaImage::from_pixel(180,80,white);
+		paint(&mut front_photo,&a,0);
+		paint(&mut front_photo,&b,100);
+		for y in 50..54 {
+			for x in 40..44 {
+				front_photo.put_pixel(x,y,white);
+			}
+		}
+
+		//back is mirrored, turned and in the other order
+		let mut back_photo = RgbaImage::from_pixel(180,80,white);
+		paint(&mut back_photo,&imageops::rotate90(&imageops::flip_horizontal(&b)),0);
+		paint(&mut back_photo,&imageops::rotate90(&imageops::flip_horizontal(&a)),100);
+
+		//pair
+		let load = |photo: &RgbaImage| -> PieceVec {
+			vec!(
+				Arc::new(RwLock::new(Piece::new(photo,&white,(0,0,80,80),0))),
+				Arc::new(RwLock::new(Piece::new(photo,&white,(100,0,80,80),1))),
+			)
+		};
+		let front = load(&front_photo);
+		let back = load(&back_photo);
+		assert_eq!(calc_area(&front[0].read().unwrap().mask),calc_area(&a) - 16);
+		assert_eq!(combine_front_back(&front,&back),2);
+
+		//each front piece takes its own shape, without the hole
+		for (pp,shape) in front.iter().zip([a,b].iter()) {
+			let p = pp.read().unwrap();
+			assert!(p.shape.is_some());
+			let (area,expected) = (calc_area(&p.mask) as f32,calc_area(shape) as f32);
+			assert!((area - expected).abs() < 0.02 * expected,"area {} expected {}",area,expected);
+		}
+	}
+}
@@ -0,0 +1,435 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// Generate synthetic puzzles to test the whole pipeline. We build a random NxM jigsaw with
+/// parametric tabs, render each piece turned and moved on a background with optional noise,
+/// blur and lighting gradient, then write the ground truth layout.
+
+//load external
+extern crate image;
+
+//std
+use std::f32;
+use std::fs::File;
+use std::io::Write;
+
+//extern
+use image::{RgbaImage,Rgba};
+
+/// Parameters of the generated puzzle.
+#[derive(Debug,Clone)]
+pub struct GeneratorConfig {
+	pub cols: u32,
+	pub rows: u32,
+	pub piece_size: u32,
+	pub seed: u64,
+	pub flat_borders: bool,
+	pub rotate: bool,
+	pub background: Rgba<u8>,
+	pub noise: u8,
+	pub blur: u32,
+	pub light: f32,
+}
+
+/// Ground truth for one piece.
+#[derive(Debug,Clone)]
+pub struct GeneratedPiece {
+	pub detect_id: usize,
+	pub col: u32,
+	pub row: u32,
+	pub center: (f32,f32),
+	pub angle: f32,
+}
+
+/// One tab on an edge, sign give the direction (0 for flat edges).
+#[derive(Debug,Clone,Copy)]
+struct Tab {
+	sign: i32,
+	pos: f32,
+	neck: f32,
+	radius: f32,
+	depth: f32,
+}
+
+/// Small xorshift random generator to get reproducible puzzles from a seed.
+struct Random {
+	state: u64,
+}
+
+struct Puzzle {
+	cols: u32,
+	rows: u32,
+	size: f32,
+	hedges: Vec<Tab>,
+	vedges: Vec<Tab>,
+}
+
+impl Default for GeneratorConfig {
+	fn default() -> Self {
+		Self {
+			cols: 4,
+			rows: 3,
+			piece_size: 120,
+			seed: 42,
+			flat_borders: true,
+			rotate: true,
+			background: Rgba([255,255,255,255]),
+			noise: 0,
+			blur: 0,
+			light: 0.0,
+		}
+	}
+}
+
+impl Random {
+	fn new(seed: u64) -> Self {
+		Self {
+			state: seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407) | 1,
+		}
+	}
+
+	fn next(self: &mut Self) -> u64 {
+		self.state ^= self.state >> 12;
+		self.state ^= self.state << 25;
+		self.state ^= self.state >> 27;
+		self.state.wrapping_mul(2685821657736338717)
+	}
+
+	/// Uniform float in [min,max[
+	fn range(self: &mut Self,min: f32,max: f32) -> f32 {
+		let v = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+		min + v * (max - min)
+	}
+
+	fn sign(self: &mut Self) -> i32 {
+		if self.next() & 1 == 0 {1} else {-1}
+	}
+}
+
+impl Tab {
+	fn flat() -> Self {
+		Self {sign: 0, pos: 0.0, neck: 0.0, radius: 0.0, depth: 0.0}
+	}
+
+	fn random(rand: &mut Random,size: f32,sign: i32) -> Self {
+		Self {
+			sign: sign,
+			pos: size * rand.range(0.42,0.58),
+			neck: size * rand.range(0.11,0.14),
+			radius: size * rand.range(0.09,0.11),
+			depth: size * rand.range(0.22,0.26),
+		}
+	}
+
+	/// Check if a point is in the tab, along is the position on the edge and dist the
+	/// distance to the edge in the direction of the tab.
+	fn contains(self: &Self,along: f32,dist: f32) -> bool {
+		if self.sign == 0 || dist < 0.0 {
+			return false;
+		}
+		let center = self.depth - self.radius;
+		let dx = along - self.pos;
+		(dx.abs() < self.neck / 2.0 && dist < center) || dx * dx + (dist - center) * (dist - center) < self.radius * self.radius
+	}
+}
+
+impl Puzzle {
+	fn new(config: &GeneratorConfig,rand: &mut Random) -> Self {
+		//vars
+		let size = config.piece_size as f32;
+		let (cols,rows) = (config.cols,config.rows);
+		let mut hedges = vec!();
+		let mut vedges = vec!();
+
+		//horizontal edges, indexed by r * cols + c
+		for r in 0..(rows+1) {
+			for _ in 0..cols {
+				let sign = if r == 0 {-1} else if r == rows {1} else {rand.sign()};
+				if config.flat_borders && (r == 0 || r == rows) {
+					hedges.push(Tab::flat());
+				} else {
+					hedges.push(Tab::random(rand,size,sign));
+				}
+			}
+		}
+
+		//vertical edges, indexed by r * (cols+1) + c
+		for _ in 0..rows {
+			for c in 0..(cols+1) {
+				let sign = if c == 0 {-1} else if c == cols {1} else {rand.sign()};
+				if config.flat_borders && (c == 0 || c == cols) {
+					vedges.push(Tab::flat());
+				} else {
+					vedges.push(Tab::random(rand,size,sign));
+				}
+			}
+		}
+
+		Self {
+			cols: cols,
+			rows: rows,
+			size: size,
+			hedges: hedges,
+			vedges: vedges,
+		}
+	}
+
+	fn hedge(self: &Self,c: i32,r: i32) -> Tab {
+		if c < 0 || r < 0 || c >= self.cols as i32 || r > self.rows as i32 {
+			Tab::flat()
+		} else {
+			self.hedges[(r as u32 * self.cols + c as u32) as usize]
+		}
+	}
+
+	fn vedge(self: &Self,c: i32,r: i32) -> Tab {
+		if c < 0 || r < 0 || c > self.cols as i32 || r >= self.rows as i32 {
+			Tab::flat()
+		} else {
+			self.vedges[(r as u32 * (self.cols + 1) + c as u32) as usize]
+		}
+	}
+
+	/// Return the piece owning the given point of the puzzle.
+	fn owner(self: &Self,x: f32,y: f32) -> Option<(i32,i32)> {
+		//base cell
+		let s = self.size;
+		let (c,r) = ((x / s).floor() as i32,(y / s).floor() as i32);
+		let (x0,y0) = (c as f32 * s,r as f32 * s);
+
+		//tabs coming in the cell from the neighboors
+		let top = self.hedge(c,r);
+		let bottom = self.hedge(c,r+1);
+		let left = self.vedge(c,r);
+		let right = self.vedge(c+1,r);
+		let ret = if top.sign > 0 && top.contains(x - x0,y - y0) {
+			(c,r-1)
+		} else if bottom.sign < 0 && bottom.contains(x - x0,y0 + s - y) {
+			(c,r+1)
+		} else if left.sign > 0 && left.contains(y - y0,x - x0) {
+			(c-1,r)
+		} else if right.sign < 0 && right.contains(y - y0,x0 + s - x) {
+			(c+1,r)
+		} else {
+			(c,r)
+		};
+
+		//check in puzzle
+		if ret.0 >= 0 && ret.1 >= 0 && ret.0 < self.cols as i32 && ret.1 < self.rows as i32 {
+			Some(ret)
+		} else {
+			None
+		}
+	}
+
+	/// Color of the picture printed on the puzzle.
+	fn picture(self: &Self,x: f32,y: f32) -> Rgba<u8> {
+		let r = 128.0 + 90.0 * (x / 40.0).sin();
+		let g = 128.0 + 90.0 * (y / 53.0 + x / 97.0).sin();
+		let b = 128.0 + 90.0 * ((x + y) / 71.0).cos();
+		Rgba([r as u8,g as u8,b as u8,255])
+	}
+}
+
+/// Apply a linear lighting gradient from left to right.
+fn apply_light(img: &mut RgbaImage,light: f32) {
+	let (w,_) = img.dimensions();
+	for (x,_,p) in img.enumerate_pixels_mut() {
+		let factor = 1.0 - light * x as f32 / w as f32;
+		for c in 0..3 {
+			p.data[c] = (p.data[c] as f32 * factor).min(255.0) as u8;
+		}
+	}
+}
+
+/// Add uniform noise on all the pixels.
+fn apply_noise(img: &mut RgbaImage,noise: u8,rand: &mut Random) {
+	let noise = noise as f32;
+	for p in img.pixels_mut() {
+		for c in 0..3 {
+			let v = p.data[c] as f32 + rand.range(-noise,noise);
+			p.data[c] = v.max(0.0).min(255.0) as u8;
+		}
+	}
+}
+
+/// Box blur on one direction.
+fn apply_blur_pass(img: &RgbaImage,radius: u32,horizontal: bool) -> RgbaImage {
+	let (w,h) = img.dimensions();
+	let r = radius as i32;
+	let mut out = img.clone();
+
+	for y in 0..h as i32 {
+		for x in 0..w as i32 {
+			let mut sum = [0u32;4];
+			let mut cnt = 0;
+			for d in -r..(r+1) {
+				let (sx,sy) = if horizontal {(x + d,y)} else {(x,y + d)};
+				if sx >= 0 && sy >= 0 && sx < w as i32 && sy < h as i32 {
+					let p = img.get_pixel(sx as u32,sy as u32);
+					for c in 0..4 {
+						sum[c] += p.data[c] as u32;
+					}
+					cnt += 1;
+				}
+			}
+			out.put_pixel(x as u32,y as u32,Rgba([(sum[0]/cnt) as u8,(sum[1]/cnt) as u8,(sum[2]/cnt) as u8,(sum[3]/cnt) as u8]));
+		}
+	}
+
+	out
+}
+
+/// Box blur in both directions.
+fn apply_blur(img: &RgbaImage,radius: u32) -> RgbaImage {
+	let tmp = apply_blur_pass(img,radius,true);
+	apply_blur_pass(&tmp,radius,false)
+}
+
+/// Generate the puzzle picture and the ground truth. The pieces are listed in the order
+/// step1_detect will find them (top most first pixel first).
+pub fn generate(config: &GeneratorConfig) -> (RgbaImage,Vec<GeneratedPiece>) {
+	//build puzzle
+	let mut rand = Random::new(config.seed);
+	let puzzle = Puzzle::new(config,&mut rand);
+	let s = puzzle.size;
+	let depth = s * 0.27;
+	let extent = (s / 2.0 + depth) * 2.0f32.sqrt();
+	let slot = (2.0 * extent + s * 0.3).ceil() as u32;
+	let nb = (config.cols * config.rows) as usize;
+
+	//shuffle the slots
+	let slots_x = (nb as f32).sqrt().ceil() as u32;
+	let slots_y = (nb as u32 + slots_x - 1) / slots_x;
+	let mut order: Vec<usize> = (0..nb).collect();
+	for i in (1..nb).rev() {
+		let j = (rand.next() % (i as u64 + 1)) as usize;
+		order.swap(i,j);
+	}
+
+	//image
+	let (w,h) = (slots_x * slot + slot / 4,slots_y * slot + slot / 4);
+	let mut img = RgbaImage::from_pixel(w,h,config.background);
+	let mut pieces = vec!();
+	let mut first_pixels = vec!();
+
+	//render all pieces
+	for r in 0..config.rows {
+		for c in 0..config.cols {
+			//placement
+			let id = (r * config.cols + c) as usize;
+			let slot_id = order[id] as u32;
+			let jitter = slot as f32 - 2.0 * extent;
+			let cx = (slot_id % slots_x) as f32 * slot as f32 + slot as f32 / 4.0 + extent + rand.range(0.0,jitter);
+			let cy = (slot_id / slots_x) as f32 * slot as f32 + slot as f32 / 4.0 + extent + rand.range(0.0,jitter);
+			let angle = if config.rotate {rand.range(0.0,2.0 * f32::consts::PI)} else {0.0};
+			let (cos,sin) = (angle.cos(),angle.sin());
+			let gc = ((c as f32 + 0.5) * s,(r as f32 + 0.5) * s);
+
+			//draw by getting back to the puzzle coordinates for each pixel
+			let mut first = (u32::max_value(),u32::max_value());
+			for y in (cy - extent) as u32..(cy + extent).ceil() as u32 {
+				for x in (cx - extent) as u32..(cx + extent).ceil() as u32 {
+					let (dx,dy) = (x as f32 - cx,y as f32 - cy);
+					let (px,py) = (gc.0 + dx * cos + dy * sin,gc.1 - dx * sin + dy * cos);
+					if puzzle.owner(px,py) == Some((c as i32,r as i32)) {
+						img.put_pixel(x,y,puzzle.picture(px,py));
+						if (y,x) < first {
+							first = (y,x);
+						}
+					}
+				}
+			}
+
+			//keep
+			first_pixels.push(first);
+			pieces.push(GeneratedPiece {
+				detect_id: 0,
+				col: c,
+				row: r,
+				center: (cx,cy),
+				angle: angle.to_degrees(),
+			});
+		}
+	}
+
+	//ids as found by the detection
+	let mut sorted: Vec<usize> = (0..nb).collect();
+	sorted.sort_by_key(|i| first_pixels[*i]);
+	for (detect_id,i) in sorted.iter().enumerate() {
+		pieces[*i].detect_id = detect_id;
+	}
+
+	//effects
+	if config.light > 0.0 {
+		apply_light(&mut img,config.light);
+	}
+	if config.noise > 0 {
+		apply_noise(&mut img,config.noise,&mut rand);
+	}
+	if config.blur > 0 {
+		img = apply_blur(&img,config.blur);
+	}
+
+	(img,pieces)
+}
+
+/// Generate the puzzle and write PREFIX.png and PREFIX.txt with the ground truth.
+pub fn generate_files(config: &GeneratorConfig,prefix: &str) {
+	//gen
+	let (img,pieces) = generate(config);
+
+	//save image
+	let fname = format!("{}.png",prefix);
+	println!("Write {}",fname);
+	img.save(&fname).unwrap();
+
+	//save truth
+	let fname = format!("{}.txt",prefix);
+	println!("Write {}",fname);
+	let mut file = File::create(fname).unwrap();
+	file.write_fmt(format_args!("# {:?}\n",config)).unwrap();
+	file.write_fmt(format_args!("# id col row center_x center_y angle\n")).unwrap();
+	let mut sorted = pieces.clone();
+	sorted.sort_by_key(|p| p.detect_id);
+	for p in sorted.iter() {
+		file.write_fmt(format_args!("{} {} {} {:.1} {:.1} {:.2}\n",p.detect_id,p.col,p.row,p.center.0,p.center.1,p.angle)).unwrap();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use generator::*;
+	use step1_detect;
+
+	#[test]
+	fn generate_detect_all() {
+		let mut config = GeneratorConfig::default();
+		config.cols = 3;
+		config.rows = 2;
+		config.piece_size = 60;
+		let (mut img,pieces) = generate(&config);
+		assert_eq!(pieces.len(),6);
+
+		//run detection
+		let back = config.background;
+		let mut cnt = 0;
+		loop {
+			let first = step1_detect::find_first_non_bg_pixel(&img,&back);
+			if first == (0,0) {
+				break;
+			}
+			let square = step1_detect::find_square_non_bg(&img,&back,first);
+			if square.2 * square.3 > 600 {
+				cnt += 1;
+			}
+			step1_detect::paint_square(&mut img,&back,square);
+		}
+		assert_eq!(cnt,6);
+	}
+}
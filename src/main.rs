@@ -16,27 +16,41 @@ extern crate num_traits;
 
 //modules
 mod piece;
+mod step0_perspective;
+mod calibrate;
+mod shadow;
+mod background;
 mod step1_detect;
+mod step2_split;
 mod step3_rotate;
 mod step4_bump;
 mod step5_corners;
 mod step6_hbpoints;
 mod step7_quality;
 mod step8_fix;
-mod step9_cleanup;
 mod step10_matching;
 mod step11_build;
 mod step12_draw;
 mod common;
 mod scan;
+mod reference;
+mod font;
+mod contour;
+mod generator;
+mod tiled;
+mod pyramid;
+mod scale;
+mod fingerprint;
+mod frontback;
+mod facedown;
+mod bitmask;
 
 //arg parse
-use argparse::{ArgumentParser, Store, List};
+use argparse::{ArgumentParser, Store, StoreTrue, List};
 
 //load std
 use std::fs::File;
 use std::path::Path;
-use std::sync::{Arc,RwLock};
 
 //pool
 use scoped_pool::Pool;
@@ -47,6 +61,44 @@ use image::GenericImage;
 //interal
 //use piece::PieceMatch;
 
+/// Append the pieces of one photo with global ids, keeping the links of the assembled pieces,
+/// and remember the incomplete ones.
+fn append_detection(all: &mut piece::PieceVec,truncated: &mut Vec<(usize,(u32,u32,u32,u32))>,photo_id: usize,detection: step1_detect::Detection) {
+	for t in detection.truncated {
+		truncated.push((photo_id,t));
+	}
+
+	let base = all.len();
+	for p in detection.pieces {
+		{
+			let mut p = p.write().unwrap();
+			p.id += base as u32;
+			for l in p.cluster_links.iter_mut() {
+				l.0 += base;
+			}
+		}
+		all.push(p);
+	}
+}
+
+/// Search the pieces of one photo and separate the touching ones. Return the detection with the
+/// side of the scale marker if found, and the photo with the objects painted for debugging.
+fn detect_photo(photo: &image::RgbaImage,background: &image::Rgba<u8>,photo_id: usize,scale: &scale::ScaleNormalizer) -> (step1_detect::Detection,Option<f32>,image::RgbaImage) {
+	//find objects
+	let mut debug = photo.clone();
+	let mut detection = step1_detect::detect_pieces(&mut debug,background,photo_id as u32);
+
+	//the marker is not a piece
+	let marker = scale.take_marker(&mut detection.pieces);
+
+	//separate touching pieces
+	step2_split::split_touching_pieces(Some(photo),background,&mut detection.pieces,Some(&mut debug));
+
+	//incomplete pieces
+	step1_detect::paint_truncated(&mut debug,&detection.truncated);
+	(detection,marker,debug)
+}
+
 ///Main function to run the program.
 fn main() {
 	//info
@@ -56,90 +108,376 @@ fn main() {
 	let mut dump:i32 = -1;
 	let mut threads = 4;
 	let mut files:Vec<String> = vec!();
+	let mut reference_file = String::new();
+	let mut reference_grid = String::new();
+	let mut render = "placed".to_string();
+	let mut map = false;
+	let mut calibrate_profile = String::new();
+	let mut undistort = String::new();
+	let mut perspective = false;
+	let mut background_mode = "auto".to_string();
+	let mut shadow = false;
+	let mut shadow_ref = String::new();
+	let mut sheet_corners = String::new();
+	let mut tiled = false;
+	let mut tile_size = common::TILED_DEFAULT_ROWS;
+	let mut coarse_size = common::PYRAMID_COARSE_SIZE;
+	let mut scale_mode = "none".to_string();
+	let mut paper_size = "a4".to_string();
+	let mut marker_size = 0.0f32;
+	let mut ppmm = 0.0f32;
+	let mut ids_file = String::new();
+	let mut dedup = false;
+	let mut back_file = String::new();
+	let mut generate = String::new();
+	let mut gen_grid = "4x3".to_string();
+	let mut gen_config = generator::GeneratorConfig::default();
+	let mut gen_tabbed_borders = false;
+	let mut gen_no_rotate = false;
+	let mut gen_background = "255,255,255".to_string();
 	{
 		let mut ap = ArgumentParser::new();
-		ap.set_description("Puzzle solver from picture considering a white background bellow the pieces and enough margins arround the pieces.");
+		ap.set_description("Puzzle solver from picture considering a uniform background bellow the pieces and enough margins arround the pieces.");
         ap.refer(&mut dump)
             .add_option(&["-d", "--dump"], Store,
 				"Dump the picture for given step, use 0 for all");
 		ap.refer(&mut threads)
 			.add_option(&["-t", "--threads"], Store,
 				"Number of threads to use (default: 4)");
+		ap.refer(&mut reference_file)
+			.add_option(&["-r", "--reference"], Store,
+				"Picture of the box used as a placement prior");
+		ap.refer(&mut reference_grid)
+			.add_option(&["--reference-grid"], Store,
+				"Grid of the reference as COLSxROWS (default: estimated from the number of pieces)");
+		ap.refer(&mut render)
+			.add_option(&["--render"], Store,
+				"Rendering of the solution : placed (default), grid or annotated");
+		ap.refer(&mut map)
+			.add_option(&["--map"], StoreTrue,
+				"Draw on the photo where each piece of the solution was taken");
+		ap.refer(&mut calibrate_profile)
+			.add_option(&["--calibrate"], Store,
+				"Estimate the lens distortion from the given checkerboard photos, save it into PROFILE then exit");
+		ap.refer(&mut undistort)
+			.add_option(&["--undistort"], Store,
+				"Remove the lens distortion of the photo with the given PROFILE");
+		ap.refer(&mut perspective)
+			.add_option(&["--perspective"], StoreTrue,
+				"Detect the background sheet and correct the perspective of the photo");
+		ap.refer(&mut sheet_corners)
+			.add_option(&["--sheet-corners"], Store,
+				"Corners of the sheet to correct the perspective as X,Y;X,Y;X,Y;X,Y clockwise from top left");
+		ap.refer(&mut background_mode)
+			.add_option(&["--background"], Store,
+				"Background estimation : auto (default) from the borders of the photo or corner to take the top left pixel");
+		ap.refer(&mut shadow)
+			.add_option(&["--shadow"], StoreTrue,
+				"Remove the shadows casted by the pieces on the background");
+		ap.refer(&mut shadow_ref)
+			.add_option(&["--shadow-ref"], Store,
+				"Photo of the same pieces with another light to guide the shadow removal (enable --shadow)");
+		ap.refer(&mut tiled)
+			.add_option(&["--tiled"], StoreTrue,
+				"Stream the PNG photos by tiles without loading them fully, for very large photos");
+		ap.refer(&mut tile_size)
+			.add_option(&["--tile-size"], Store,
+				"Number of rows of the tiles in tiled mode (default: 512)");
+		ap.refer(&mut coarse_size)
+			.add_option(&["--coarse-size"], Store,
+				"Size of the downscaled pieces used to search the rotation, 0 for full resolution (default: 256)");
+		ap.refer(&mut scale_mode)
+			.add_option(&["--scale"], Store,
+				"Bring the photos to the same scale : none (default), median piece size, paper size or marker");
+		ap.refer(&mut paper_size)
+			.add_option(&["--paper-size"], Store,
+				"Size of the background sheet for --scale paper as a4, a3, a5, letter or WxH in mm (default: a4)");
+		ap.refer(&mut marker_size)
+			.add_option(&["--marker-size"], Store,
+				"Side in mm of the printed square used by --scale marker");
+		ap.refer(&mut ppmm)
+			.add_option(&["--ppmm"], Store,
+				"Pixels per mm wanted for --scale paper and marker (default: scale of the first photo)");
+		ap.refer(&mut ids_file)
+			.add_option(&["--ids"], Store,
+				"File keeping the fingerprints of the pieces to give them the same ids between the runs");
+		ap.refer(&mut dedup)
+			.add_option(&["--dedup"], StoreTrue,
+				"Merge the pieces seen on several photos");
+		ap.refer(&mut back_file)
+			.add_option(&["--back"], Store,
+				"Photo of the back side of the same pieces, their shape is taken from it");
+		ap.refer(&mut generate)
+			.add_option(&["--generate"], Store,
+				"Generate a synthetic puzzle into PREFIX.png with the ground truth in PREFIX.txt then exit");
+		ap.refer(&mut gen_grid)
+			.add_option(&["--gen-grid"], Store,
+				"Grid of the generated puzzle as COLSxROWS (default: 4x3)");
+		ap.refer(&mut gen_config.piece_size)
+			.add_option(&["--gen-size"], Store,
+				"Size of the generated pieces in pixels (default: 120)");
+		ap.refer(&mut gen_config.seed)
+			.add_option(&["--gen-seed"], Store,
+				"Seed of the generated puzzle (default: 42)");
+		ap.refer(&mut gen_tabbed_borders)
+			.add_option(&["--gen-tabbed-borders"], StoreTrue,
+				"Put tabs also on the borders of the generated puzzle");
+		ap.refer(&mut gen_no_rotate)
+			.add_option(&["--gen-no-rotate"], StoreTrue,
+				"Do not turn the generated pieces");
+		ap.refer(&mut gen_background)
+			.add_option(&["--gen-background"], Store,
+				"Background color of the generated picture as R,G,B (default: 255,255,255)");
+		ap.refer(&mut gen_config.noise)
+			.add_option(&["--gen-noise"], Store,
+				"Amplitude of the noise added on the generated picture (default: 0)");
+		ap.refer(&mut gen_config.blur)
+			.add_option(&["--gen-blur"], Store,
+				"Radius of the blur applied on the generated picture (default: 0)");
+		ap.refer(&mut gen_config.light)
+			.add_option(&["--gen-light"], Store,
+				"Strength of the lighting gradient on the generated picture between 0 and 1 (default: 0)");
 		ap.refer(&mut files)
             .add_argument("FILES", List,
-                "List of files to parse (support only 1 now)");
+                "List of photos to parse");
 		ap.parse_args_or_exit();
 	}
 
-	//check
-	if files.len() != 1 {
-		panic!("Invalid number of files, should be exactly one !")
+	//generate mode
+	if !generate.is_empty() {
+		let (cols,rows) = reference::parse_grid(&gen_grid).expect("Invalid generator grid, should be COLSxROWS !");
+		let color: Vec<u8> = gen_background.split(',').map(|v| v.trim().parse().expect("Invalid background color, should be R,G,B !")).collect();
+		assert!(color.len() == 3,"Invalid background color, should be R,G,B !");
+		gen_config.cols = cols as u32;
+		gen_config.rows = rows as u32;
+		gen_config.flat_borders = !gen_tabbed_borders;
+		gen_config.rotate = !gen_no_rotate;
+		gen_config.background = image::Rgba([color[0],color[1],color[2],255]);
+		generator::generate_files(&gen_config,&generate);
+		return;
 	}
-	let file = &files[0];
 
-	//load image
-	println!("Load image {:?}",file);
-	let mut img = image::open(&Path::new(&file)).unwrap();
-
-	//print size
-	println!("dimensions {:?}", img.dimensions());
-
-	//print colors
-	println!("{:?}", img.color());
+	//calibrate mode
+	if !calibrate_profile.is_empty() {
+		let profile = calibrate::calibrate(&files);
+		profile.save(&calibrate_profile);
+		return;
+	}
 
-	//pixel 0,0
-	let background = img.get_pixel(0,0);
-	println!("Pixel 0,0 : {:?}",background);
+	//check
+	if files.is_empty() {
+		panic!("Invalid number of files, should be at least one !")
+	}
+	if !shadow_ref.is_empty() && files.len() != 1 {
+		panic!("Option --shadow-ref is only supported with one photo !")
+	}
+	if !back_file.is_empty() && (files.len() != 1 || tiled) {
+		panic!("Option --back is only supported with one photo and without --tiled !")
+	}
+	if tiled && (!undistort.is_empty() || perspective || !sheet_corners.is_empty() || shadow || !shadow_ref.is_empty()) {
+		panic!("Option --tiled cannot be used with --undistort, --perspective, --sheet-corners or --shadow !")
+	}
 
-	//check if RGB which is not supported by our code
-	{
-		let r = img.as_mut_rgb8();
-		match r {
-			Some(_) => println!("Not ok, image if RGB, not RGBA"),
-			None => {}
-		}
+	//background mode
+	let auto_background = match background_mode.as_ref() {
+		"auto" => true,
+		"corner" => false,
+		_ => panic!("Invalid background mode {}, should be auto or corner !",background_mode),
+	};
+
+	//scale mode
+	let mut scale = scale::ScaleNormalizer::new(match scale_mode.as_ref() {
+		"none" => scale::ScaleMode::Keep,
+		"median" => scale::ScaleMode::Median,
+		"paper" => scale::ScaleMode::Paper(scale::parse_paper_size(&paper_size).expect("Invalid paper size, should be a4, a3, a5, letter or WxH !")),
+		"marker" if marker_size > 0.0 => scale::ScaleMode::Marker(marker_size),
+		"marker" => panic!("Option --scale marker needs --marker-size !"),
+		_ => panic!("Invalid scale mode {}, should be none, median, paper or marker !",scale_mode),
+	},ppmm);
+	if tiled && scale.mode != scale::ScaleMode::Keep {
+		panic!("Option --tiled cannot be used with --scale !")
 	}
 
 	//list
 	let mut all: piece::PieceVec = Vec::new();
+	let mut photos: Vec<Option<image::RgbaImage>> = vec!();
+	let mut backgrounds: Vec<image::Rgba<u8>> = vec!();
+	let mut truncated: Vec<(usize,(u32,u32,u32,u32))> = vec!();
+
+	//load a photo and prepare it for the detection, return the photo, the background model and
+	//the size of the sheet if the perspective was corrected
+	let load_photo = |file: &str,guide_file: &str,suffix: &str| {
+		let dump_name = |step: &str| format!("{}{}.png",step,suffix);
+
+		//load image
+		println!("Load image {:?}",file);
+		let mut img = image::open(&Path::new(&file)).unwrap();
+
+		//load the photo with another light to find the shadows
+		let mut guide = if guide_file.is_empty() {
+			None
+		} else {
+			println!("Load shadow reference {:?}",guide_file);
+			Some(image::open(&Path::new(guide_file)).unwrap().to_rgba())
+		};
+
+		//remove lens distortion
+		if !undistort.is_empty() {
+			let profile = calibrate::DistortionProfile::load(&undistort);
+			img = image::DynamicImage::ImageRgba8(calibrate::undistort_image(&img.to_rgba(),&profile));
+			guide = guide.map(|g| calibrate::undistort_image(&g,&profile));
+		}
 
-	//find first black pixel
-	{
-		let rgba8 = img.as_mut_rgba8();
-		match rgba8 {
-			Some(rgba) => {
-				let mut first = (1,1);
-				let mut id: u32 = 0;
-				while first != (0,0) {
-					first = step1_detect::find_first_non_bg_pixel(&rgba,&background);
-					println!("First black pixel : {:?}",first);
-
-					//fin square
-					let square = step1_detect::find_square_non_bg(&rgba,&background,first);
-					println!("Square : {:?}",square);
-
-					//extract into list
-					let (_,_,w,h) = square;
-					if w*h > 600 {
-						all.push(Arc::new(RwLock::new(piece::Piece::new(rgba,&background,square,id))));
-						id = id + 1;
-					} else {
-						println!("IGNORE, too small !");
-					}
-					
-					//draw for save
-					step1_detect::paint_square(rgba,&background,square);
+		//correct perspective
+		let mut sheet = None;
+		if perspective || !sheet_corners.is_empty() {
+			let corners = if sheet_corners.is_empty() {
+				None
+			} else {
+				Some(step0_perspective::parse_corners(&sheet_corners).expect("Invalid sheet corners, should be X,Y;X,Y;X,Y;X,Y !"))
+			};
+			if let Some((warped,corners)) = step0_perspective::correct_perspective(&img.to_rgba(),corners) {
+				img = image::DynamicImage::ImageRgba8(warped);
+				let margin = 2 * common::PERSPECTIVE_SHEET_MARGIN;
+				sheet = Some((img.width() + margin,img.height() + margin));
+				guide = guide.map(|g| step0_perspective::warp_sheet(&g,&corners));
+				if dump == 0 {
+					let ref mut fout = File::create(dump_name("step-0-perspective")).unwrap();
+					img.write_to(fout, image::PNG).unwrap();
 				}
-			},
-			None => println!("Invalid format ! Expect RGBA8 !")
+			}
+		}
+
+		//estimate background
+		let model = if auto_background {
+			background::BackgroundModel::estimate(&img.to_rgba())
+		} else {
+			background::BackgroundModel::from_corner(&img.to_rgba())
+		};
+		println!("Background : {:?}",model);
+		if !model.is_separable() {
+			println!("WARNING : background and pieces colors are too close to be well separated ({:.0}% ambiguous pixels)",model.ambiguous * 100.0);
+		}
+		{
+			let mut rgba = img.to_rgba();
+			let cnt = model.normalize(&mut rgba);
+			println!("Background pixels normalized : {}",cnt);
+			img = image::DynamicImage::ImageRgba8(rgba);
+			if dump == 0 {
+				let ref mut fout = File::create(dump_name("step-0-background")).unwrap();
+				img.write_to(fout, image::PNG).unwrap();
+			}
+		}
+
+		//remove shadows
+		if shadow || guide.is_some() {
+			let mut rgba = img.to_rgba();
+			shadow::remove_shadows(&mut rgba,&model.color,guide.as_ref());
+			img = image::DynamicImage::ImageRgba8(rgba);
+			if dump == 0 {
+				let ref mut fout = File::create(dump_name("step-0-shadow")).unwrap();
+				img.write_to(fout, image::PNG).unwrap();
+			}
+		}
+
+		//print size
+		println!("dimensions {:?}", img.dimensions());
+
+		//print colors
+		println!("{:?}", img.color());
+
+		(img.to_rgba(),model,sheet)
+	};
+
+	//loop on all photos
+	for (photo_id,file) in files.iter().enumerate() {
+		//name of the dump files
+		let suffix = if files.len() == 1 {String::new()} else {format!("-photo-{}",photo_id)};
+		let dump_name = |step: &str| format!("{}{}.png",step,suffix);
+
+		//tiled mode, the photo is never fully loaded
+		if tiled {
+			println!("Load image by tiles {:?}",file);
+			let (mut detection,model) = tiled::detect_pieces_tiled(file,auto_background,tile_size,photo_id as u32);
+			println!("Background : {:?}",model);
+			if !model.is_separable() {
+				println!("WARNING : background and pieces colors are too close to be well separated ({:.0}% ambiguous pixels)",model.ambiguous * 100.0);
+			}
+			step2_split::split_touching_pieces(None,&model.color,&mut detection.pieces,None);
+			append_detection(&mut all,&mut truncated,photo_id,detection);
+			photos.push(None);
+			backgrounds.push(model.color);
+			continue;
 		}
+
+		//load and prepare
+		let (photo,model,sheet) = load_photo(file,&shadow_ref,&suffix);
+
+		//background
+		let background = model.color;
+		println!("Background color : {:?}",background);
+
+		//find pieces
+		let (detection,marker,debug) = detect_photo(&photo,&background,photo_id,&scale);
+
+		//bring the pieces to the common scale
+		scale.normalize_pieces(&detection.pieces,Some(&photo),sheet,marker,&model);
+		append_detection(&mut all,&mut truncated,photo_id,detection);
+		let img = image::DynamicImage::ImageRgba8(debug);
+
+		//create output image
+		if dump == 0 || dump == 1 {
+			let ref mut fout = File::create(dump_name("step-1-detect")).unwrap();
+			//write to file
+			img.write_to(fout, image::PNG).unwrap();
+		}
+
+		//keep
+		photos.push(Some(photo));
+		backgrounds.push(background);
+	}
+
+	//report incomplete pieces
+	if !truncated.is_empty() {
+		println!("===============================");
+		println!("Pieces touching the border of the photo, they are ignored, take them again on another photo :");
+		for (photo_id,(x,y,w,h)) in truncated.iter() {
+			println!("  - {} at ({},{}) size {}x{}",files[*photo_id],x,y,w,h);
+		}
+	}
+
+	//shape from the back side
+	if !back_file.is_empty() {
+		//same preparation and scale than the front photo so the masks can be compared
+		let (back_img,model,sheet) = load_photo(&back_file,"","-back");
+		let (detection,marker,_) = detect_photo(&back_img,&model.color,0,&scale);
+		scale.normalize_pieces(&detection.pieces,Some(&back_img),sheet,marker,&model);
+		let cnt = frontback::combine_front_back(&all,&detection.pieces);
+		println!("Pieces with the shape of the back : {}",cnt);
 	}
 
-	//create output image
-	if dump == 0 || dump == 1 {
-		let ref mut fout = File::create("step-1-detect.png").unwrap();
-		//write to file
-		img.write_to(fout, image::PNG).unwrap();
+	//recognize the pieces
+	fingerprint::compute_all(&all);
+	if dedup {
+		let cnt = fingerprint::merge_duplicates(&mut all);
+		println!("Duplicated pieces removed : {}",cnt);
+	}
+	if !ids_file.is_empty() {
+		let mut db = fingerprint::FingerprintDb::load(&ids_file);
+		db.assign_ids(&all);
+		db.save(&ids_file);
+	}
+
+	//pieces showing their back
+	let face_down = facedown::detect_face_down(&all);
+	if !face_down.is_empty() {
+		println!("===============================");
+		println!("Pieces lying face down, flip them and take the photo again :");
+		for id in face_down.iter() {
+			let p = all[*id].read().unwrap();
+			let (x,y,w,h) = p.position;
+			println!("  - {} at ({},{}) size {}x{}",files[p.photo as usize],x,y,w,h);
+		}
 	}
 
 	//create pool
@@ -149,11 +487,22 @@ fn main() {
 	pool.scoped(|scope| {
 		for pp in all.iter_mut() {
 			scope.execute(move || {
-				let mut p = pp.write().unwrap();
-				let angle = step3_rotate::find_best_rectangle(&p.mask);
+				let mut guard = pp.write().unwrap();
+				let p = &mut *guard;
+				let factor = pyramid::calc_factor(p.mask.dimensions(),coarse_size);
+				let angle = if factor == 1 {
+					step3_rotate::find_best_rectangle(&p.mask)
+				} else {
+					step3_rotate::find_best_rectangle(&pyramid::downscale_mask(&p.mask,factor))
+				};
 				p.angle = angle;
 				println!("=============> {:?} => {:?} <==============",p.id,angle);
-				step3_rotate::draw_best_rectangle(&mut p.mask,angle);
+				if dump >= 0 {
+					p.enable_overlay();
+					if let Some(ref mut overlay) = p.overlay {
+						step3_rotate::draw_best_rectangle(overlay,&p.mask,angle);
+					}
+				}
 
 				//save
 				if dump == 0 || dump == 2 {
@@ -161,30 +510,21 @@ fn main() {
 				}
 
 				//do all jobs
-				scan::scan_piece(&mut p, dump);
+				scan::scan_piece(p, dump);
 			});
 		}
 	});
 
 	//loop on invalid to fix them
 	println!("===============================");
-	step8_fix::fix_bad_quality(&pool,&all,file,dump);
+	step8_fix::fix_bad_quality(&pool,&all,&photos,&backgrounds,coarse_size,dump);
 
-	//cleanup
-	pool.scoped(|scope| {
-		for pp in all.iter_mut() {
-			scope.execute(move || {
-				let mut p = pp.write().unwrap();
-				step9_cleanup::clear_debug_markers(&mut p.mask);
-				if dump == 0 || dump == 9 {
-					p.save(9,"cleanup");
-				}
-			});
-		}
-	});
+	//known neighboors of the assembled pieces
+	step2_split::resolve_cluster_links(&all);
 
 	//do matchin
-	step10_matching::compute_matching(&pool,&mut all,dump);
+	let keep = if reference_file.is_empty() {1} else {common::REFERENCE_MATCH_KEEP};
+	step10_matching::compute_matching(&pool,&mut all,keep,dump);
 
 	//let links
 	/*{
@@ -219,12 +559,56 @@ fn main() {
 		p1.matches[2].push(PieceMatch{piece:2,side:0,angle:0.0,distance:0.0});
 	}*/
 
+	//load reference
+	let mut reference = None;
+	if !reference_file.is_empty() {
+		let grid = if reference_grid.is_empty() {
+			None
+		} else {
+			Some(reference::parse_grid(&reference_grid).expect("Invalid reference grid, should be COLSxROWS !"))
+		};
+		reference = Some(reference::ReferencePrior::new(&reference_file,&all,grid));
+	}
+
 	//build
-	let sol = step11_build::build_solution(&mut all,dump);
+	let sol = match reference {
+		Some(ref r) => step11_build::build_solution_guided(&mut all,Some(r),dump),
+		None => step11_build::build_solution(&mut all,dump),
+	};
+
+	//outlines
+	if dump == 0 || dump == 12 {
+		for p in all.iter() {
+			let p = p.read().unwrap();
+			step12_draw::draw_piece_outline_svg(&p,&format!("step-12-outline-{:05}.svg",p.id));
+		}
+	}
 
 	//draw
 	for (i,s) in sol.iter().enumerate() {
 		let fname = format!("step-12-solution-{}.png",i);
-		step12_draw::draw_solution(&s,&all,&fname);
+		match render.as_ref() {
+			"grid" => step12_draw::draw_solution(&s,&all,&fname),
+			"placed" => step12_draw::draw_solution_placed(&s,&all,&fname),
+			"annotated" => step12_draw::draw_solution_annotated(&s,&all,&fname),
+			_ => panic!("Invalid render mode {}, should be placed, grid or annotated !",render),
+		}
+		let fname = format!("step-12-solution-{}.svg",i);
+		step12_draw::draw_solution_svg(&s,&all,&fname);
+
+		//map back on the photo
+		if map {
+			for (photo_id,photo) in photos.iter().enumerate() {
+				let photo = match photo {
+					Some(photo) => photo,
+					None => {
+						println!("Skip map of photo {}, not kept in tiled mode",photo_id);
+						continue;
+					}
+				};
+				let fname = format!("step-12-map-{}-photo-{}.png",i,photo_id);
+				step12_draw::draw_photo_map(&photo,photo_id as u32,&s,&all,&fname);
+			}
+		}
 	}
 }
@@ -11,9 +11,12 @@ extern crate image;
 
 //from internal
 use common;
+use pyramid;
+use step6_hbpoints;
+use fingerprint::Fingerprint;
 
 //std
-use std::u32;
+use std::f32;
 
 //from image
 use image::GrayImage;
@@ -22,7 +25,8 @@ use image::RgbaImage;
 use std::sync::{Arc,RwLock};
 
 //consts
-const EXTRACT_MARGINS: u32 = 30;
+/// Margin in pixels around the piece, the rotation builds its own canvas.
+const EXTRACT_MARGINS: u32 = 10;
 pub const TOP:usize = 0;
 pub const RIGHT:usize = 1;
 pub const BOTTOM:usize = 2;
@@ -36,6 +40,16 @@ pub enum PieceSideType {
 	Unknown,
 }
 
+/// Measures used to decide the type of a side.
+#[derive(Debug,Copy,Clone)]
+pub struct PieceSideEvidence {
+	/// Lines of bump found by step 4 compared to the number needed to call it a bump.
+	pub bump_lines: f32,
+	/// Depth of the deepest bump and hole fitted on the contour (0 if none).
+	pub bump_depth: f32,
+	pub hole_depth: f32,
+}
+
 /// Structure to remember which side is bump and which is hole (true for bump)
 #[derive(Debug)]
 pub struct PieceSideInfos {
@@ -43,19 +57,45 @@ pub struct PieceSideInfos {
 	pub right: PieceSideType,
 	pub top: PieceSideType,
 	pub bottom: PieceSideType,
+	/// Confidence in [0,1] of the type of each side (TOP,RIGHT,BOTTOM,LEFT).
+	pub confidence: [f32;4],
+	pub evidence: [PieceSideEvidence;4],
 }
 
-/// Coordinate for intersting points
+/// Coordinate for intersting points (f32::MAX when not found)
 #[derive(Debug)]
 pub struct PiecePoints {
-	pub top_left_corner: (u32,u32),
-	pub top_right_corner: (u32,u32),
-	pub bottom_left_corner: (u32,u32),
-	pub bottom_right_corner: (u32,u32),
-	pub top_shape: (u32,u32),
-	pub bottom_shape: (u32,u32),
-	pub right_shape: (u32,u32),
-	pub left_shape: (u32,u32),
+	pub top_left_corner: (f32,f32),
+	pub top_right_corner: (f32,f32),
+	pub bottom_left_corner: (f32,f32),
+	pub bottom_right_corner: (f32,f32),
+	pub top_shape: (f32,f32),
+	pub bottom_shape: (f32,f32),
+	pub right_shape: (f32,f32),
+	pub left_shape: (f32,f32),
+	/// Confidence in [0,1] of the corners in clockwise order starting from the top left one
+	/// (0 when it was not found on the contour), the weak ones lower the quality mark.
+	pub corner_confidence: [f32;4],
+}
+
+/// Shape of a bump or hole fitted on the contour of a side. The positions are taken in the frame
+/// of the side : distance along the side from its first corner (clockwise order) and distance
+/// from the line joining the two corners, growing toward the end of the tab.
+#[derive(Debug,Copy,Clone)]
+pub struct PieceTab {
+	pub mode: PieceSideType,
+	/// Position along the side of the middle of the neck.
+	pub neck_pos: f32,
+	pub neck_width: f32,
+	pub head_center: (f32,f32),
+	pub head_radius: f32,
+	pub depth: f32,
+	/// Position of the head center compared to the middle of the side.
+	pub offset: f32,
+	/// Mean distance of the contour to the circle fitted on the head.
+	pub fit_error: f32,
+	/// End of the tab in the piece coordinates.
+	pub point: (f32,f32),
 }
 
 /// Store a matching solution
@@ -85,15 +125,39 @@ pub struct PieceFace {
 /// operation up the the matching.
 pub struct Piece {
 	pub id: u32,
+	/// Id kept between the runs thanks to the fingerprint database (same as id without it).
+	pub stable_id: u32,
+	pub photo: u32,
 	pub position:(u32,u32,u32,u32),
+	/// When the piece was separated from another one, mask of the pixels to keep in the position rect.
+	pub region: Option<GrayImage>,
+	/// Crop of the photo on the position rect when we do not keep the full photo (tiled mode) or
+	/// when the piece was resampled to the common scale.
+	pub source: Option<RgbaImage>,
+	/// Mask taken from the photo of the back side, used instead of the one built from the photo.
+	pub shape: Option<GrayImage>,
 	pub image: RgbaImage,
+	/// Pure binary mask of the piece (MASK_PIECE_PIXEL or MASK_BACKGROUND).
 	pub mask: GrayImage,
+	/// Debug annotations drawn by the scan steps (same size than the mask), None to not draw them.
+	pub overlay: Option<GrayImage>,
 	pub angle: u32,
 	pub side_infos: PieceSideInfos,
 	pub points: PiecePoints,
+	/// Bumps and holes fitted on each side (TOP,RIGHT,BOTTOM,LEFT) in clockwise order, a side
+	/// can have no tab (flat) or several ones (irregular cut).
+	pub tabs: [Vec<PieceTab>;4],
 	pub quality: u32,
 	pub faces: [PieceFace;4],
 	pub matches: [PieceMatchVec;4],
+	/// Pieces found assembled with this one on the photo with the vector between the centers.
+	pub cluster_links: Vec<(usize,(f32,f32))>,
+	/// Known neighboor on each side, resolved from cluster_links once the piece is rotated.
+	pub links: [Option<PieceMatch>;4],
+	/// Rotation invariant signature to recognize the piece.
+	pub fingerprint: Option<Fingerprint>,
+	/// The piece shows its back, its colors are not used.
+	pub face_down: bool,
 }
 
 pub type PieceVec = Vec<Arc<RwLock<Piece>>>;
@@ -102,14 +166,15 @@ impl PiecePoints {
 	/// Constructor to init coords
 	pub fn new() -> Self {
 		Self {
-			top_left_corner: (u32::MAX,u32::MAX),
-			top_right_corner: (u32::MAX,u32::MAX),
-			bottom_left_corner: (u32::MAX,u32::MAX),
-			bottom_right_corner: (u32::MAX,u32::MAX),
-			top_shape: (u32::MAX,u32::MAX),
-			bottom_shape: (u32::MAX,u32::MAX),
-			right_shape: (u32::MAX,u32::MAX),
-			left_shape: (u32::MAX,u32::MAX),
+			top_left_corner: (f32::MAX,f32::MAX),
+			top_right_corner: (f32::MAX,f32::MAX),
+			bottom_left_corner: (f32::MAX,f32::MAX),
+			bottom_right_corner: (f32::MAX,f32::MAX),
+			top_shape: (f32::MAX,f32::MAX),
+			bottom_shape: (f32::MAX,f32::MAX),
+			right_shape: (f32::MAX,f32::MAX),
+			left_shape: (f32::MAX,f32::MAX),
+			corner_confidence: [0.0;4],
 		}
 	}
 }
@@ -125,6 +190,16 @@ impl PieceFace {
 	}
 }
 
+impl PieceSideEvidence {
+	pub fn new() -> Self {
+		Self {
+			bump_lines: 0.0,
+			bump_depth: 0.0,
+			hole_depth: 0.0,
+		}
+	}
+}
+
 impl PieceSideInfos {
 	/// Constructor to init side infos
 	pub fn new() -> Self {
@@ -133,17 +208,74 @@ impl PieceSideInfos {
 			right: PieceSideType::Unknown,
 			top: PieceSideType::Unknown,
 			bottom: PieceSideType::Unknown,
+			confidence: [0.0;4],
+			evidence: [PieceSideEvidence::new();4],
+		}
+	}
+
+	/// Type of the given side (TOP,RIGHT,BOTTOM,LEFT).
+	pub fn get(self: &Self,side: usize) -> PieceSideType {
+		match side {
+			TOP => self.top,
+			RIGHT => self.right,
+			BOTTOM => self.bottom,
+			LEFT => self.left,
+			_ => panic!("Invalid side {} !",side),
+		}
+	}
+
+	/// Compute the confidence of the type of the side from the evidence. The tabs fitted on the
+	/// contour are compared to the opposite ones on the same side, without tabs we only know how
+	/// far step 4 was from its threshold. The confidence is halved when both disagree.
+	pub fn update_confidence(self: &mut Self,side: usize) {
+		let ev = self.evidence[side];
+		let bump = match self.get(side) {
+			PieceSideType::Bump => true,
+			PieceSideType::Hole => false,
+			PieceSideType::Unknown => {
+				self.confidence[side] = 0.0;
+				return;
+			},
+		};
+		let mut confidence = if ev.bump_depth > 0.0 || ev.hole_depth > 0.0 {
+			let (own,other) = if bump {(ev.bump_depth,ev.hole_depth)} else {(ev.hole_depth,ev.bump_depth)};
+			if own > 0.0 {(own - other) / own} else {0.0}
+		} else if bump {
+			ev.bump_lines - 1.0
+		} else {
+			1.0 - ev.bump_lines
+		};
+		if (ev.bump_lines >= 1.0) != bump {
+			confidence /= 2.0;
+		}
+		self.confidence[side] = confidence.max(0.0).min(1.0);
+	}
+
+	/// Change the type of the given side (TOP,RIGHT,BOTTOM,LEFT).
+	pub fn set(self: &mut Self,side: usize,mode: PieceSideType) {
+		match side {
+			TOP => self.top = mode,
+			RIGHT => self.right = mode,
+			BOTTOM => self.bottom = mode,
+			LEFT => self.left = mode,
+			_ => panic!("Invalid side {} !",side),
 		}
 	}
 }
 
 impl Piece {
-	fn internal_load(self: &mut Self,img:&image::RgbaImage,back:&image::Rgba<u8>) {
+	fn internal_load(self: &mut Self,img:Option<&image::RgbaImage>,back:&image::Rgba<u8>) {
 		//extract
 		let (x0,y0,w,h) = self.position;
 
-		//take margins to rotate inside
-		let (x1,y1) = (EXTRACT_MARGINS*w/100,EXTRACT_MARGINS*h/100);
+		//when we keep our own crop of the photo we read from it, it can be resampled to another scale
+		let (img,x0,y0,w,h) = match self.source {
+			Some(ref source) => (source,0,0,source.width(),source.height()),
+			None => (img.expect("Need the photo to load the piece !"),x0,y0,w,h),
+		};
+
+		//keep margins
+		let (x1,y1) = (EXTRACT_MARGINS,EXTRACT_MARGINS);
 
 		//init images
 		for pixel in self.image.pixels_mut() {
@@ -155,30 +287,56 @@ impl Piece {
 		}
 
 		//copy image & mask
+		let col = image::Luma([common::MASK_PIECE_PIXEL]);
 		for y in 0..h {
 			for x in 0..w {
+				//ignore pixels of the other pieces
+				if let Some(ref region) = self.region {
+					if *region.get_pixel(x,y) != col {
+						continue;
+					}
+				}
 				self.image.put_pixel(x1+x,y1+y,*img.get_pixel(x0+x,y0+y));
 			}
 		}
 
 		//build mask
-		let col = image::Luma([common::MASK_PIECE_PIXEL]);
 		for y in 0..h {
 			for x in 0..w {
+				if let Some(ref region) = self.region {
+					if *region.get_pixel(x,y) != col {
+						continue;
+					}
+				}
 				if img.get_pixel(x0+x,y0+y) != back {
 					self.mask.put_pixel(x1+x,y1+y,col);
 				}
 			}
 		}
+
+		//shape from the back side
+		if let Some(ref shape) = self.shape {
+			self.mask = shape.clone();
+		}
 	}
 
-	pub fn load(self: &mut Self,img:&image::RgbaImage,back:&image::Rgba<u8>) {
+	/// Reload the piece from the photo (None if the piece keep its own crop).
+	pub fn load(self: &mut Self,img:Option<&image::RgbaImage>,back:&image::Rgba<u8>) {
 		//erase
 		self.angle = 0;
 		self.side_infos = PieceSideInfos::new();
 		self.points = PiecePoints::new();
+		self.tabs = [vec!(),vec!(),vec!(),vec!()];
 		self.quality = 0;
 
+		//the rotation changed the size of the canvas
+		let (w,h) = Self::calc_canvas_size(self.position,self.source.as_ref());
+		self.image = RgbaImage::new(w,h);
+		self.mask = GrayImage::new(w,h);
+		if self.overlay.is_some() {
+			self.enable_overlay();
+		}
+
 		//load image
 		self.internal_load(img,back);
 	}
@@ -186,31 +344,120 @@ impl Piece {
 	/// Constructor of a puzzle piece. It take the global image, the backround color and a square
 	/// from which to extract the image and the mask.
 	pub fn new(img:&image::RgbaImage,back:&image::Rgba<u8>,square:(u32,u32,u32,u32),id:u32) -> Self {
-		//extract
-		let (_,_,w,h) = square;
+		Self::new_with_region(img,back,square,None,id)
+	}
 
-		//take margins to rotate inside
-		let (ww,hh) = (w+2*EXTRACT_MARGINS*w/100,h+2*EXTRACT_MARGINS*h/100);
+	/// Same than new() but only keep the pixels of the given region (same size than the square)
+	/// when the piece was separated from other ones.
+	pub fn new_with_region(img:&image::RgbaImage,back:&image::Rgba<u8>,square:(u32,u32,u32,u32),region:Option<GrayImage>,id:u32) -> Self {
+		let mut cur = Self::create(square,region,None,id);
+		cur.internal_load(Some(img),back);
+		cur
+	}
+
+	/// Build the piece from its own crop of the photo (the square), which is kept to reload it
+	/// later without the full photo.
+	pub fn new_with_source(source:RgbaImage,back:&image::Rgba<u8>,square:(u32,u32,u32,u32),region:Option<GrayImage>,id:u32) -> Self {
+		let mut cur = Self::create(square,region,Some(source),id);
+		cur.internal_load(None,back);
+		cur
+	}
+
+	/// Size of the image of the piece before rotation, from its own crop if it has one.
+	fn calc_canvas_size(square:(u32,u32,u32,u32),source:Option<&RgbaImage>) -> (u32,u32) {
+		let (w,h) = match source {
+			Some(source) => source.dimensions(),
+			None => (square.2,square.3),
+		};
+		(w+2*EXTRACT_MARGINS,h+2*EXTRACT_MARGINS)
+	}
+
+	fn create(square:(u32,u32,u32,u32),region:Option<GrayImage>,source:Option<RgbaImage>,id:u32) -> Self {
+		//keep margins
+		let (ww,hh) = Self::calc_canvas_size(square,source.as_ref());
 
 		//create
-		let mut cur = Piece {
+		Piece {
 			id: id,
+			stable_id: id,
+			photo: 0,
 			position: square,
+			region: region,
+			source: source,
+			shape: None,
 			image: RgbaImage::new(ww,hh),
 			mask: GrayImage::new(ww,hh),
+			overlay: None,
 			angle: 0,
 			side_infos: PieceSideInfos::new(),
 			points: PiecePoints::new(),
+			tabs: [vec!(),vec!(),vec!(),vec!()],
 			quality: 0,
 			faces: [PieceFace::new(),PieceFace::new(),PieceFace::new(),PieceFace::new()],
 			matches: [vec!(),vec!(),vec!(),vec!()],
-		};
+			cluster_links: vec!(),
+			links: [None,None,None,None],
+			fingerprint: None,
+			face_down: false,
+		}
+	}
 
-		//load
-		cur.internal_load(&img,&back);
+	/// Build a coarse copy of the piece reduced by the given factor to quickly search its angle.
+	pub fn downscale(self: &Self,factor:u32) -> Self {
+		self.copy_with(pyramid::downscale_rgba(&self.image,factor),pyramid::downscale_mask(&self.mask,factor))
+	}
 
-		//ret
-		cur
+	/// Build a copy of the piece at full resolution to test an angle without touching it.
+	pub fn scan_copy(self: &Self) -> Self {
+		self.copy_with(self.image.clone(),self.mask.clone())
+	}
+
+	/// Copy of the piece with the given image and mask, without the crops and the scan results.
+	fn copy_with(self: &Self,image: RgbaImage,mask: GrayImage) -> Self {
+		Piece {
+			id: self.id,
+			stable_id: self.stable_id,
+			photo: self.photo,
+			position: self.position,
+			region: None,
+			source: None,
+			shape: None,
+			image: image,
+			mask: mask,
+			overlay: None,
+			angle: self.angle,
+			side_infos: PieceSideInfos::new(),
+			points: PiecePoints::new(),
+			tabs: [vec!(),vec!(),vec!(),vec!()],
+			quality: 0,
+			faces: [PieceFace::new(),PieceFace::new(),PieceFace::new(),PieceFace::new()],
+			matches: [vec!(),vec!(),vec!(),vec!()],
+			cluster_links: vec!(),
+			links: [None,None,None,None],
+			fingerprint: None,
+			face_down: false,
+		}
+	}
+
+	/// Start a new empty overlay to receive the debug annotations.
+	pub fn enable_overlay(self: &mut Self) {
+		let (w,h) = self.mask.dimensions();
+		self.overlay = Some(GrayImage::from_pixel(w,h,image::Luma([common::OVERLAY_NONE])));
+	}
+
+	/// Use the given mask (same size than the image) as shape of the piece.
+	pub fn set_shape(self: &mut Self,shape:GrayImage) {
+		self.mask = shape.clone();
+		self.shape = Some(shape);
+	}
+
+	/// Extract the given square (in photo coordinates) from the own crop of the piece if it has one.
+	pub fn crop_source(self: &Self,square:(u32,u32,u32,u32)) -> Option<RgbaImage> {
+		let (x0,y0,_,_) = self.position;
+		let (x,y,w,h) = square;
+		self.source.as_ref().map(|source| {
+			RgbaImage::from_fn(w,h,|dx,dy| *source.get_pixel(x - x0 + dx,y - y0 + dy))
+		})
 	}
 
 	/// Save the image and mask into files for debugging.
@@ -229,19 +476,47 @@ impl Piece {
 			let fname = base.to_string() + "-mask.png";
 			self.mask.save(fname).unwrap();
 		}
+
+		//export annotations over the mask
+		if let Some(ref overlay) = self.overlay {
+			let fname = base.to_string() + "-overlay.png";
+			let mut merged = self.mask.clone();
+			for (pixel,annotation) in merged.pixels_mut().zip(overlay.pixels()) {
+				if annotation.data[0] != common::OVERLAY_NONE {
+					*pixel = *annotation;
+				}
+			}
+			merged.save(fname).unwrap();
+		}
+	}
+
+	/// Return the 4 corners in clockwise order starting from the top left one.
+	pub fn get_corners(self: &Self) -> [(f32,f32);4] {
+		let p = &self.points;
+		[p.top_left_corner,p.top_right_corner,p.bottom_right_corner,p.bottom_left_corner]
 	}
 
 	//help
-	fn helper_face(top:(u32,u32),middle:(u32,u32),bottom:(u32,u32),mode:PieceSideType) -> PieceFace
+	fn helper_face(top:(f32,f32),middle:(f32,f32),bottom:(f32,f32),mode:PieceSideType) -> PieceFace
 	{
 		PieceFace {
-			top: (top.0 as f32,top.1 as f32),
-			middle: (middle.0 as f32,middle.1 as f32),
-			bottom: (bottom.0 as f32,bottom.1 as f32),
+			top: top,
+			middle: middle,
+			bottom: bottom,
 			mode: mode,
 		}
 	}
 
+	/// Check if the given side can be assembled with the side of the other piece : the main tabs
+	/// (the deepest ones) must be of opposite types. When the tabs are not known we only compare
+	/// the type of the sides.
+	pub fn sides_compatible(self: &Self,side: usize,other: &Piece,other_side: usize) -> bool {
+		match (step6_hbpoints::get_main_tab(&self.tabs[side]),step6_hbpoints::get_main_tab(&other.tabs[other_side])) {
+			(Some(t1),Some(t2)) => t1.mode != t2.mode,
+			_ => self.faces[side].mode != other.faces[other_side].mode,
+		}
+	}
+
 	//extract faces to help matching
 	pub fn extract_faces(self: &mut Self) {
 		self.faces[TOP] = Self::helper_face(self.points.top_left_corner,self.points.top_shape,self.points.top_right_corner,self.side_infos.top);
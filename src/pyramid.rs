@@ -0,0 +1,100 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// On high resolution photos the search of the rotation angle and the brute force of step8_fix
+/// scan the masks many times. The angle does not depend on the resolution so we run those
+/// searches on a downscaled level of the piece (power of two factor) and only keep the
+/// full resolution for the corners, the hole/bump points and the matching.
+
+//load external
+extern crate image;
+
+//extern
+use image::{GrayImage,RgbaImage,Luma,Rgba};
+
+//internal
+use common;
+
+/// Compute the downscale factor (power of two) to get the largest side under the target size.
+/// Return 1 to keep the full resolution, also if the target is 0.
+pub fn calc_factor(size: (u32,u32),target: u32) -> u32 {
+	let mut factor = 1;
+	if target == 0 {
+		return factor;
+	}
+	while size.0.max(size.1) / factor > target {
+		factor *= 2;
+	}
+	factor
+}
+
+/// Reduce the mask, a coarse pixel is part of the piece if at least half of the block is.
+pub fn downscale_mask(mask: &GrayImage,factor: u32) -> GrayImage {
+	let (w,h) = mask.dimensions();
+	GrayImage::from_fn(w / factor,h / factor,|x,y| {
+		let mut cnt = 0;
+		for dy in 0..factor {
+			for dx in 0..factor {
+				if mask.get_pixel(x * factor + dx,y * factor + dy).data[0] == common::MASK_PIECE_PIXEL {
+					cnt += 1;
+				}
+			}
+		}
+		if 2 * cnt >= factor * factor {
+			Luma([common::MASK_PIECE_PIXEL])
+		} else {
+			Luma([common::MASK_BACKGROUND])
+		}
+	})
+}
+
+/// Reduce the image by averaging the blocks.
+pub fn downscale_rgba(img: &RgbaImage,factor: u32) -> RgbaImage {
+	let (w,h) = img.dimensions();
+	let cnt = factor * factor;
+	RgbaImage::from_fn(w / factor,h / factor,|x,y| {
+		let mut sum = [0u32;4];
+		for dy in 0..factor {
+			for dx in 0..factor {
+				let p = img.get_pixel(x * factor + dx,y * factor + dy);
+				for c in 0..4 {
+					sum[c] += p.data[c] as u32;
+				}
+			}
+		}
+		Rgba([(sum[0] / cnt) as u8,(sum[1] / cnt) as u8,(sum[2] / cnt) as u8,(sum[3] / cnt) as u8])
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,Luma};
+	use pyramid::*;
+	use common;
+
+	#[test]
+	fn factor() {
+		assert_eq!(calc_factor((200,150),256),1);
+		assert_eq!(calc_factor((1000,600),256),4);
+		assert_eq!(calc_factor((1000,600),0),1);
+	}
+
+	#[test]
+	fn downscale_square() {
+		let mut mask = GrayImage::new(40,40);
+		for y in 12..30 {
+			for x in 12..28 {
+				mask.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+		let coarse = downscale_mask(&mask,4);
+		assert_eq!(coarse.dimensions(),(10,10));
+		let cnt = coarse.pixels().filter(|p| p.data[0] == common::MASK_PIECE_PIXEL).count();
+		assert_eq!(cnt,20);
+	}
+}
@@ -0,0 +1,404 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// Most puzzles come with the picture of the box. We cut it in the expected grid and compare
+/// the colors of each piece with each tile for the 4 rotations. This gives a placement prior
+/// used by step11_build in addition to the shape matching.
+
+//load external
+extern crate image;
+
+//std
+use std::f32;
+use std::path::Path;
+
+//extern
+use image::RgbaImage;
+
+//internal
+use piece::{Piece,PieceVec};
+use step5_corners;
+use common;
+
+/// Mean color of each cell of a REFERENCE_SIGN_CELLS*REFERENCE_SIGN_CELLS grid (None if the cell is empty).
+type Signature = Vec<Option<[f32;3]>>;
+
+/// Cost for each piece to be placed on each tile of the reference with each rotation.
+pub struct ReferencePrior {
+	pub cols: usize,
+	pub rows: usize,
+	pub strict: bool,
+	costs: Vec<Vec<[f32;4]>>,
+}
+
+/// Parse a grid given as COLSxROWS on the command line.
+pub fn parse_grid(value: &str) -> Option<(usize,usize)> {
+	let parts: Vec<&str> = value.split('x').collect();
+	if parts.len() != 2 {
+		return None;
+	}
+	match (parts[0].trim().parse::<usize>(),parts[1].trim().parse::<usize>()) {
+		(Ok(cols),Ok(rows)) if cols > 0 && rows > 0 => Some((cols,rows)),
+		_ => None,
+	}
+}
+
+/// Estimate the grid from the number of pieces and the ratio of the reference picture.
+/// We keep the grid with the closest ratio, penalizing empty cells.
+pub fn estimate_grid(nb: usize,size: (u32,u32)) -> (usize,usize) {
+	let ratio = size.0 as f32 / size.1 as f32;
+	let mut best = (nb.max(1),1);
+	let mut best_score = f32::MAX;
+
+	for rows in 1..(nb+1) {
+		let cols = (nb + rows - 1) / rows;
+		let empty = (cols * rows - nb) as f32;
+		let score = ((cols as f32 / rows as f32) / ratio).ln().abs() + 0.1 * empty;
+		if score < best_score {
+			best_score = score;
+			best = (cols,rows);
+		}
+	}
+
+	best
+}
+
+/// Compute the mean color of the pixels in the given rect split into cells.
+/// If a mask is given we only consider the piece pixels.
+fn calc_signature(img: &RgbaImage,mask: Option<&image::GrayImage>,rect: (u32,u32,u32,u32)) -> Signature {
+	//vars
+	let cells = common::REFERENCE_SIGN_CELLS;
+	let (x0,y0,x1,y1) = rect;
+	let (w,h) = (x1 - x0,y1 - y0);
+	let color = image::Luma([common::MASK_PIECE_PIXEL]);
+	let mut sum = vec![[0.0f32;3];(cells*cells) as usize];
+	let mut cnt = vec![0u32;(cells*cells) as usize];
+
+	//loop on all pixels
+	for y in y0..y1 {
+		for x in x0..x1 {
+			let keep = match mask {
+				Some(m) => *m.get_pixel(x,y) == color,
+				None => true,
+			};
+			if keep {
+				let cx = ((x - x0) * cells / w).min(cells - 1);
+				let cy = ((y - y0) * cells / h).min(cells - 1);
+				let id = (cy * cells + cx) as usize;
+				let p = img.get_pixel(x,y);
+				for c in 0..3 {
+					sum[id][c] += p.data[c] as f32;
+				}
+				cnt[id] += 1;
+			}
+		}
+	}
+
+	//mean
+	sum.iter().zip(cnt.iter()).map(|(s,n)| {
+		if *n == 0 {
+			None
+		} else {
+			let n = *n as f32;
+			Some([s[0] / n,s[1] / n,s[2] / n])
+		}
+	}).collect()
+}
+
+/// Extract the rectangle inside the 4 corners of the piece, fallback on the surrounding
+/// rect if the corners are not usable.
+fn get_piece_core_rect(piece: &Piece) -> (u32,u32,u32,u32) {
+	let p = &piece.points;
+	let x0 = p.top_left_corner.0.max(p.bottom_left_corner.0).round() as u32;
+	let x1 = p.top_right_corner.0.min(p.bottom_right_corner.0).round() as u32;
+	let y0 = p.top_left_corner.1.max(p.top_right_corner.1).round() as u32;
+	let y1 = p.bottom_left_corner.1.min(p.bottom_right_corner.1).round() as u32;
+	let (w,h) = piece.mask.dimensions();
+
+	if x1 > x0 && y1 > y0 && x1 < w && y1 < h {
+		(x0,y0,x1,y1)
+	} else {
+		let (xmin,ymin,xmax,ymax) = step5_corners::extract_surrounding_rect(&piece.mask);
+		(xmin,ymin,xmax+1,ymax+1)
+	}
+}
+
+/// Rotate the signature grid by 90 degree clockwise, the same way the solution rotate the pieces.
+fn rotate_signature(sign: &Signature) -> Signature {
+	let cells = common::REFERENCE_SIGN_CELLS as usize;
+	let mut ret = sign.clone();
+	for y in 0..cells {
+		for x in 0..cells {
+			ret[y * cells + x] = sign[(cells - 1 - x) * cells + y];
+		}
+	}
+	ret
+}
+
+/// Distance between two signature, normalized in [0,1].
+fn calc_signature_dist(s1: &Signature,s2: &Signature) -> f32 {
+	let mut sum = 0.0;
+	let mut cnt = 0;
+	for (c1,c2) in s1.iter().zip(s2.iter()) {
+		if let (Some(c1),Some(c2)) = (c1,c2) {
+			let d: f32 = (0..3).map(|c| (c1[c] - c2[c]) * (c1[c] - c2[c])).sum();
+			sum += d.sqrt() / (255.0 * 3.0f32.sqrt());
+			cnt += 1;
+		}
+	}
+
+	if cnt == 0 {
+		common::REFERENCE_OUT_COST
+	} else {
+		sum / cnt as f32
+	}
+}
+
+/// Mean color of all the cells of a list of signatures.
+fn calc_mean_color(signs: &Vec<Signature>) -> [f32;3] {
+	let mut sum = [0.0f32;3];
+	let mut cnt = 0;
+	for s in signs.iter() {
+		for c in s.iter() {
+			if let Some(c) = c {
+				for i in 0..3 {
+					sum[i] += c[i];
+				}
+				cnt += 1;
+			}
+		}
+	}
+
+	if cnt == 0 {
+		[1.0,1.0,1.0]
+	} else {
+		[sum[0] / cnt as f32,sum[1] / cnt as f32,sum[2] / cnt as f32]
+	}
+}
+
+impl ReferencePrior {
+	/// Load the reference picture and compute the costs for all pieces. If no grid is given
+	/// we estimate it from the number of pieces.
+	pub fn new(fname: &str,pieces: &PieceVec,grid: Option<(usize,usize)>) -> Self {
+		println!("Load reference {:?}",fname);
+		let img = image::open(&Path::new(fname)).unwrap().to_rgba();
+		Self::new_from_image(&img,pieces,grid)
+	}
+
+	/// Same than new() but from an already loaded picture.
+	pub fn new_from_image(img: &RgbaImage,pieces: &PieceVec,grid: Option<(usize,usize)>) -> Self {
+		let (w,h) = img.dimensions();
+
+		//grid
+		let (cols,rows) = match grid {
+			Some(g) => g,
+			None => estimate_grid(pieces.len(),(w,h)),
+		};
+		println!("Reference grid : {}x{}",cols,rows);
+
+		//tiles
+		let mut tiles: Vec<Signature> = vec!();
+		for row in 0..rows {
+			for col in 0..cols {
+				let rect = (
+					col as u32 * w / cols as u32,
+					row as u32 * h / rows as u32,
+					(col as u32 + 1) * w / cols as u32,
+					(row as u32 + 1) * h / rows as u32,
+				);
+				tiles.push(calc_signature(img,None,rect));
+			}
+		}
+
+		//pieces
+		let mut signs: Vec<Signature> = vec!();
+		for p in pieces.iter() {
+			let p = p.read().unwrap();
+			if p.face_down {
+				//the back colors do not tell anything about the placement
+				signs.push(vec![None;(common::REFERENCE_SIGN_CELLS * common::REFERENCE_SIGN_CELLS) as usize]);
+			} else {
+				let rect = get_piece_core_rect(&p);
+				signs.push(calc_signature(&p.image,Some(&p.mask),rect));
+			}
+		}
+
+		//white balance, the photo and the box picture are rarely taken with the same light
+		let ref_mean = calc_mean_color(&tiles);
+		let piece_mean = calc_mean_color(&signs);
+		for s in signs.iter_mut() {
+			for c in s.iter_mut() {
+				if let Some(c) = c.as_mut() {
+					for i in 0..3 {
+						c[i] = (c[i] * ref_mean[i] / piece_mean[i].max(1.0)).min(255.0);
+					}
+				}
+			}
+		}
+
+		//costs
+		let mut costs = vec!();
+		for s in signs.iter() {
+			let mut rotated = vec!(s.clone());
+			for r in 1..4 {
+				let next = rotate_signature(&rotated[r-1]);
+				rotated.push(next);
+			}
+			let mut piece_costs = vec!();
+			for t in tiles.iter() {
+				let mut c = [0.0f32;4];
+				for r in 0..4 {
+					c[r] = calc_signature_dist(&rotated[r],t);
+				}
+				piece_costs.push(c);
+			}
+			costs.push(piece_costs);
+		}
+
+		Self {
+			cols: cols,
+			rows: rows,
+			strict: grid.is_some(),
+			costs: costs,
+		}
+	}
+
+	/// Cost to put the piece on the given tile with the given rotation. Tiles out of the
+	/// reference get the maximal cost.
+	pub fn cost(self: &Self,piece: usize,tile: (i64,i64),rotation: usize) -> f32 {
+		let (col,row) = tile;
+		if col < 0 || row < 0 || col >= self.cols as i64 || row >= self.rows as i64 {
+			common::REFERENCE_OUT_COST
+		} else {
+			self.costs[piece][row as usize * self.cols + col as usize][rotation % 4]
+		}
+	}
+
+	/// Return the best (tile,rotation,cost) for the given piece.
+	pub fn best_tile(self: &Self,piece: usize) -> ((usize,usize),usize,f32) {
+		let mut ret = ((0,0),0,f32::MAX);
+		for (tid,c) in self.costs[piece].iter().enumerate() {
+			for r in 0..4 {
+				if c[r] < ret.2 {
+					ret = ((tid % self.cols,tid / self.cols),r,c[r]);
+				}
+			}
+		}
+		ret
+	}
+
+	/// Select the piece to start the build with, we take the one with the larger gap between its
+	/// best and second best tile as it is the less ambiguous.
+	pub fn select_start(self: &Self) -> (usize,(usize,usize),usize) {
+		let mut ret = (0,(0,0),0);
+		let mut best_gap = -1.0;
+		for pid in 0..self.costs.len() {
+			let (tile,rot,best) = self.best_tile(pid);
+			let mut second = f32::MAX;
+			for (tid,c) in self.costs[pid].iter().enumerate() {
+				if tid != tile.1 * self.cols + tile.0 {
+					for r in 0..4 {
+						second = second.min(c[r]);
+					}
+				}
+			}
+			let gap = if second == f32::MAX {0.0} else {second - best};
+			if gap > best_gap {
+				best_gap = gap;
+				ret = (pid,tile,rot);
+			}
+		}
+		ret
+	}
+}
+
+///Implement unit tests of the module
+#[cfg(test)]
+mod test {
+	use std::sync::{Arc,RwLock};
+	use image::{Rgba,RgbaImage};
+	use reference::*;
+	use piece::{Piece,PieceVec};
+
+	/// Fill a rect of the image with the given color.
+	fn fill(img: &mut RgbaImage,rect: (u32,u32,u32,u32),color: [u8;4]) {
+		let (x0,y0,w,h) = rect;
+		for y in y0..(y0+h) {
+			for x in x0..(x0+w) {
+				img.put_pixel(x,y,Rgba(color));
+			}
+		}
+	}
+
+	#[test]
+	fn parse_grid_values() {
+		assert_eq!(parse_grid("4x3"),Some((4,3)));
+		assert_eq!(parse_grid(" 2 x 5 "),Some((2,5)));
+		assert_eq!(parse_grid("0x3"),None);
+		assert_eq!(parse_grid("4x3x2"),None);
+		assert_eq!(parse_grid("abc"),None);
+	}
+
+	#[test]
+	fn estimate_grid_ratio() {
+		assert_eq!(estimate_grid(12,(400,300)),(4,3));
+		assert_eq!(estimate_grid(12,(300,400)),(3,4));
+		assert_eq!(estimate_grid(6,(300,200)),(3,2));
+		assert_eq!(estimate_grid(7,(300,200)),(4,2));
+	}
+
+	#[test]
+	fn rotate_signature_clockwise() {
+		let cells = common::REFERENCE_SIGN_CELLS as usize;
+		let sign: Signature = (0..cells*cells).map(|i| Some([i as f32,0.0,0.0])).collect();
+
+		//top left goes to top right
+		let rot = rotate_signature(&sign);
+		assert_eq!(rot[cells - 1],sign[0]);
+		assert_eq!(rot[cells * cells - 1],sign[cells - 1]);
+
+		//4 rotations is identity
+		let mut cur = rot;
+		for _ in 0..3 {
+			cur = rotate_signature(&cur);
+		}
+		assert_eq!(cur,sign);
+	}
+
+	#[test]
+	fn best_tile_on_box() {
+		//box picture 2x1, red|green on the left tile, yellow over blue on the right tile
+		let (red,green,yellow,blue) = ([255,0,0,255],[0,255,0,255],[255,255,0,255],[0,0,255,255]);
+		let mut refimg = RgbaImage::new(100,50);
+		fill(&mut refimg,(0,0,25,50),red);
+		fill(&mut refimg,(25,0,25,50),green);
+		fill(&mut refimg,(50,0,50,25),yellow);
+		fill(&mut refimg,(50,25,50,25),blue);
+
+		//two pieces on a white photo, the second one is yellow|blue so need one rotation
+		let white = Rgba([255,255,255,255]);
+		let mut photo = RgbaImage::from_pixel(100,50,white);
+		fill(&mut photo,(10,10,10,20),red);
+		fill(&mut photo,(20,10,10,20),green);
+		fill(&mut photo,(60,10,10,20),yellow);
+		fill(&mut photo,(70,10,10,20),blue);
+		let mut pieces: PieceVec = Vec::new();
+		pieces.push(Arc::new(RwLock::new(Piece::new(&photo,&white,(10,10,20,20),0))));
+		pieces.push(Arc::new(RwLock::new(Piece::new(&photo,&white,(60,10,20,20),1))));
+
+		//check
+		let prior = ReferencePrior::new_from_image(&refimg,&pieces,Some((2,1)));
+		let (tile,rot,cost) = prior.best_tile(0);
+		assert_eq!((tile,rot),((0,0),0));
+		assert!(cost < 0.05);
+		let (tile,rot,cost) = prior.best_tile(1);
+		assert_eq!((tile,rot),((1,0),1));
+		assert!(cost < 0.05);
+		assert_eq!(prior.cost(1,(2,0),0),common::REFERENCE_OUT_COST);
+	}
+}
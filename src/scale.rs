@@ -0,0 +1,243 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// When the photos are taken at different distances the pieces do not have the same size in
+/// pixels and the faces or the masks cannot be compared by the matching. We measure the scale
+/// of each photo (median size of the pieces, size of the background sheet of known paper
+/// format or a printed black square marker of known size) and resample each piece to the same
+/// scale. The resampled piece keeps its own crop while its position stays in the coordinates of
+/// the photo so the detection is done only once.
+
+//load external
+extern crate image;
+
+//extern
+use image::{RgbaImage,imageops};
+
+//internal
+use piece::{Piece,PieceVec};
+use step2_split::calc_area;
+use background::BackgroundModel;
+use common;
+
+/// How to measure the scale of a photo.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ScaleMode {
+	/// Keep the photos as they are.
+	Keep,
+	/// Median size of the pieces, relative to the first photo.
+	Median,
+	/// Size of the background sheet in millimeters (the perspective correction must be enabled).
+	Paper((f32,f32)),
+	/// Side of a printed black square in millimeters.
+	Marker(f32),
+}
+
+/// Bring all the photos to the same scale.
+pub struct ScaleNormalizer {
+	pub mode: ScaleMode,
+	/// Wanted scale, in pixels per millimeter for paper and marker or median piece side, taken from the first photo if None.
+	pub target: Option<f32>,
+}
+
+/// Parse the paper size given as a format name (a3, a4, a5, letter) or as WxH in millimeters.
+pub fn parse_paper_size(value: &str) -> Option<(f32,f32)> {
+	match value.to_lowercase().as_ref() {
+		"a3" => return Some((297.0,420.0)),
+		"a4" => return Some((210.0,297.0)),
+		"a5" => return Some((148.0,210.0)),
+		"letter" => return Some((215.9,279.4)),
+		_ => {},
+	}
+	let parts: Vec<&str> = value.split('x').collect();
+	if parts.len() != 2 {
+		return None;
+	}
+	match (parts[0].trim().parse::<f32>(),parts[1].trim().parse::<f32>()) {
+		(Ok(w),Ok(h)) if w > 0.0 && h > 0.0 => Some((w,h)),
+		_ => None,
+	}
+}
+
+/// Pixels per millimeter from the size of the sheet in pixels, matching the long sides together.
+pub fn calc_paper_ppmm(sheet: (u32,u32),paper: (f32,f32)) -> f32 {
+	let (long,short) = (sheet.0.max(sheet.1) as f32,sheet.0.min(sheet.1) as f32);
+	let (plong,pshort) = (paper.0.max(paper.1),paper.0.min(paper.1));
+	(long / plong + short / pshort) / 2.0
+}
+
+/// Resample the piece by the given factor and repaint the blended background pixels. The pixels
+/// of the other pieces (split pieces) are erased before so they do not bleed into this one.
+///
+/// **photo**: photo of the piece (None if the piece keeps its own crop).
+pub fn resample_piece(piece: &Piece,photo: Option<&RgbaImage>,factor: f32,model: &BackgroundModel) -> Piece {
+	//crop
+	let (x0,y0,w,h) = piece.position;
+	let keep = image::Luma([common::MASK_PIECE_PIXEL]);
+	let crop = RgbaImage::from_fn(w,h,|x,y| {
+		if piece.region.as_ref().map(|r| *r.get_pixel(x,y) != keep).unwrap_or(false) {
+			model.color
+		} else {
+			match piece.source {
+				Some(ref source) => *source.get_pixel(x,y),
+				None => *photo.expect("Need the photo to resample the piece !").get_pixel(x0 + x,y0 + y),
+			}
+		}
+	});
+
+	//resize
+	let (nw,nh) = (((w as f32 * factor).round() as u32).max(1),((h as f32 * factor).round() as u32).max(1));
+	let mut resized = imageops::resize(&crop,nw,nh,imageops::FilterType::Triangle);
+	model.normalize(&mut resized);
+
+	//rebuild
+	let mut ret = Piece::new_with_source(resized,&model.color,piece.position,None,piece.id);
+	ret.photo = piece.photo;
+	ret.cluster_links = piece.cluster_links.iter().map(|&(other,d)| (other,(d.0 * factor,d.1 * factor))).collect();
+	ret
+}
+
+impl ScaleNormalizer {
+	pub fn new(mode: ScaleMode,target: f32) -> Self {
+		Self {
+			mode: mode,
+			target: if target > 0.0 {Some(target)} else {None},
+		}
+	}
+
+	/// In marker mode search the marker among the objects : a filled square. It is
+	/// removed from the pieces and its side in pixels is returned.
+	pub fn take_marker(self: &Self,pieces: &mut PieceVec) -> Option<f32> {
+		if let ScaleMode::Marker(_) = self.mode {
+			//search
+			let found = pieces.iter().position(|pp| {
+				let p = pp.read().unwrap();
+				let (_,_,w,h) = p.position;
+				let fill = calc_area(&p.mask) as f32 / (w * h).max(1) as f32;
+				let aspect = w.min(h) as f32 / w.max(h).max(1) as f32;
+				fill >= common::SCALE_MARKER_MIN_FILL && aspect >= common::SCALE_MARKER_MIN_ASPECT
+			});
+
+			//remove and renumber
+			if let Some(id) = found {
+				let side = (calc_area(&pieces[id].read().unwrap().mask) as f32).sqrt();
+				pieces.remove(id);
+				for (i,p) in pieces.iter().enumerate() {
+					p.write().unwrap().id = i as u32;
+				}
+				println!("Scale marker found, side {} pixels",side);
+				return Some(side);
+			} else {
+				println!("WARNING : scale marker not found");
+			}
+		}
+		None
+	}
+
+	/// Measure the scale of the photo, None if it cannot be measured.
+	///
+	/// **pieces**: pieces found on the photo.
+	/// **sheet**: size of the sheet in pixels if the perspective was corrected.
+	/// **marker**: side of the marker in pixels if found.
+	pub fn measure(self: &Self,pieces: &PieceVec,sheet: Option<(u32,u32)>,marker: Option<f32>) -> Option<f32> {
+		match self.mode {
+			ScaleMode::Keep => None,
+			ScaleMode::Median => {
+				let mut areas: Vec<u32> = pieces.iter().map(|p| calc_area(&p.read().unwrap().mask)).collect();
+				areas.sort();
+				areas.get(areas.len() / 2).map(|a| (*a as f32).sqrt())
+			},
+			ScaleMode::Paper(paper) => {
+				if sheet.is_none() {
+					println!("WARNING : sheet not found, cannot compute the scale");
+				}
+				sheet.map(|s| calc_paper_ppmm(s,paper))
+			},
+			ScaleMode::Marker(size) => marker.map(|m| m / size),
+		}
+	}
+
+	/// Measure the scale of the pieces of one photo and resample them to the common scale if needed.
+	///
+	/// **photo**: photo of the pieces (None if the pieces keep their own crop).
+	/// **sheet**: size of the sheet in pixels if the perspective was corrected.
+	/// **marker**: side of the marker in pixels if found.
+	pub fn normalize_pieces(self: &mut Self,pieces: &PieceVec,photo: Option<&RgbaImage>,sheet: Option<(u32,u32)>,marker: Option<f32>,model: &BackgroundModel) {
+		if let Some(measure) = self.measure(pieces,sheet,marker) {
+			let factor = self.calc_factor(measure);
+			if (factor - 1.0).abs() > common::SCALE_MIN_CHANGE {
+				println!("Resample the pieces by {}",factor);
+				for pp in pieces.iter() {
+					let resampled = resample_piece(&pp.read().unwrap(),photo,factor,model);
+					*pp.write().unwrap() = resampled;
+				}
+			}
+		}
+	}
+
+	/// Compute the resize factor to reach the common scale, the first measured photo gives
+	/// the scale if no target was requested.
+	pub fn calc_factor(self: &mut Self,measure: f32) -> f32 {
+		let target = *self.target.get_or_insert(measure);
+		let factor = target / measure;
+		println!("Scale {} => target {}, factor {}",measure,target,factor);
+		factor
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use image::{Rgba,GrayImage,Luma};
+	use scale::*;
+
+	#[test]
+	fn paper_scale() {
+		assert_eq!(parse_paper_size("A4"),Some((210.0,297.0)));
+		assert_eq!(parse_paper_size("100x50"),Some((100.0,50.0)));
+		assert_eq!(parse_paper_size("100"),None);
+		let ppmm = calc_paper_ppmm((1485,1050),(210.0,297.0));
+		assert!((ppmm - 5.0).abs() < 0.01);
+
+		let mut normalizer = ScaleNormalizer::new(ScaleMode::Median,0.0);
+		assert_eq!(normalizer.calc_factor(100.0),1.0);
+		assert_eq!(normalizer.calc_factor(125.0),0.8);
+	}
+
+	#[test]
+	fn resample_one_piece() {
+		//red square of 20x20 on a white photo
+		let white = Rgba([255,255,255,255]);
+		let model = BackgroundModel{color:white,tolerance:10,ambiguous:0.0};
+		let mut photo = RgbaImage::from_pixel(60,40,white);
+		for y in 10..30 {
+			for x in 20..40 {
+				photo.put_pixel(x,y,Rgba([255,0,0,255]));
+			}
+		}
+		let piece = Piece::new(&photo,&white,(20,10,20,20),3);
+
+		//half size, the position stays in the photo
+		let small = resample_piece(&piece,Some(&photo),0.5,&model);
+		assert_eq!(small.id,3);
+		assert_eq!(small.position,(20,10,20,20));
+		assert_eq!(small.source.as_ref().unwrap().dimensions(),(10,10));
+		assert_eq!(calc_area(&small.mask),100);
+
+		//only keep the region of a split piece
+		let mut region = GrayImage::new(20,20);
+		for y in 0..20 {
+			for x in 0..10 {
+				region.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+		let split = Piece::new_with_region(&photo,&white,(20,10,20,20),Some(region),0);
+		let small = resample_piece(&split,Some(&photo),0.5,&model);
+		assert!(small.region.is_none());
+		assert_eq!(calc_area(&small.mask),50);
+	}
+}
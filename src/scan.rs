@@ -20,8 +20,12 @@ use step7_quality;
 pub fn scan_piece(p: &mut piece::Piece, dump: i32) {
 	//rotate
 	println!("Rotate {:?}",p.id);
-	p.mask = step3_rotate::do_rotate_gray(& p.mask,p.angle);
-	p.image = step3_rotate::do_rotate_rgba(& p.image,p.angle);
+	let frame = step3_rotate::calc_rotated_frame(&p.mask,p.angle);
+	p.mask = step3_rotate::do_rotate_gray(& p.mask,&frame);
+	p.image = step3_rotate::do_rotate_rgba(& p.image,&frame);
+	if let Some(ref mut overlay) = p.overlay {
+		*overlay = step3_rotate::do_rotate_gray(overlay,&frame);
+	}
 
 	//save
 	if dump == 0 || dump == 3 {
@@ -30,7 +34,11 @@ pub fn scan_piece(p: &mut piece::Piece, dump: i32) {
 
 	//remove bumps
 	println!("Remove bumps");
-	p.side_infos = step4_bump::remove_bumps(&mut p.mask);
+	let (side_infos,bumps) = step4_bump::remove_bumps(&p.mask);
+	p.side_infos = side_infos;
+	if let Some(ref mut overlay) = p.overlay {
+		step4_bump::draw_bumps(overlay,&bumps);
+	}
 
 	//save
 	if dump == 0 || dump == 4 {
@@ -40,7 +48,9 @@ pub fn scan_piece(p: &mut piece::Piece, dump: i32) {
 	//extract points
 	println!("Extract corners");
 	p.points = step5_corners::extract_piece_points(&p.mask,&p.side_infos);
-	step5_corners::draw_corners(&mut p.mask,&p.points);
+	if let Some(ref mut overlay) = p.overlay {
+		step5_corners::draw_corners(overlay,&p.mask,&p.points);
+	}
 
 	//save
 	if dump == 0 || dump == 5 {
@@ -49,8 +59,10 @@ pub fn scan_piece(p: &mut piece::Piece, dump: i32) {
 
 	//extract points
 	println!("Extract holes/bump points");
-	step6_hbpoints::extract_piece_points(&p.mask,&mut p.points,&p.side_infos);
-	step6_hbpoints::draw_corners(&mut p.mask,&p.points);
+	p.tabs = step6_hbpoints::extract_piece_points(&p.mask,&bumps,&mut p.points,&mut p.side_infos);
+	if let Some(ref mut overlay) = p.overlay {
+		step6_hbpoints::draw_corners(overlay,&p.mask,&p.points);
+	}
 
 	//save
 	if dump == 0 || dump == 6 {
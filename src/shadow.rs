@@ -0,0 +1,142 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// With a side light the pieces cast a dark band on the background which is then considered
+/// as part of the piece. A shadow has the same hue than the background, is only darker, varies
+/// smoothly and is attached to the background. We follow those pixels from the background up
+/// to a maximal width and repaint them with the background color, stopping on sharp steps
+/// which are the real border of the piece. A second photo taken with another light can be
+/// given, the shadows move but not the pieces.
+
+//load external
+extern crate image;
+
+//std
+use std::collections::VecDeque;
+
+//extern
+use image::{RgbaImage,Rgba};
+
+//internal
+use step0_perspective::get_luminance;
+use common;
+
+/// Check if the pixel looks like the background in the shadow : darker with the same chromaticity.
+fn is_shadow_like(p: &Rgba<u8>,back: &Rgba<u8>) -> bool {
+	//luminance
+	let lum = get_luminance(p) as f32;
+	let lum_back = (get_luminance(back) as f32).max(1.0);
+	let ratio = lum / lum_back;
+	if ratio < common::SHADOW_MIN_RATIO || ratio >= 1.0 {
+		return false;
+	}
+
+	//chromaticity
+	let sum = (p.data[0] as f32 + p.data[1] as f32 + p.data[2] as f32).max(1.0);
+	let sum_back = (back.data[0] as f32 + back.data[1] as f32 + back.data[2] as f32).max(1.0);
+	let dist = (0..3).map(|c| (p.data[c] as f32 / sum - back.data[c] as f32 / sum_back).abs()).fold(0.0,f32::max);
+	dist < common::SHADOW_MAX_CHROMA
+}
+
+/// Check if the pixel of the guide photo is background or shadow, so it is not a piece.
+fn is_guide_back(p: &Rgba<u8>,back: &Rgba<u8>) -> bool {
+	p == back || is_shadow_like(p,back) || (0..3).all(|c| (p.data[c] as i32 - back.data[c] as i32).abs() <= common::SHADOW_GUIDE_TOLERANCE)
+}
+
+/// Repaint the shadows with the background color and return the number of pixels changed.
+///
+/// **img**: photo to clean.
+/// **back**: background color.
+/// **guide**: optional photo of the same scene with another light.
+pub fn remove_shadows(img: &mut RgbaImage,back: &Rgba<u8>,guide: Option<&RgbaImage>) -> u32 {
+	//vars
+	let (w,h) = img.dimensions();
+	let mut dist = vec![u32::max_value();(w * h) as usize];
+	let mut todo = VecDeque::new();
+	let guide_back = guide.map(|g| *g.get_pixel(0,0));
+
+	//start from the background
+	for (x,y,p) in img.enumerate_pixels() {
+		if p == back {
+			dist[(y * w + x) as usize] = 0;
+			todo.push_back((x,y));
+		}
+	}
+
+	//grow into the shadows
+	let mut changed = vec!();
+	while let Some((x,y)) = todo.pop_front() {
+		let d = dist[(y * w + x) as usize];
+		if d >= common::SHADOW_MAX_WIDTH {
+			continue;
+		}
+		let lum = get_luminance(img.get_pixel(x,y)) as i32;
+		for dy in -1i64..2 {
+			for dx in -1i64..2 {
+				let (nx,ny) = (x as i64 + dx,y as i64 + dy);
+				if nx < 0 || ny < 0 || nx >= w as i64 || ny >= h as i64 {
+					continue;
+				}
+				let (nx,ny) = (nx as u32,ny as u32);
+				let id = (ny * w + nx) as usize;
+				if dist[id] != u32::max_value() {
+					continue;
+				}
+
+				//check if shadow and not a sharp border
+				let p = img.get_pixel(nx,ny);
+				let step = (lum - get_luminance(p) as i32).abs();
+				let mut ok = is_shadow_like(p,back) && step <= common::SHADOW_MAX_STEP;
+				if let (Some(g),Some(gb)) = (guide,guide_back) {
+					ok = ok && is_guide_back(g.get_pixel(nx,ny),&gb);
+				}
+
+				if ok {
+					dist[id] = d + 1;
+					todo.push_back((nx,ny));
+					changed.push((nx,ny));
+				}
+			}
+		}
+	}
+
+	//repaint
+	for &(x,y) in changed.iter() {
+		img.put_pixel(x,y,*back);
+	}
+
+	println!("Shadow pixels removed : {}",changed.len());
+	changed.len() as u32
+}
+
+#[cfg(test)]
+mod test {
+	use image::{RgbaImage,Rgba};
+	use shadow::*;
+
+	#[test]
+	fn remove_shadow_keep_piece() {
+		//white back, dark grey piece with a smooth grey shadow on the right
+		let back = Rgba([240,240,240,255]);
+		let mut img = RgbaImage::from_pixel(60,40,back);
+		for y in 10..30 {
+			for x in 10..30 {
+				img.put_pixel(x,y,Rgba([60,60,60,255]));
+			}
+			for x in 30..36 {
+				let v = 180 + 8 * (x - 30) as u8;
+				img.put_pixel(x,y,Rgba([v,v,v,255]));
+			}
+		}
+
+		let cnt = remove_shadows(&mut img,&back,None);
+		assert_eq!(cnt,20*6);
+		assert_eq!(*img.get_pixel(29,20),Rgba([60,60,60,255]));
+		assert_eq!(*img.get_pixel(30,20),back);
+	}
+}
@@ -0,0 +1,282 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// If the photo is not taken exactly from the top the pieces become trapezoids. We search the
+/// four corners of the background sheet (or get them from the command line), compute the
+/// homography to a rectangle and warp the photo before searching the pieces.
+
+//load external
+extern crate image;
+
+//std
+use std::f64;
+use std::collections::VecDeque;
+
+//extern
+use image::{RgbaImage,Rgba};
+
+//internal
+use common;
+
+/// Corners of the sheet in clockwise order from the top left one.
+pub type SheetCorners = [(f32,f32);4];
+
+/// Parse the corners given as X,Y;X,Y;X,Y;X,Y (top left, top right, bottom right, bottom left).
+pub fn parse_corners(value: &str) -> Option<SheetCorners> {
+	let mut ret = [(0.0,0.0);4];
+	let points: Vec<&str> = value.split(';').collect();
+	if points.len() != 4 {
+		return None;
+	}
+	for (i,p) in points.iter().enumerate() {
+		let coords: Vec<&str> = p.split(',').collect();
+		if coords.len() != 2 {
+			return None;
+		}
+		match (coords[0].trim().parse::<f32>(),coords[1].trim().parse::<f32>()) {
+			(Ok(x),Ok(y)) => ret[i] = (x,y),
+			_ => return None,
+		}
+	}
+	Some(ret)
+}
+
+pub fn get_luminance(p: &Rgba<u8>) -> u8 {
+	((p.data[0] as u32 * 299 + p.data[1] as u32 * 587 + p.data[2] as u32 * 114) / 1000) as u8
+}
+
+/// Compute the threshold separating the sheet from the table with the Otsu method.
+pub fn calc_otsu_threshold(img: &RgbaImage) -> u8 {
+	//histogram
+	let mut hist = [0u64;256];
+	for p in img.pixels() {
+		hist[get_luminance(p) as usize] += 1;
+	}
+
+	//search threshold maximizing the variance between the two classes
+	let total: u64 = hist.iter().sum();
+	let sum: f64 = hist.iter().enumerate().map(|(i,n)| i as f64 * *n as f64).sum();
+	let mut sum_back = 0.0;
+	let mut cnt_back = 0u64;
+	let mut best = 0.0;
+	let mut ret = 0;
+	for t in 0..256 {
+		cnt_back += hist[t];
+		if cnt_back == 0 || cnt_back == total {
+			continue;
+		}
+		sum_back += t as f64 * hist[t] as f64;
+		let cnt_fore = (total - cnt_back) as f64;
+		let mean_back = sum_back / cnt_back as f64;
+		let mean_fore = (sum - sum_back) / cnt_fore;
+		let var = cnt_back as f64 * cnt_fore * (mean_back - mean_fore) * (mean_back - mean_fore);
+		if var > best {
+			best = var;
+			ret = t as u8;
+		}
+	}
+
+	ret
+}
+
+/// Search the corners of the sheet as the extreme points of the largest bright region.
+/// Return None if the sheet is not found or if it already cover the full photo.
+pub fn detect_sheet_corners(img: &RgbaImage) -> Option<SheetCorners> {
+	//vars
+	let (w,h) = img.dimensions();
+	let threshold = calc_otsu_threshold(img);
+	let mut seen = vec![false;(w * h) as usize];
+	let mut best_size = 0;
+	let mut best = [(0.0,0.0);4];
+
+	//loop on all bright regions
+	for start in 0..(w * h) {
+		if seen[start as usize] || get_luminance(img.get_pixel(start % w,start / w)) <= threshold {
+			continue;
+		}
+
+		//fill the region tracking the extreme points on the diagonals
+		let mut size = 0;
+		let mut extremes = [(start % w,start / w);4];
+		let mut todo = VecDeque::new();
+		seen[start as usize] = true;
+		todo.push_back((start % w,start / w));
+		while let Some((x,y)) = todo.pop_front() {
+			size += 1;
+			let (xi,yi) = (x as i64,y as i64);
+			if xi + yi < (extremes[0].0 + extremes[0].1) as i64 {extremes[0] = (x,y);}
+			if xi - yi > extremes[1].0 as i64 - extremes[1].1 as i64 {extremes[1] = (x,y);}
+			if xi + yi > (extremes[2].0 + extremes[2].1) as i64 {extremes[2] = (x,y);}
+			if yi - xi > extremes[3].1 as i64 - extremes[3].0 as i64 {extremes[3] = (x,y);}
+			for &(dx,dy) in [(-1i64,0i64),(1,0),(0,-1),(0,1)].iter() {
+				let (nx,ny) = (xi + dx,yi + dy);
+				if nx >= 0 && ny >= 0 && nx < w as i64 && ny < h as i64 {
+					let id = (ny as u32 * w + nx as u32) as usize;
+					if !seen[id] && get_luminance(img.get_pixel(nx as u32,ny as u32)) > threshold {
+						seen[id] = true;
+						todo.push_back((nx as u32,ny as u32));
+					}
+				}
+			}
+		}
+
+		//keep largest
+		if size > best_size {
+			best_size = size;
+			for i in 0..4 {
+				best[i] = (extremes[i].0 as f32,extremes[i].1 as f32);
+			}
+		}
+	}
+
+	//check we found a real sheet
+	if (best_size as f32) < common::PERSPECTIVE_MIN_SHEET_RATIO * (w * h) as f32 {
+		println!("Sheet not found, keep photo as it is");
+		return None;
+	}
+
+	//check if we need to do something
+	let image_corners = [(0.0,0.0),(w as f32 - 1.0,0.0),(w as f32 - 1.0,h as f32 - 1.0),(0.0,h as f32 - 1.0)];
+	let max_shift = best.iter().zip(image_corners.iter())
+		.map(|(a,b)| ((a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)).sqrt())
+		.fold(0.0,f32::max);
+	if max_shift < common::PERSPECTIVE_MIN_SHIFT {
+		println!("Sheet cover the full photo, keep photo as it is");
+		return None;
+	}
+
+	Some(best)
+}
+
+/// Compute the homography mapping the 4 src points on the 4 dst points (direct linear transform
+/// solved with a gauss pivot). The matrix is returned line by line with h[8] = 1.
+pub fn compute_homography(src: &SheetCorners,dst: &SheetCorners) -> Option<[f64;9]> {
+	//build system
+	let mut m = [[0.0f64;9];8];
+	for i in 0..4 {
+		let (x,y) = (src[i].0 as f64,src[i].1 as f64);
+		let (u,v) = (dst[i].0 as f64,dst[i].1 as f64);
+		m[2*i] = [x,y,1.0,0.0,0.0,0.0,-u*x,-u*y,u];
+		m[2*i+1] = [0.0,0.0,0.0,x,y,1.0,-v*x,-v*y,v];
+	}
+
+	//gauss pivot
+	for col in 0..8 {
+		//search pivot
+		let mut pivot = col;
+		for row in (col+1)..8 {
+			if m[row][col].abs() > m[pivot][col].abs() {
+				pivot = row;
+			}
+		}
+		if m[pivot][col].abs() < 1e-12 {
+			return None;
+		}
+		m.swap(col,pivot);
+
+		//eliminate
+		for row in 0..8 {
+			if row != col {
+				let f = m[row][col] / m[col][col];
+				for k in col..9 {
+					m[row][k] -= f * m[col][k];
+				}
+			}
+		}
+	}
+
+	//extract
+	let mut ret = [1.0f64;9];
+	for i in 0..8 {
+		ret[i] = m[i][8] / m[i][i];
+	}
+	Some(ret)
+}
+
+/// Apply the homography on a point.
+pub fn apply_homography(hom: &[f64;9],p: (f64,f64)) -> (f64,f64) {
+	let z = hom[6] * p.0 + hom[7] * p.1 + hom[8];
+	(
+		(hom[0] * p.0 + hom[1] * p.1 + hom[2]) / z,
+		(hom[3] * p.0 + hom[4] * p.1 + hom[5]) / z,
+	)
+}
+
+/// Get the pixel at a non integer position with bilinear interpolation (clamped on the borders).
+pub fn get_pixel_bilinear(img: &RgbaImage,p: (f64,f64)) -> Rgba<u8> {
+	let (w,h) = img.dimensions();
+	let x = p.0.max(0.0).min(w as f64 - 1.0);
+	let y = p.1.max(0.0).min(h as f64 - 1.0);
+	let (x0,y0) = (x.floor() as u32,y.floor() as u32);
+	let (x1,y1) = ((x0 + 1).min(w - 1),(y0 + 1).min(h - 1));
+	let (fx,fy) = (x - x0 as f64,y - y0 as f64);
+
+	let mut ret = [0u8;4];
+	for c in 0..4 {
+		let top = img.get_pixel(x0,y0).data[c] as f64 * (1.0 - fx) + img.get_pixel(x1,y0).data[c] as f64 * fx;
+		let bottom = img.get_pixel(x0,y1).data[c] as f64 * (1.0 - fx) + img.get_pixel(x1,y1).data[c] as f64 * fx;
+		ret[c] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+	}
+	Rgba(ret)
+}
+
+fn calc_dist(a: (f32,f32),b: (f32,f32)) -> f32 {
+	((a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)).sqrt()
+}
+
+/// Warp the sheet to a rectangle keeping its largest sides. We remove a small margin
+/// on the borders to not keep the edge of the sheet.
+pub fn warp_sheet(img: &RgbaImage,corners: &SheetCorners) -> RgbaImage {
+	//target size
+	let w = calc_dist(corners[0],corners[1]).max(calc_dist(corners[3],corners[2])).round();
+	let h = calc_dist(corners[0],corners[3]).max(calc_dist(corners[1],corners[2])).round();
+	let rect = [(0.0,0.0),(w,0.0),(w,h),(0.0,h)];
+
+	//we loop on the target pixels so need the inverse mapping
+	let hom = compute_homography(&rect,corners).expect("Invalid sheet corners, they should not be aligned !");
+
+	//warp
+	let margin = common::PERSPECTIVE_SHEET_MARGIN;
+	let out_w = (w as u32).saturating_sub(2 * margin).max(1);
+	let out_h = (h as u32).saturating_sub(2 * margin).max(1);
+	println!("Warp sheet {:?} => {}x{}",corners,out_w,out_h);
+	RgbaImage::from_fn(out_w,out_h,|x,y| {
+		let p = apply_homography(&hom,((x + margin) as f64 + 0.5,(y + margin) as f64 + 0.5));
+		get_pixel_bilinear(img,(p.0 - 0.5,p.1 - 0.5))
+	})
+}
+
+/// Detect the sheet if no corners are given and warp the photo. Return None if there is nothing to do,
+/// else the warped photo and the corners used so other photos of the same scene can get the same warp.
+pub fn correct_perspective(img: &RgbaImage,corners: Option<SheetCorners>) -> Option<(RgbaImage,SheetCorners)> {
+	let corners = match corners {
+		Some(c) => Some(c),
+		None => detect_sheet_corners(img),
+	};
+	corners.map(|c| (warp_sheet(img,&c),c))
+}
+
+#[cfg(test)]
+mod test {
+	use step0_perspective::*;
+
+	#[test]
+	fn homography_maps_corners() {
+		let src = [(10.0,20.0),(300.0,5.0),(320.0,250.0),(0.0,230.0)];
+		let dst = [(0.0,0.0),(310.0,0.0),(310.0,240.0),(0.0,240.0)];
+		let hom = compute_homography(&src,&dst).unwrap();
+		for i in 0..4 {
+			let (x,y) = apply_homography(&hom,(src[i].0 as f64,src[i].1 as f64));
+			assert!((x - dst[i].0 as f64).abs() < 1e-6);
+			assert!((y - dst[i].1 as f64).abs() < 1e-6);
+		}
+
+		assert!(parse_corners("10,20;300,5;320,250;0,230") == Some(src));
+		assert!(parse_corners("10,20;300,5") == None);
+	}
+}
@@ -26,10 +26,11 @@ use image::{GrayImage,RgbaImage,imageops,Luma,Rgba};
 use scoped_pool::Pool;
 
 //internal
-use piece::{PieceFace,PieceVec,PieceMatch,Piece};
+use piece::{PieceFace,PieceVec,PieceMatch,Piece,PieceTab};
 use std::cmp::Ordering;
-use step5_corners;
+use bitmask::BitMask;
 use common;
+use step6_hbpoints;
 
 fn move_face(face: &PieceFace,dx:f32,dy:f32) -> PieceFace {
 	PieceFace {
@@ -104,27 +105,33 @@ fn check_quick_face_distance(face1: &PieceFace,face2: &PieceFace) -> (f32,f32,Pi
 	(ret,angle,face2)
 }
 
-fn  cacl_rotate(face: usize, want_on: usize) -> usize {
-	((want_on + 4) - face) % 4
+/// Difference between the shapes of the main bumps and holes facing each other (the sides are
+/// walked in opposite directions so their offsets are opposed), plus a penalty for each tab
+/// found on only one side. 0 if the tabs are not known on both sides.
+fn calc_tab_distance(tabs1: &Vec<PieceTab>,tabs2: &Vec<PieceTab>) -> f32 {
+	match (step6_hbpoints::get_main_tab(tabs1),step6_hbpoints::get_main_tab(tabs2)) {
+		(Some(t1),Some(t2)) => {
+			let count_diff = (tabs1.len() as i32 - tabs2.len() as i32).abs() as f32;
+			(t1.neck_width - t2.neck_width).abs()
+				+ (t1.head_radius - t2.head_radius).abs()
+				+ (t1.depth - t2.depth).abs()
+				+ (t1.offset + t2.offset).abs()
+				+ count_diff * common::MATCH_TAB_COUNT_PENALTY
+		},
+		_ => 0.0,
+	}
 }
 
-//TODO make generic
-fn get_rotated_mask(img: &GrayImage,face: usize,want_on:usize) -> GrayImage {
-	//calc roation
-	let rot = cacl_rotate(face,want_on);
-
-	//rotate
-	let rotated;
-	match rot {
-		0 => rotated = img.clone(),
-		1 => rotated = imageops::rotate90(img),
-		2 => rotated = imageops::rotate180(img),
-		3 => rotated = imageops::rotate270(img),
-		_ => panic!("Invalid value should be 0,1,2 or 3 !"),
-	}
+/// Check if the two sides have to be compared, when we are not sure of the type of one of the
+/// sides we test it against both bumps and holes.
+fn sides_can_match(p1: &Piece,fid1: usize,p2: &Piece,fid2: usize) -> bool {
+	let unsure = p1.side_infos.confidence[fid1] < common::MATCH_MIN_SIDE_CONFIDENCE
+		|| p2.side_infos.confidence[fid2] < common::MATCH_MIN_SIDE_CONFIDENCE;
+	unsure || p1.sides_compatible(fid1,p2,fid2)
+}
 
-	//ret
-	rotated
+fn  cacl_rotate(face: usize, want_on: usize) -> usize {
+	((want_on + 4) - face) % 4
 }
 
 //TODO make generic
@@ -235,22 +242,6 @@ fn add_colored(out: &mut RgbaImage,input: &RgbaImage,mask: &GrayImage,pos: (u32,
 	}
 }
 
-fn add_mask(out: &mut GrayImage,mask: &GrayImage,pos: (u32,u32)) {
-	let (w,h) = mask.dimensions();
-	let (x0,y0) = pos;
-	for y in 0..h {
-		for x in 0..w {
-			let color;
-			{
-				let m = mask.get_pixel(x,y);
-				let o = out.get_pixel(x+x0,y+y0);
-				color = Luma([m.data[0] + o.data[0]]);
-			}
-			out.put_pixel(x+x0,y+y0,color);
-		}
-	}
-}
-
 fn calc_right_mask_pos(left_face: &PieceFace,right_face: &PieceFace,left_pos:(u32,u32),offset:(i32,i32)) -> (u32,u32) {
 	//calc angle
 	//println!("FACE {:?} {:?}",left_face,right_face);
@@ -262,33 +253,105 @@ fn calc_right_mask_pos(left_face: &PieceFace,right_face: &PieceFace,left_pos:(u3
 	(x as u32,y as u32)
 }
 
-fn count_superp(img: &GrayImage,rect:(u32,u32,u32,u32)) -> u32 {
-	let (x0,y0,x1,y1) = rect;
-	let c = Luma([common::MASK_PIECE_PIXEL * 2]);
-	let back = Luma([common::MASK_BACKGROUND]);
-	let mut cnt = 0;
-	for y in y0..y1 {
-		for x in x0..x1 {
-			let color = img.get_pixel(x,y);
-			if *color == back {
-				cnt += 1;
+/// Masks of a piece turned to put one face on the right (left piece of the match) or on the
+/// left (right piece of the match).
+struct FaceMask {
+	/// Pixels of the piece.
+	piece: BitMask,
+	/// Pixels of the piece and background pixels outside of the face, which are not counted as
+	/// gaps.
+	covered: BitMask,
+	/// The face in the turned mask.
+	face: PieceFace,
+	/// Surrounding rect of the piece in the turned mask.
+	rect: (u32,u32,u32,u32),
+}
+
+/// Turned masks of the 4 faces of a piece, computed once before matching all the pairs.
+struct PieceMasks {
+	left: Vec<FaceMask>,
+	right: Vec<FaceMask>,
+}
+
+fn hide_external_black_pixel(mask: &BitMask,face: &PieceFace) -> BitMask {
+	//vars
+	let (w,h) = mask.dimensions();
+	let mut ignore = BitMask::new(w,h);
+
+	//top
+	let xmax = face.top.0 as u32;
+	assert!(xmax < w);
+	for x in 0..xmax {
+		for y in 0..h {
+			if mask.get(x,y) {
+				break;
 			}
-			if *color == c {
-				cnt += 1;
+			ignore.set(x,y);
+		}
+	}
+
+	//bottom
+	let xmax = face.bottom.0 as u32;
+	assert!(xmax < w);
+	for x in 0..xmax {
+		for y in 0..h {
+			if mask.get(x,h-y-1) {
+				break;
 			}
+			ignore.set(x,h-y-1);
 		}
 	}
 
-	cnt
+	ignore
+}
+
+fn build_face_mask(piece: &Piece,mask: &BitMask,fid: usize,as_left: bool) -> FaceMask {
+	//turn to have the face on the right to hide the pixels outside of it
+	let size = piece.mask.dimensions();
+	let turned = mask.rotate(cacl_rotate(fid,1));
+	let face = rotate_face_center(size,&piece.faces[fid],fid,1);
+	let ignore = hide_external_black_pixel(&turned,&face);
+
+	//turn back the right piece
+	let (turned,ignore,face) = if as_left {
+		(turned,ignore,face)
+	} else {
+		(turned.rotate(2),ignore.rotate(2),rotate_face_center(size,&piece.faces[fid],fid,3))
+	};
+
+	FaceMask {
+		rect: turned.bounding_rect(),
+		covered: turned.or(&ignore),
+		piece: turned,
+		face: face,
+	}
+}
+
+fn build_piece_masks(piece: &Piece) -> PieceMasks {
+	let mask = BitMask::from_gray(&piece.mask,common::MASK_PIECE_PIXEL);
+	PieceMasks {
+		left: (0..4).map(|fid| build_face_mask(piece,&mask,fid,true)).collect(),
+		right: (0..4).map(|fid| build_face_mask(piece,&mask,fid,false)).collect(),
+	}
+}
+
+fn calc_left_mask_pos(left: &FaceMask,size: u32) -> (u32,u32) {
+	let (lw,lh) = left.piece.dimensions();
+	(size/2 - lw,size/2-lh/2)
 }
 
-fn calc_face_mask_dist(left: &Piece, fid_left: usize,right: &Piece, fid_right: usize,id: u32,dump: i32) -> f32 {
+fn calc_face_mask_dist(left: &Piece, fid_left: usize,right: &Piece, fid_right: usize,left_mask: &FaceMask,right_mask: &FaceMask,id: u32,dump: i32) -> f32 {
+	//compute size
+	let (lw,lh) = left.mask.dimensions();
+	let (rw,rh) = right.mask.dimensions();
+	let size = lw.max(lh).max(rw).max(rh) * 2;
+
 	let mut min = f32::MAX;
 	//let base = format!("tmp-{}:{}-{}:{}.txt",left.id,fid_left,right.id,fid_right);
     //let mut file = File::create(base).unwrap();
 	for y in -common::MATCH_MASK_OFFET..common::MATCH_MASK_OFFET {
 		for x in -common::MATCH_MASK_OFFET..common::MATCH_MASK_OFFET {
-			let dist = calc_face_mask_dist_offset(left,fid_left,right,fid_right,id,dump,(x*common::MATCH_MASK_OFFSET_STEP,y*common::MATCH_MASK_OFFSET_STEP));
+			let dist = calc_face_mask_dist_offset(left_mask,right_mask,size,(x*common::MATCH_MASK_OFFSET_STEP,y*common::MATCH_MASK_OFFSET_STEP));
 			//file.write_fmt(format_args!("{} {} {}\n",x,y,dist)).unwrap();
 			if dist < min {
 				min = dist;
@@ -296,6 +359,11 @@ fn calc_face_mask_dist(left: &Piece, fid_left: usize,right: &Piece, fid_right: u
 		}
 	}
 
+	//save into file
+	if dump == -10 { //&& (left.id == 4 && fid_left == 1 || right.id == 4 && fid_right == 1) {
+		save_face_mask_match(left,fid_left,right,fid_right,left_mask,right_mask,size,id);
+	}
+
 	min
 }
 
@@ -303,149 +371,78 @@ fn move_rect(rect:(u32,u32,u32,u32),pos:(u32,u32)) -> (u32,u32,u32,u32) {
 	(rect.0 + pos.0,rect.1 + pos.1, rect.2 + pos.0, rect.3 + pos.1)
 }
 
-fn get_intersection(left: &GrayImage,left_face: &PieceFace,pleft:(u32,u32),right: &GrayImage,right_face: &PieceFace,pright:(u32,u32)) -> (u32,u32,u32,u32) {
-	//extract surrounding
-	let rect_left = step5_corners::extract_surrounding_rect(left);
-	let rect_right = step5_corners::extract_surrounding_rect(right);
-
+fn get_intersection(left: &FaceMask,pleft:(u32,u32),right: &FaceMask,pright:(u32,u32)) -> (u32,u32,u32,u32) {
 	//move
-	let rect_left = move_rect(rect_left,pleft);
-	let rect_right = move_rect(rect_right,pright);
-	let left_face = move_face(left_face,pleft.0 as f32,pleft.1 as f32);
-	let right_face = move_face(right_face,pright.0 as f32,pright.1 as f32);
+	let rect_left = move_rect(left.rect,pleft);
+	let rect_right = move_rect(right.rect,pright);
+	let left_face = move_face(&left.face,pleft.0 as f32,pleft.1 as f32);
+	let right_face = move_face(&right.face,pright.0 as f32,pright.1 as f32);
 
 	//intersect
 	let xmin = rect_right.0.min(left_face.top.0 as u32).min(left_face.bottom.0 as u32);
-	let xmax = rect_left.2.max(right_face.top.0 as u32).max(right_face.bottom.0 as u32);;
+	let xmax = rect_left.2.max(right_face.top.0 as u32).max(right_face.bottom.0 as u32);
 	let ymin = left_face.top.1.min(right_face.top.1) as u32;
 	let ymax = left_face.bottom.1.max(right_face.bottom.1) as u32;
 
 	(xmin,ymin,xmax+1,ymax+1)
 }
 
-fn hide_external_black_pixel(mask: &mut GrayImage,face: &PieceFace) {
-	//vars
-	let (w,h) = mask.dimensions();
-	let back = Luma([common::MASK_BACKGROUND]);
-	let ignore = Luma([common::MASK_IGNORE_SUPERP]);
+/// Count the pixels of the intersection of the two faces which are covered by both pieces or
+/// by none of them. The count is done in the frame of the left masks which stay in place, only
+/// the right ones are read moved for the offset.
+fn calc_face_mask_dist_offset(left: &FaceMask,right: &FaceMask,size: u32,offset:(i32,i32)) -> f32 {
+	//calculate piece position
+	let left_pos = calc_left_mask_pos(left,size);
+	let right_pos = calc_right_mask_pos(&left.face,&right.face,left_pos,offset);
+
+	//move into the frame of the left masks
+	let (lx,ly) = (left_pos.0 as i32,left_pos.1 as i32);
+	let rect = get_intersection(left,left_pos,right,right_pos);
+	let rect = (rect.0 as i32 - lx,rect.1 as i32 - ly,rect.2 as i32 - lx,rect.3 as i32 - ly);
+	let (dx,dy) = (right_pos.0 as i32 - lx,right_pos.1 as i32 - ly);
+
+	//count
+	let gaps = left.covered.count_shifted(&right.covered,dx,dy,rect,|a,b| !(a | b));
+	let overlap = left.piece.count_shifted(&right.piece,dx,dy,rect,|a,b| a & b);
+	(gaps + overlap) as f32
+}
 
-	//top
-	let xmax = face.top.0 as u32;
-	assert!(xmax < w);
-	for x in 0..xmax {
-		//let y0 = face.top.1 as u32;
-		let y0 = 0;
-		for y in y0..h {
-			if *mask.get_pixel(x,y) == back {
-				mask.put_pixel(x,y,ignore);
-			} else {
-				break;
-			}
-		}
-	}
+/// Save the masks and the colors of the two faces placed without offset for debugging.
+fn save_face_mask_match(left: &Piece, fid_left: usize,right: &Piece, fid_right: usize,left_mask: &FaceMask,right_mask: &FaceMask,size: u32,id: u32) {
+	//positions
+	let left_pos = calc_left_mask_pos(left_mask,size);
+	let right_pos = calc_right_mask_pos(&left_mask.face,&right_mask.face,left_pos,(0,0));
+	let ret = calc_face_mask_dist_offset(left_mask,right_mask,size,(0,0));
 
-	//bottom
-	let xmax = face.bottom.0 as u32;
-	assert!(xmax < w);
-	for x in 0..xmax {
-		//let y0 = h - face.bottom.1 as u32;
-		let y0 = 0;
-		for y in y0..h {
-			if *mask.get_pixel(x,h-y-1) == back {
-				mask.put_pixel(x,h-y-1,ignore);
-			} else {
-				break;
+	//sum the masks
+	let mut img = GrayImage::new(size,size);
+	for &(mask,pos) in [(left_mask,left_pos),(right_mask,right_pos)].iter() {
+		let piece = mask.piece.shift(pos.0 as i32,pos.1 as i32,size,size);
+		let covered = mask.covered.shift(pos.0 as i32,pos.1 as i32,size,size);
+		for (x,y,p) in img.enumerate_pixels_mut() {
+			if piece.get(x,y) {
+				p.data[0] += common::MASK_PIECE_PIXEL;
+			} else if covered.get(x,y) {
+				p.data[0] += common::MASK_IGNORE_SUPERP;
 			}
 		}
 	}
-}
 
-fn calc_face_mask_dist_offset(left: &Piece, fid_left: usize,right: &Piece, fid_right: usize,id: u32,dump: i32,offset:(i32,i32)) -> f32 {
-	//compute size
-	let (lw,lh) = left.mask.dimensions();
-	let (rw,rh) = right.mask.dimensions();
-	let size = lw.max(lh).max(rw).max(rh) * 2;
-
-	//build out image & roate masks
-	let mut img = GrayImage::new(size,size);
+	//colors
 	let mut dbgimg = RgbaImage::new(size,size);
-
 	add_colored(&mut dbgimg,& left.image, &left.mask, (0,0));
 	add_colored(&mut dbgimg,& right.image, &right.mask, (size/2,0));
-
-	//rotate
-	let mut left_mask = get_rotated_mask(&left.mask,fid_left,1);
-	//special trick to use hige_external_black_pixel
-	let mut right_mask = get_rotated_mask(&right.mask,fid_right,1);
-
-	//rotate color
-	let left_img = get_rotated_rgba(&left.image,fid_left,1);
-	let right_img = get_rotated_rgba(&right.image,fid_right,3);
-
-	//prepare points
-	let left_face = rotate_face_center((lw,lh),&left.faces[fid_left],fid_left,1);
-	//special trick to use hige_external_black_pixel
-	let right_face = rotate_face_center((rw,rh),&right.faces[fid_right],fid_right,1);
-
-	//fix mask
-	hide_external_black_pixel(&mut left_mask,&left_face);
-	hide_external_black_pixel(&mut right_mask,&right_face);
-
-	//turn back the rigth piece
-	let right_mask = imageops::rotate180(&right_mask);
-	let right_face = rotate_face_center((rw,rh),&right.faces[fid_right],fid_right,3);
-	
-	//calculate piece position
-	let (lw,lh) = left_mask.dimensions();
-	let left_pos = (size/2 - lw,size/2-lh/2);
-	//println!("LEFT {:?}",left_pos);
-	//let right_pos = (size/2, size/2);
-	let right_pos = calc_right_mask_pos(&left_face,&right_face,left_pos,offset);
-
-	//ignore gray in overlap
-	//ignore_gray_overlap(&mut left_mask,&left_img,(0,0),common::MASK_BACKGROUND);
-	//ignore_gray_overlap(&mut right_mask,&right_img,(0,0)common::MASK_BACKGROUND);
-
-	//draw
-	add_mask(&mut img,&left_mask,left_pos);
-	add_colored(&mut dbgimg,& left_img, &left_mask, left_pos);
-	//step5_corners::draw_point(&mut img,(left_pos.0+left_face.top.0 as u32,left_pos.1+left_face.top.1 as u32));
-	//step5_corners::draw_point(&mut img,(left_pos.0+left_face.bottom.0 as u32,left_pos.1+left_face.bottom.1 as u32));
-	//step5_corners::draw_point(&mut img,(left_pos.0+left_face.middle.0 as u32,left_pos.1+left_face.middle.1 as u32));
-	
-	add_mask(&mut img,&right_mask,right_pos);
-	add_colored(&mut dbgimg,& right_img, &right_mask, right_pos);
-	//step5_corners::draw_point(&mut img,(right_pos.0+right_face.top.0 as u32,right_pos.1+right_face.top.1 as u32));
-	//step5_corners::draw_point(&mut img,(right_pos.0+right_face.middle.0 as u32,right_pos.1+right_face.middle.1 as u32));
-	//step5_corners::draw_point(&mut img,(right_pos.0+right_face.bottom.0 as u32,right_pos.1+right_face.bottom.1 as u32));
-
-	//ignore gray in overlap
-	//ignore_gray_overlap(&mut img,&left_img,left_pos,common::MASK_IGNORE_SUPERP);
-	//ignore_gray_overlap(&mut img,&right_img,right_pos,common::MASK_IGNORE_SUPERP);
-
-	//ret
-	let rect = get_intersection(&left_mask,&left_face,left_pos,&right_mask,&right_face,right_pos);
-	//println!("interect {:?}",rect);
-	let ret = count_superp(&img,rect) as f32;
-
-	/*for y in rect.1..rect.3 {
-		for x in rect.0..rect.2 {
-			dbgimg.put_pixel(x,y,Rgba([0,0,0,255]));
-		}
-	}*/
-
-	//save into file
-	if dump == -10 && offset == (0,0) { //&& (left.id == 4 && fid_left == 1 || right.id == 4 && fid_right == 1) {
-		let fname = format!("step-10-mask-match-{:05}-{}:{}-{}:{}-{}:{}-mask-{}.png",id,left.id,fid_left,right.id,fid_right,offset.0,offset.1,ret);
-		img.save(fname).unwrap();
-		let fname = format!("step-10-mask-match-{:05}-{}:{}-{}:{}-{}:{}-color-{}.png",id,left.id,fid_left,right.id,fid_right,offset.0,offset.1,ret);
-		dbgimg.save(fname).unwrap();
-	}
-
-	ret
+	add_colored(&mut dbgimg,&get_rotated_rgba(&left.image,fid_left,1),&left_mask.piece.to_gray(common::MASK_PIECE_PIXEL),left_pos);
+	add_colored(&mut dbgimg,&get_rotated_rgba(&right.image,fid_right,3),&right_mask.piece.to_gray(common::MASK_PIECE_PIXEL),right_pos);
+
+	//save
+	let fname = format!("step-10-mask-match-{:05}-{}:{}-{}:{}-0:0-mask-{}.png",id,left.id,fid_left,right.id,fid_right,ret);
+	img.save(fname).unwrap();
+	let fname = format!("step-10-mask-match-{:05}-{}:{}-{}:{}-0:0-color-{}.png",id,left.id,fid_left,right.id,fid_right,ret);
+	dbgimg.save(fname).unwrap();
 }
 
-pub fn compute_matching(pool: &Pool,pieces: &mut PieceVec, dump:i32) {
+pub fn compute_matching(pool: &Pool,pieces: &mut PieceVec, keep:usize, dump:i32) {
 	//to extract media dist
 	let mut full_soluce: Vec<(f32,f32,bool,usize,usize,usize,usize)> = vec!();
 	let mut file: Option<File> = None;
@@ -479,9 +476,11 @@ pub fn compute_matching(pool: &Pool,pieces: &mut PieceVec, dump:i32) {
 					let face2 = rotate_face_center(p2.mask.dimensions(),&face2,fid2,3);
 
 					//check if can match then match
-					if face1.mode != face2.mode {
+					if sides_can_match(p1,fid1,p2,fid2) {
 						let (dist1,angle1,f1) = check_quick_face_distance(&face1,&face2);
 						let (dist2,angle2,f2) = check_quick_face_distance_mirrored(&face1,&face2);
+						let tab_dist = calc_tab_distance(&p1.tabs[fid1],&p2.tabs[fid2]);
+						let (dist1,dist2) = (dist1 + tab_dist,dist2 + tab_dist);
 						let dist = dist1.min(dist2);
 						if dist1 < dist2 {
 							full_soluce.push((dist,angle1,false,i1,fid1,i2,fid2));;
@@ -511,6 +510,8 @@ pub fn compute_matching(pool: &Pool,pieces: &mut PieceVec, dump:i32) {
 
 	//apply second step filter
 	let filtered_soluce: Arc<Mutex<Vec<(f32,f32,bool,usize,usize,usize,usize)>>> = Arc::new(Mutex::new(vec!()));
+	let masks: Vec<PieceMasks> = pieces.iter().map(|p| build_piece_masks(&p.read().unwrap())).collect();
+	let masks = &masks;
 	let id = Arc::new(Mutex::new(0));
 	pool.scoped(|scope| {
 		for m in full_soluce {
@@ -529,7 +530,7 @@ pub fn compute_matching(pool: &Pool,pieces: &mut PieceVec, dump:i32) {
 					}
 					let p1 = &pieces[id1].read().unwrap();
 					let p2 = &pieces[id2].read().unwrap();
-					let fdist = calc_face_mask_dist(p1,fid1,p2,fid2,i,dump);
+					let fdist = calc_face_mask_dist(p1,fid1,p2,fid2,&masks[id1].left[fid1],&masks[id2].right[fid2],i,dump);
 					match file2.lock().unwrap().as_mut() {
 						Some(f) => f.write_fmt(format_args!("Match {}:{} <-> {}:{} -> {}\n",id1,fid1,id2,fid2,fdist)).unwrap(),
 						None => {}
@@ -573,11 +574,105 @@ pub fn compute_matching(pool: &Pool,pieces: &mut PieceVec, dump:i32) {
 		//}
 	}
 
-	for p in pieces {
+	for p in pieces.iter() {
 		let mut pp = p.write().unwrap();
 		for f in 0..4 {
 			pp.matches[f].sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Ordering::Equal));
-			pp.matches[f].truncate(1);
+			pp.matches[f].truncate(keep);
+		}
+	}
+
+	//apply the links known from the assembled pieces
+	apply_links(pieces);
+}
+
+/// The sides known to be assembled only match their neighboor and cannot be used by others.
+fn apply_links(pieces: &PieceVec) {
+	//list sides already taken
+	let mut taken = vec!();
+	for (i,p) in pieces.iter().enumerate() {
+		let p = p.read().unwrap();
+		for f in 0..4 {
+			if p.links[f].is_some() {
+				taken.push((i,f));
+			}
+		}
+	}
+
+	//force
+	for p in pieces.iter() {
+		let mut pp = p.write().unwrap();
+		for f in 0..4 {
+			match pp.links[f].clone() {
+				Some(link) => pp.matches[f] = vec!(link),
+				None => pp.matches[f].retain(|m| !taken.contains(&(m.piece,m.side))),
+			}
 		}
 	}
-}
\ No newline at end of file
+}
+
+///Implement unit tests of the module
+#[cfg(test)]
+mod test {
+	use std::sync::{Arc,RwLock};
+	use step10_matching::*;
+	use piece::{PieceSideType,TOP,BOTTOM};
+
+	fn build_tab(mode: PieceSideType,depth: f32,offset: f32) -> PieceTab {
+		PieceTab {
+			mode: mode,
+			neck_pos: 50.0 + offset,
+			neck_width: 12.0,
+			head_center: (50.0 + offset,-depth / 2.0),
+			head_radius: 12.0,
+			depth: depth,
+			offset: offset,
+			fit_error: 0.0,
+			point: (0.0,0.0),
+		}
+	}
+
+	#[test]
+	fn tab_distance() {
+		//the main tabs face each other with opposed offsets
+		let bump = vec!(build_tab(PieceSideType::Bump,30.0,5.0));
+		let hole = vec!(build_tab(PieceSideType::Hole,28.0,-5.0));
+		assert_eq!(calc_tab_distance(&bump,&hole),2.0);
+		assert_eq!(calc_tab_distance(&bump,&Vec::new()),0.0);
+
+		//an extra small tab on one side
+		let holes = vec!(build_tab(PieceSideType::Bump,8.0,-30.0),build_tab(PieceSideType::Hole,28.0,-5.0));
+		assert_eq!(calc_tab_distance(&bump,&holes),2.0 + common::MATCH_TAB_COUNT_PENALTY);
+		assert_eq!(calc_tab_distance(&holes,&bump),2.0 + common::MATCH_TAB_COUNT_PENALTY);
+	}
+
+	#[test]
+	fn side_confidence_matching() {
+		//two pieces with a bump on the top side
+		let white = Rgba([255,255,255,255]);
+		let photo = RgbaImage::from_pixel(60,30,white);
+		let pieces: PieceVec = (0..2).map(|i| Arc::new(RwLock::new(Piece::new(&photo,&white,(i * 30,0,30,30),i)))).collect();
+		for pp in pieces.iter() {
+			let mut p = pp.write().unwrap();
+			p.tabs[TOP] = vec!(build_tab(PieceSideType::Bump,20.0,0.0));
+			p.tabs[BOTTOM] = vec!(build_tab(PieceSideType::Hole,20.0,0.0));
+			p.side_infos.set(TOP,PieceSideType::Bump);
+			p.side_infos.set(BOTTOM,PieceSideType::Hole);
+			p.side_infos.confidence = [0.9;4];
+		}
+
+		//sure of the types : only a bump with a hole
+		{
+			let (p1,p2) = (pieces[0].read().unwrap(),pieces[1].read().unwrap());
+			assert!(!sides_can_match(&p1,TOP,&p2,TOP));
+			assert!(sides_can_match(&p1,TOP,&p2,BOTTOM));
+		}
+
+		//not sure of the type of the second top side : the two bumps are compared
+		pieces[1].write().unwrap().side_infos.confidence[TOP] = common::MATCH_MIN_SIDE_CONFIDENCE / 2.0;
+		let (p1,p2) = (pieces[0].read().unwrap(),pieces[1].read().unwrap());
+		assert!(sides_can_match(&p1,TOP,&p2,TOP));
+		assert!(sides_can_match(&p2,TOP,&p1,TOP));
+		assert!(!sides_can_match(&p1,BOTTOM,&p2,BOTTOM));
+	}
+}
@@ -16,13 +16,16 @@ extern crate image;
 use std::usize;
 use std::ops::Add;
 use std::fmt;
+use std::cmp::Ordering;
 
 //extern
 use ndarray::{Array2,Axis};
 use num_traits::identities::Zero;
 
 //local
-use piece::{PieceVec,TOP,RIGHT,LEFT,BOTTOM,PieceMatchVec};
+use piece::{PieceVec,TOP,RIGHT,LEFT,BOTTOM,PieceMatch,PieceMatchVec};
+use reference::ReferencePrior;
+use common;
 
 //consts
 pub const NONE: usize = usize::MAX;
@@ -87,10 +90,67 @@ type SoluceVec = Vec<Soluce>;
 //to track final solutions
 struct SoluceProposal {
     list: SoluceVec,
+    costs: Vec<f32>,
     nb: u32,
 	connectivity: u32,
 }
 
+/// Placement prior from the reference picture, the anchor tell us which cell of the
+/// solution correspond to which tile of the reference.
+struct Guide<'a> {
+	prior: Option<&'a ReferencePrior>,
+	anchor_cell: (usize,usize),
+	anchor_tile: (usize,usize),
+	scale: f32,
+}
+
+impl<'a> Guide<'a> {
+	/// Cost given by the reference to put the piece at the given position (0 if no reference)
+	fn cost(self: &Self,piece: usize,pos: (usize,usize),rotation: usize) -> f32 {
+		match self.prior {
+			Some(prior) => {
+				let col = pos.0 as i64 - self.anchor_cell.0 as i64 + self.anchor_tile.0 as i64;
+				let row = pos.1 as i64 - self.anchor_cell.1 as i64 + self.anchor_tile.1 as i64;
+				common::REFERENCE_WEIGHT * self.scale * prior.cost(piece,(col,row),rotation)
+			},
+			None => 0.0,
+		}
+	}
+
+	/// Check if the position is allowed, we reject out of the reference only if the grid
+	/// was given by the user.
+	fn accept(self: &Self,pos: (usize,usize)) -> bool {
+		match self.prior {
+			Some(prior) if prior.strict => {
+				let col = pos.0 as i64 - self.anchor_cell.0 as i64 + self.anchor_tile.0 as i64;
+				let row = pos.1 as i64 - self.anchor_cell.1 as i64 + self.anchor_tile.1 as i64;
+				col >= 0 && row >= 0 && col < prior.cols as i64 && row < prior.rows as i64
+			},
+			_ => true,
+		}
+	}
+}
+
+/// Median distance of all the matches to scale the reference costs.
+fn calc_median_match_distance(pieces: &PieceVec) -> f32 {
+	let mut all: Vec<f32> = vec!();
+	for p in pieces.iter() {
+		let p = p.read().unwrap();
+		for f in 0..4 {
+			for m in p.matches[f].iter() {
+				all.push(m.distance);
+			}
+		}
+	}
+	all.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+	if all.is_empty() {
+		1.0
+	} else {
+		all[all.len() / 2].max(1.0)
+	}
+}
+
 fn cell_has_piece(current: &Soluce,x : usize, y: usize, dx: i32, dy: i32) -> Option<(usize,usize)> {
     let x = x as i32 + dx;
     let y = y as i32 + dy;
@@ -295,7 +355,7 @@ fn calc_connectivity(soluce: &Soluce) -> u32 {
 	cnt
 }
 
-fn search_next_step_recurse(pieces: &PieceVec, current: &mut Soluce, usage: &mut PieceUsage, proposal: &mut SoluceProposal,depth:u32,dist:f32) {
+fn search_next_step_recurse(pieces: &PieceVec, current: &mut Soluce, usage: &mut PieceUsage, proposal: &mut SoluceProposal,guide: &Guide,depth:u32,dist:f32) {
     //search an intersting position
     let (w,h) = (current.len_of(Axis(0)),current.len_of(Axis(1)));
 	let mut found = false;
@@ -327,13 +387,18 @@ fn search_next_step_recurse(pieces: &PieceVec, current: &mut Soluce, usage: &mut
 							candidates = n.matches[nside].clone();
 						}
 
+						//with a reference we first try the candidates the picture agree with
+						let mut candidates: Vec<(f32,usize,&PieceMatch)> = candidates.iter().map(|c| {
+							let rot = calc_rotation((x,y),coord,c.side);
+							(c.distance + guide.cost(c.piece,(x,y),rot),rot,c)
+						}).collect();
+						candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
 						//loop on candidates
-						for c in candidates.iter() {
+						for &(cost,rot,c) in candidates.iter() {
 							//println!("TTry {}",c.piece);
 							//check if already in use
-							if !usage[c.piece] {
-								//calc rotation to place the piece
-								let rot = calc_rotation((x,y),coord,c.side);
+							if !usage[c.piece] && guide.accept((x,y)) {
 								//println!("Try {}, {}",c.piece,rot);
 
 								//setup piece in place
@@ -348,7 +413,7 @@ fn search_next_step_recurse(pieces: &PieceVec, current: &mut Soluce, usage: &mut
 								//check if match with all neighboors
 								if check_match_all_neighboors(pieces,current,(x,y)) {
 									//println!("Recurse {}",depth);
-									search_next_step_recurse(pieces,current,usage,proposal,depth+1,dist + c.distance);
+									search_next_step_recurse(pieces,current,usage,proposal,guide,depth+1,dist + cost);
 									found = true;
 								}
 
@@ -384,11 +449,13 @@ fn search_next_step_recurse(pieces: &PieceVec, current: &mut Soluce, usage: &mut
 		if cnt > proposal.nb {
 			println!("CLEAR nb");
 			proposal.list.clear();
+			proposal.costs.clear();
 			proposal.nb = cnt;
 			proposal.connectivity = connectivity;
 		} else if cnt == proposal.nb && connectivity > proposal.connectivity {
 			println!("CLEAR connectivity");
 			proposal.list.clear();
+			proposal.costs.clear();
 			proposal.nb = cnt;
 			proposal.connectivity = connectivity;
 		}
@@ -407,6 +474,7 @@ fn search_next_step_recurse(pieces: &PieceVec, current: &mut Soluce, usage: &mut
 			if keep {
 				let copy = current.clone();
 				proposal.list.push(copy);
+				proposal.costs.push(dist);
 				println!("{}",current);
 			}
 
@@ -418,16 +486,38 @@ fn search_next_step_recurse(pieces: &PieceVec, current: &mut Soluce, usage: &mut
 	}
 }
 
-pub fn build_solution(pieces: &PieceVec, _dump:i32) -> SoluceVec {
+pub fn build_solution(pieces: &PieceVec, dump:i32) -> SoluceVec {
+	build_solution_guided(pieces,None,dump)
+}
+
+/// Same than build_solution but using the reference picture as a placement prior.
+pub fn build_solution_guided(pieces: &PieceVec, reference: Option<&ReferencePrior>, _dump:i32) -> SoluceVec {
     //estimate size & middle pos
     let size = pieces.len() * 2;
     let (x,y) = (size / 2, size / 2);
 
+	//setup guide and select starting piece
+	let mut guide = Guide {
+		prior: reference,
+		anchor_cell: (x,y),
+		anchor_tile: (0,0),
+		scale: 1.0,
+	};
+	let mut start = (0,0);
+	if let Some(prior) = reference {
+		let (pid,tile,rot) = prior.select_start();
+		println!("Start from piece {} on reference tile {:?} with rotation {}",pid,tile,rot);
+		guide.anchor_tile = tile;
+		guide.scale = calc_median_match_distance(pieces);
+		start = (pid,rot);
+	}
+
     //init a solution
     let mut current = Soluce::zeros((size,size));
     let mut usage:PieceUsage = vec!();
     let mut proposal = SoluceProposal {
         list:vec!(),
+        costs:vec!(),
         nb:0,
 		connectivity:0,
     };
@@ -438,20 +528,20 @@ pub fn build_solution(pieces: &PieceVec, _dump:i32) -> SoluceVec {
     }
 
     //loop on all piece to start
-    for i in 0..1 { //pieces.len() {
+    for i in start.0..(start.0+1) { //pieces.len() {
         //place to center
         {
             let mut cell = current.get_mut((x,y)).unwrap();
 			assert!(cell.piece_id == NONE);
             cell.piece_id = i;
-            cell.rotation = 0;
+            cell.rotation = start.1;
         }
 
         //mark as used
         usage[i] = true;
 
         //serch next step
-        search_next_step_recurse(pieces,&mut current,&mut usage,&mut proposal,1,0.0);
+        search_next_step_recurse(pieces,&mut current,&mut usage,&mut proposal,&guide,1,0.0);
 
         //remove piece for next round
         usage[i] = false;
@@ -465,6 +555,13 @@ pub fn build_solution(pieces: &PieceVec, _dump:i32) -> SoluceVec {
         }
     }
 
+	//with a reference we can rank the solutions
+	if reference.is_some() {
+		let mut ranked: Vec<(f32,Soluce)> = proposal.costs.iter().cloned().zip(proposal.list.drain(..)).collect();
+		ranked.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+		proposal.list = ranked.into_iter().map(|(_,s)| s).collect();
+	}
+
 	//print solutions
 	println!("Found {} solutions with {} pieces",proposal.list.len(),proposal.nb);
 
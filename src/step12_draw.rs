@@ -13,16 +13,34 @@ extern crate image;
 extern crate imageproc;
 
 //external
-use image::{RgbaImage,Luma,imageops};
+use image::{RgbaImage,GrayImage,Luma,Rgba,imageops};
 use ndarray::Axis;
 use imageproc::{drawing,rect};
 use std::usize;
+use std::f32;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
 
 //internal
 use step11_build::{Soluce,NONE};
 use step5_corners::extract_surrounding_rect;
-use piece::{Piece,PieceVec};
+use piece::{Piece,PieceVec,TOP,RIGHT,BOTTOM,LEFT};
 use common;
+use font;
+use contour;
+
+/// Position of a piece in the composed picture. The piece images are first turned by
+/// quarter of turn (rotation) then moved with the small rotation (angle) and translation.
+#[derive(Debug,Clone)]
+pub struct Placement {
+	pub piece_id: usize,
+	pub rotation: usize,
+	pub cell: (usize,usize),
+	pub angle: f32,
+	pub translation: (f32,f32),
+	pub corners: [(f32,f32);4],
+}
 
 type SurroundVec = Vec<(u32,u32,u32,u32)>;
 
@@ -91,17 +109,19 @@ fn get_soluce_size(soluce:&Soluce) -> (usize,usize,usize,usize) {
 	(xmin,ymin,xmax - xmin + 1,ymax - ymin + 1)
 }
 
-fn draw_piece(image: &mut RgbaImage, piece: &Piece, rotate: usize,pos:(u32,u32),size:(u32,u32)) {
-	//rotate image and
-	let imgrot;
-	let maskrot;
+fn rotate_piece(piece: &Piece, rotate: usize) -> (RgbaImage,GrayImage) {
 	match rotate {
-		0 => {imgrot = piece.image.clone();maskrot = piece.mask.clone()},
-		1 => {imgrot = imageops::rotate90(&piece.image); maskrot = imageops::rotate90(&piece.mask);},
-		2 => {imgrot = imageops::rotate180(&piece.image); maskrot = imageops::rotate180(&piece.mask);},
-		3 => {imgrot = imageops::rotate270(&piece.image); maskrot = imageops::rotate270(&piece.mask);},
+		0 => (piece.image.clone(),piece.mask.clone()),
+		1 => (imageops::rotate90(&piece.image),imageops::rotate90(&piece.mask)),
+		2 => (imageops::rotate180(&piece.image),imageops::rotate180(&piece.mask)),
+		3 => (imageops::rotate270(&piece.image),imageops::rotate270(&piece.mask)),
 		_ => panic!("Invalid value should be 0,1,2 or 3 !"),
 	}
+}
+
+fn draw_piece(image: &mut RgbaImage, piece: &Piece, rotate: usize,pos:(u32,u32),size:(u32,u32)) {
+	//rotate image and
+	let (imgrot,maskrot) = rotate_piece(piece,rotate);
 
  	//recalculate rect
 	let rect = extract_surrounding_rect(&maskrot);
@@ -147,7 +167,7 @@ pub fn draw_solution(soluce:&Soluce, pieces: &PieceVec,fname:&str) {
 			let pid = s.piece_id;
 			if pid != NONE {
 				let p = pieces[pid].read().unwrap();
-				let pos = (x as u32 * w_el,y as u32 * w_el);
+				let pos = (x as u32 * w_el,y as u32 * h_el);
 				draw_piece(&mut image, &p, s.rotation,pos,(w_el,h_el));
 			}
 		}
@@ -155,4 +175,612 @@ pub fn draw_solution(soluce:&Soluce, pieces: &PieceVec,fname:&str) {
 
 	//save into file
 	image.save(fname).unwrap();
-}
\ No newline at end of file
+}
+
+/// Apply the same transformation on a point than imageops::rotate90/180/270 on an image of the given size.
+fn rotate_point_quarter(point: (f32,f32),size: (u32,u32),rotate: usize) -> (f32,f32) {
+	let (x,y) = point;
+	let (w,h) = (size.0 as f32,size.1 as f32);
+	match rotate {
+		0 => (x,y),
+		1 => (h - 1.0 - y,x),
+		2 => (w - 1.0 - x,h - 1.0 - y),
+		3 => (y,w - 1.0 - x),
+		_ => panic!("Invalid value should be 0,1,2 or 3 !"),
+	}
+}
+
+/// Corners of the piece (clockwise from top left) after turning it by quarter of turns.
+fn get_rotated_corners(piece: &Piece,rotate: usize) -> [(f32,f32);4] {
+	let corners = piece.get_corners();
+	let size = piece.mask.dimensions();
+	let mut ret = [(0.0,0.0);4];
+	for k in 0..4 {
+		ret[k] = rotate_point_quarter(corners[(k + 4 - rotate) % 4],size,rotate);
+	}
+	ret
+}
+
+fn apply_transform(point: (f32,f32),angle: f32,translation: (f32,f32)) -> (f32,f32) {
+	let (x,y) = point;
+	(x * angle.cos() - y * angle.sin() + translation.0,x * angle.sin() + y * angle.cos() + translation.1)
+}
+
+/// Find the rotation and translation moving the local points the closer to the target ones.
+/// The angle is limited as we only want to fix small imprecisions of the matching.
+fn fit_transform(pairs: &Vec<((f32,f32),(f32,f32))>) -> (f32,(f32,f32)) {
+	//centers
+	let n = pairs.len() as f32;
+	let (mut ca,mut cb) = ((0.0,0.0),(0.0,0.0));
+	for &(a,b) in pairs.iter() {
+		ca = (ca.0 + a.0 / n,ca.1 + a.1 / n);
+		cb = (cb.0 + b.0 / n,cb.1 + b.1 / n);
+	}
+
+	//angle
+	let (mut sin,mut cos) = (0.0,0.0);
+	for &(a,b) in pairs.iter() {
+		let (ax,ay) = (a.0 - ca.0,a.1 - ca.1);
+		let (bx,by) = (b.0 - cb.0,b.1 - cb.1);
+		sin += ax * by - ay * bx;
+		cos += ax * bx + ay * by;
+	}
+	let mut angle = sin.atan2(cos);
+	if pairs.len() < 2 || angle.abs() > common::DRAW_MAX_PLACEMENT_ANGLE.to_radians() {
+		angle = 0.0;
+	}
+
+	//translation
+	let rot = apply_transform(ca,angle,(0.0,0.0));
+	(angle,(cb.0 - rot.0,cb.1 - rot.1))
+}
+
+/// For a neighboor in the given direction, return which of our corners need to be on which of its corners.
+fn get_shared_corners(direction: usize) -> [(usize,usize);2] {
+	match direction {
+		TOP => [(0,3),(1,2)],
+		RIGHT => [(1,0),(2,3)],
+		BOTTOM => [(3,0),(2,1)],
+		LEFT => [(0,1),(3,2)],
+		_ => panic!("Invalid direction !"),
+	}
+}
+
+/// Compute the placement of all the pieces of the solution by making the corners of the neighboors
+/// match. We walk the solution from the first piece so each piece has at least one placed neighboor,
+/// if not (disconnected part) we fallback on a grid estimation.
+pub fn compute_placement(soluce:&Soluce, pieces: &PieceVec) -> Vec<Placement> {
+	//infos
+	let (x_sol,y_sol,w_sol,h_sol) = get_soluce_size(soluce);
+	let (w_el,h_el) = max_size(&build_surround(pieces));
+	let mut placed: Vec<Vec<Option<Placement>>> = vec![vec![None;h_sol];w_sol];
+	let mut ret = vec!();
+	let dirs = [(TOP,(0,-1)),(RIGHT,(1,0)),(BOTTOM,(0,1)),(LEFT,(-1,0))];
+
+	//loop until all placed
+	for y0 in 0..h_sol {
+		for x0 in 0..w_sol {
+			if soluce.get((x0+x_sol,y0+y_sol)).unwrap().piece_id == NONE || placed[x0][y0].is_some() {
+				continue;
+			}
+
+			//walk from there
+			let mut queue = VecDeque::new();
+			queue.push_back((x0,y0));
+			while let Some((x,y)) = queue.pop_front() {
+				if placed[x][y].is_some() {
+					continue;
+				}
+
+				//extract
+				let s = soluce.get((x+x_sol,y+y_sol)).unwrap();
+				let p = pieces[s.piece_id].read().unwrap();
+				let corners = get_rotated_corners(&p,s.rotation);
+
+				//build constrains from placed neighboors
+				let mut pairs = vec!();
+				for &(dir,(dx,dy)) in dirs.iter() {
+					let (nx,ny) = (x as i32 + dx,y as i32 + dy);
+					if nx >= 0 && ny >= 0 && nx < w_sol as i32 && ny < h_sol as i32 {
+						if let Some(ref n) = placed[nx as usize][ny as usize] {
+							for &(mine,their) in get_shared_corners(dir).iter() {
+								pairs.push((corners[mine],n.corners[their]));
+							}
+						}
+					}
+				}
+
+				//fit or fallback on grid
+				let (angle,translation) = if pairs.is_empty() {
+					let (cx,cy) = (corners[0].0,corners[0].1);
+					(0.0,((x as u32 * w_el) as f32 - cx,(y as u32 * h_el) as f32 - cy))
+				} else {
+					fit_transform(&pairs)
+				};
+
+				//register
+				let mut global = [(0.0,0.0);4];
+				for k in 0..4 {
+					global[k] = apply_transform(corners[k],angle,translation);
+				}
+				let placement = Placement {
+					piece_id: s.piece_id,
+					rotation: s.rotation,
+					cell: (x,y),
+					angle: angle,
+					translation: translation,
+					corners: global,
+				};
+				placed[x][y] = Some(placement.clone());
+				ret.push(placement);
+
+				//push neighboors
+				for &(_,(dx,dy)) in dirs.iter() {
+					let (nx,ny) = (x as i32 + dx,y as i32 + dy);
+					if nx >= 0 && ny >= 0 && nx < w_sol as i32 && ny < h_sol as i32 {
+						let (nx,ny) = (nx as usize,ny as usize);
+						if placed[nx][ny].is_none() && soluce.get((nx+x_sol,ny+y_sol)).unwrap().piece_id != NONE {
+							queue.push_back((nx,ny));
+						}
+					}
+				}
+			}
+		}
+	}
+
+	ret
+}
+
+/// Compute the bounding box of all the placed pieces.
+fn calc_placement_bounds(placements: &Vec<Placement>, pieces: &PieceVec) -> (f32,f32,f32,f32) {
+	let (mut xmin,mut ymin,mut xmax,mut ymax) = (f32::MAX,f32::MAX,f32::MIN,f32::MIN);
+	for pl in placements.iter() {
+		let p = pieces[pl.piece_id].read().unwrap();
+		let (w,h) = p.mask.dimensions();
+		let (w,h) = if pl.rotation % 2 == 1 {(h as f32,w as f32)} else {(w as f32,h as f32)};
+		for &c in [(0.0,0.0),(w,0.0),(w,h),(0.0,h)].iter() {
+			let (x,y) = apply_transform(c,pl.angle,pl.translation);
+			xmin = xmin.min(x);
+			ymin = ymin.min(y);
+			xmax = xmax.max(x);
+			ymax = ymax.max(y);
+		}
+	}
+	(xmin,ymin,xmax,ymax)
+}
+
+/// Draw the piece with its placement, we walk the destination pixels and get back the source one.
+fn draw_piece_placed(image: &mut RgbaImage, piece: &Piece, placement: &Placement, shift: (f32,f32)) {
+	//rotate
+	let (imgrot,maskrot) = rotate_piece(piece,placement.rotation);
+	let (w,h) = maskrot.dimensions();
+	let (iw,ih) = image.dimensions();
+	let translation = (placement.translation.0 - shift.0,placement.translation.1 - shift.1);
+	let (cos,sin) = (placement.angle.cos(),placement.angle.sin());
+	let keepcolor = Luma([common::MASK_PIECE_PIXEL]);
+
+	//destination rect
+	let (mut xmin,mut ymin,mut xmax,mut ymax) = (f32::MAX,f32::MAX,f32::MIN,f32::MIN);
+	for &c in [(0.0,0.0),(w as f32,0.0),(w as f32,h as f32),(0.0,h as f32)].iter() {
+		let (x,y) = apply_transform(c,placement.angle,translation);
+		xmin = xmin.min(x);
+		ymin = ymin.min(y);
+		xmax = xmax.max(x);
+		ymax = ymax.max(y);
+	}
+
+	//draw
+	for y in (ymin.max(0.0) as u32)..(ymax.ceil().max(0.0) as u32).min(ih) {
+		for x in (xmin.max(0.0) as u32)..(xmax.ceil().max(0.0) as u32).min(iw) {
+			let (dx,dy) = (x as f32 - translation.0,y as f32 - translation.1);
+			let (sx,sy) = (dx * cos + dy * sin,-dx * sin + dy * cos);
+			let (sx,sy) = (sx.round(),sy.round());
+			if sx >= 0.0 && sy >= 0.0 && (sx as u32) < w && (sy as u32) < h {
+				if *maskrot.get_pixel(sx as u32,sy as u32) == keepcolor {
+					image.put_pixel(x,y,*imgrot.get_pixel(sx as u32,sy as u32));
+				}
+			}
+		}
+	}
+}
+
+/// Draw the solution by placing the pieces from their real geometry so the bumps and holes
+/// interlock like on the real puzzle.
+pub fn draw_solution_placed(soluce:&Soluce, pieces: &PieceVec,fname:&str) {
+	//compute placement
+	let placements = compute_placement(soluce,pieces);
+	let (xmin,ymin,xmax,ymax) = calc_placement_bounds(&placements,pieces);
+	let (w,h) = ((xmax - xmin).ceil() as u32 + 1,(ymax - ymin).ceil() as u32 + 1);
+
+	//build final image
+	let mut image = RgbaImage::new(w,h);
+	let back = image::Rgba([255u8,255u8,255u8,0u8]);
+	drawing::draw_filled_rect_mut(&mut image,rect::Rect::at(0, 0).of_size(w, h),back);
+
+	//draw
+	for pl in placements.iter() {
+		let p = pieces[pl.piece_id].read().unwrap();
+		draw_piece_placed(&mut image,&p,pl,(xmin,ymin));
+	}
+
+	//save into file
+	image.save(fname).unwrap();
+}
+
+/// Search the distance of the match linking the two pieces through the given faces.
+fn find_link_distance(pieces: &PieceVec,pid: usize,fid: usize,nid: usize,nfid: usize) -> Option<f32> {
+	let p = pieces[pid].read().unwrap();
+	p.matches[fid].iter().find(|m| m.piece == nid && m.side == nfid).map(|m| m.distance)
+}
+
+/// Color for a link, from green (good) to red (bad), gray if the link is not in the matches.
+fn get_link_color(distance: Option<f32>,max: f32) -> Rgba<u8> {
+	match distance {
+		Some(d) => {
+			let ratio = if max > 0.0 {(d / max).min(1.0).max(0.0)} else {0.0};
+			Rgba([(255.0 * ratio) as u8,(255.0 * (1.0 - ratio)) as u8,0,255])
+		},
+		None => Rgba([128,128,128,255]),
+	}
+}
+
+fn draw_thick_line(image: &mut RgbaImage,start: (f32,f32),end: (f32,f32),width: i32,color: Rgba<u8>) {
+	for dy in -width/2..(width/2+1) {
+		for dx in -width/2..(width/2+1) {
+			let (dx,dy) = (dx as f32,dy as f32);
+			drawing::draw_line_segment_mut(image,(start.0+dx,start.1+dy),(end.0+dx,end.1+dy),color);
+		}
+	}
+}
+
+/// Draw an arrow from the center of the piece to the side which was on top in the photo.
+fn draw_rotation_arrow(image: &mut RgbaImage,pl: &Placement,shift: (f32,f32),width: i32,color: Rgba<u8>) {
+	let side = (TOP + pl.rotation) % 4;
+	let center = (
+		pl.corners.iter().map(|c| c.0).sum::<f32>() / 4.0 - shift.0,
+		pl.corners.iter().map(|c| c.1).sum::<f32>() / 4.0 - shift.1,
+	);
+	let (c1,c2) = (pl.corners[side],pl.corners[(side + 1) % 4]);
+	let middle = ((c1.0 + c2.0) / 2.0 - shift.0,(c1.1 + c2.1) / 2.0 - shift.1);
+	let (vx,vy) = ((middle.0 - center.0) * 0.6,(middle.1 - center.1) * 0.6);
+	let end = (center.0 + vx,center.1 + vy);
+	draw_thick_line(image,center,end,width,color);
+
+	//head
+	let head = 0.3;
+	let left = (end.0 - head * (vx - vy),end.1 - head * (vy + vx));
+	let right = (end.0 - head * (vx + vy),end.1 - head * (vy - vx));
+	draw_thick_line(image,end,left,width,color);
+	draw_thick_line(image,end,right,width,color);
+}
+
+/// Draw the solution with labels : piece id, photo index, grid (row,col), an arrow pointing the side
+/// which was on top in the photo and the links colored from the matching distance.
+pub fn draw_solution_annotated(soluce:&Soluce, pieces: &PieceVec,fname:&str) {
+	//compute placement
+	let placements = compute_placement(soluce,pieces);
+	let (xmin,ymin,xmax,ymax) = calc_placement_bounds(&placements,pieces);
+	let (w,h) = ((xmax - xmin).ceil() as u32 + 1,(ymax - ymin).ceil() as u32 + 1);
+	let (w_el,_) = max_size(&build_surround(pieces));
+	let scale = (w_el / 60).max(2);
+	let width = (scale as i32 / 2).max(1);
+
+	//build final image
+	let mut image = RgbaImage::new(w,h);
+	let back = image::Rgba([255u8,255u8,255u8,0u8]);
+	drawing::draw_filled_rect_mut(&mut image,rect::Rect::at(0, 0).of_size(w, h),back);
+
+	//draw pieces
+	for pl in placements.iter() {
+		let p = pieces[pl.piece_id].read().unwrap();
+		draw_piece_placed(&mut image,&p,pl,(xmin,ymin));
+	}
+
+	//extract links on right and bottom to draw each only once
+	let mut links = vec!();
+	for pl in placements.iter() {
+		for &(dir,(dx,dy)) in [(RIGHT,(1,0)),(BOTTOM,(0,1))].iter() {
+			let cell = (pl.cell.0 + dx,pl.cell.1 + dy);
+			if let Some(n) = placements.iter().find(|n| n.cell == cell) {
+				let fid = (dir + 4 - pl.rotation) % 4;
+				let nfid = ((dir + 2) + 4 - n.rotation) % 4;
+				let dist = find_link_distance(pieces,pl.piece_id,fid,n.piece_id,nfid);
+				let shared = get_shared_corners(dir);
+				links.push((dist,pl.corners[shared[0].0],pl.corners[shared[1].0]));
+			}
+		}
+	}
+	let max = links.iter().filter_map(|l| l.0).fold(0.0,f32::max);
+
+	//draw links
+	for &(dist,c1,c2) in links.iter() {
+		let color = get_link_color(dist,max);
+		draw_thick_line(&mut image,(c1.0 - xmin,c1.1 - ymin),(c2.0 - xmin,c2.1 - ymin),width * 2,color);
+	}
+
+	//draw arrows & labels
+	let black = Rgba([0,0,0,255]);
+	let white = Rgba([255,255,255,255]);
+	let blue = Rgba([0,0,255,255]);
+	for pl in placements.iter() {
+		let p = pieces[pl.piece_id].read().unwrap();
+		let cx = pl.corners.iter().map(|c| c.0).sum::<f32>() / 4.0 - xmin;
+		let cy = pl.corners.iter().map(|c| c.1).sum::<f32>() / 4.0 - ymin;
+		draw_rotation_arrow(&mut image,pl,(xmin,ymin),width,blue);
+		let (_,th) = font::text_size("0",scale);
+		let line1 = if p.face_down {format!("#{} back",p.stable_id)} else {format!("#{}",p.stable_id)};
+		let line2 = format!("p{} ({},{})",p.photo,pl.cell.1,pl.cell.0);
+		font::draw_label(&mut image,&line1,(cx as i32,cy as i32 - th as i32),scale,black,white);
+		font::draw_label(&mut image,&line2,(cx as i32,cy as i32 + th as i32),scale,black,white);
+	}
+
+	//save into file
+	image.save(fname).unwrap();
+}
+
+/// Draw on the original photo where each piece was taken with its id and its grid coordinates in the solution.
+pub fn draw_photo_map(photo: &RgbaImage,photo_id: u32,soluce:&Soluce, pieces: &PieceVec,fname:&str) {
+	//vars
+	let mut image = photo.clone();
+	let placements = compute_placement(soluce,pieces);
+	let (w_el,_) = max_size(&build_surround(pieces));
+	let scale = (w_el / 60).max(2);
+	let red = Rgba([255,0,0,255]);
+	let gray = Rgba([128,128,128,255]);
+	let black = Rgba([0,0,0,255]);
+	let white = Rgba([255,255,255,255]);
+
+	//draw all pieces from this photo
+	for (index,p) in pieces.iter().enumerate() {
+		let p = p.read().unwrap();
+		if p.photo != photo_id {
+			continue;
+		}
+
+		//search in solution
+		let pl = placements.iter().find(|pl| pl.piece_id == index);
+
+		//rect
+		let (x,y,w,h) = p.position;
+		let color = if pl.is_some() {red} else {gray};
+		for i in 0..(scale as i32 / 2).max(1) {
+			let r = rect::Rect::at(x as i32 - i,y as i32 - i).of_size(w + 2 * i as u32,h + 2 * i as u32);
+			drawing::draw_hollow_rect_mut(&mut image,r,color);
+		}
+
+		//label
+		let text = match pl {
+			Some(pl) => format!("#{} ({},{})",p.stable_id,pl.cell.1,pl.cell.0),
+			None => format!("#{} -",p.stable_id),
+		};
+		font::draw_label(&mut image,&text,((x + w / 2) as i32,(y + h / 2) as i32),scale,black,white);
+	}
+
+	//save into file
+	image.save(fname).unwrap();
+}
+
+/// Mean color of the piece to fill its shape in the SVG.
+fn calc_mean_color(piece: &Piece) -> (u8,u8,u8) {
+	let keep = Luma([common::MASK_PIECE_PIXEL]);
+	let mut sum = [0u64;3];
+	let mut cnt = 0u64;
+	for (x,y,m) in piece.mask.enumerate_pixels() {
+		if *m == keep {
+			let c = piece.image.get_pixel(x,y);
+			for i in 0..3 {
+				sum[i] += c.data[i] as u64;
+			}
+			cnt += 1;
+		}
+	}
+	let cnt = cnt.max(1);
+	((sum[0] / cnt) as u8,(sum[1] / cnt) as u8,(sum[2] / cnt) as u8)
+}
+
+/// Build the SVG path of the piece outline in the piece image coordinates.
+fn build_svg_path(piece: &Piece) -> String {
+	let outline = contour::simplify_contour(&contour::extract_contour(&piece.mask),common::DRAW_SVG_SIMPLIFY);
+	let mut path = String::new();
+	for (i,&(x,y)) in outline.iter().enumerate() {
+		path += &format!("{}{} {} ",if i == 0 {"M"} else {"L"},x,y);
+	}
+	path += "Z";
+	path
+}
+
+/// Build the SVG transform applying the quarter of turns then the placement.
+fn build_svg_transform(pl: &Placement,size: (u32,u32),shift: (f32,f32)) -> String {
+	let (w,h) = (size.0 as f32,size.1 as f32);
+	let quarter = match pl.rotation {
+		0 => (0.0,0.0),
+		1 => (h - 1.0,0.0),
+		2 => (w - 1.0,h - 1.0),
+		3 => (0.0,w - 1.0),
+		_ => panic!("Invalid value should be 0,1,2 or 3 !"),
+	};
+	format!("translate({:.2},{:.2}) rotate({:.3}) translate({},{}) rotate({})",
+		pl.translation.0 - shift.0,pl.translation.1 - shift.1,pl.angle.to_degrees(),
+		quarter.0,quarter.1,90 * pl.rotation)
+}
+
+/// Export the solution as SVG, each piece is a path from its contour moved in place with a
+/// transformation and the matching infos as attributes.
+pub fn draw_solution_svg(soluce:&Soluce, pieces: &PieceVec,fname:&str) {
+	//compute placement
+	let placements = compute_placement(soluce,pieces);
+	let (xmin,ymin,xmax,ymax) = calc_placement_bounds(&placements,pieces);
+	let (w,h) = ((xmax - xmin).ceil() as u32 + 1,(ymax - ymin).ceil() as u32 + 1);
+	let (w_el,_) = max_size(&build_surround(pieces));
+
+	//header
+	let mut out = String::new();
+	out += "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n";
+	out += &format!("<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",w,h,w,h);
+
+	//pieces
+	for pl in placements.iter() {
+		let p = pieces[pl.piece_id].read().unwrap();
+		let (r,g,b) = calc_mean_color(&p);
+		out += &format!("  <g id=\"piece-{}\" data-piece=\"{}\" data-stable-id=\"{}\" data-photo=\"{}\" data-row=\"{}\" data-col=\"{}\" data-rotation=\"{}\" data-angle=\"{:.3}\" data-quality=\"{}\" transform=\"{}\">\n",
+			p.id,p.id,p.stable_id,p.photo,pl.cell.1,pl.cell.0,pl.rotation,p.angle,p.quality,build_svg_transform(pl,p.mask.dimensions(),(xmin,ymin)));
+		out += &format!("    <path d=\"{}\" fill=\"rgb({},{},{})\" stroke=\"black\" stroke-width=\"1\"/>\n",build_svg_path(&p),r,g,b);
+		out += "  </g>\n";
+	}
+
+	//labels on top, not turned
+	let size = (w_el / 8).max(8);
+	for pl in placements.iter() {
+		let p = pieces[pl.piece_id].read().unwrap();
+		let cx = pl.corners.iter().map(|c| c.0).sum::<f32>() / 4.0 - xmin;
+		let cy = pl.corners.iter().map(|c| c.1).sum::<f32>() / 4.0 - ymin;
+		out += &format!("  <text x=\"{:.1}\" y=\"{:.1}\" font-size=\"{}\" text-anchor=\"middle\" font-family=\"sans-serif\">{}</text>\n",cx,cy,size,p.stable_id);
+	}
+	out += "</svg>\n";
+
+	//save into file
+	let mut file = File::create(fname).unwrap();
+	file.write_all(out.as_bytes()).unwrap();
+}
+
+/// Export the outline of one piece with its corners and bump/hole points for inspection.
+pub fn draw_piece_outline_svg(piece: &Piece,fname:&str) {
+	//vars
+	let (w,h) = piece.mask.dimensions();
+	let corners = piece.get_corners();
+	let shapes = [piece.points.top_shape,piece.points.right_shape,piece.points.bottom_shape,piece.points.left_shape];
+
+	//header
+	let mut out = String::new();
+	out += "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n";
+	out += &format!("<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",w,h,w,h);
+	out += &format!("  <g id=\"piece-{}\" data-piece=\"{}\" data-photo=\"{}\" data-angle=\"{}\" data-quality=\"{}\" data-sides=\"{:?} {:?} {:?} {:?}\" data-sides-confidence=\"{:.2} {:.2} {:.2} {:.2}\">\n",
+		piece.id,piece.id,piece.photo,piece.angle,piece.quality,
+		piece.side_infos.top,piece.side_infos.right,piece.side_infos.bottom,piece.side_infos.left,
+		piece.side_infos.confidence[0],piece.side_infos.confidence[1],piece.side_infos.confidence[2],piece.side_infos.confidence[3]);
+	out += &format!("    <path d=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"1\"/>\n",build_svg_path(piece));
+
+	//points
+	for c in corners.iter() {
+		out += &format!("    <circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"red\"/>\n",c.0,c.1);
+	}
+	for (side,s) in shapes.iter().enumerate() {
+		if piece.tabs[side].is_empty() {
+			out += &format!("    <circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"blue\"/>\n",s.0,s.1);
+		}
+		for tab in piece.tabs[side].iter() {
+			out += &format!("    <circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"blue\"/>\n",tab.point.0,tab.point.1);
+		}
+	}
+	out += "  </g>\n</svg>\n";
+
+	//save into file
+	let mut file = File::create(fname).unwrap();
+	file.write_all(out.as_bytes()).unwrap();
+}
+
+///Implement unit tests of the module
+#[cfg(test)]
+mod test {
+	use std::sync::{Arc,RwLock};
+	use image::{GrayImage,Luma,Rgba,RgbaImage};
+	use ndarray::Array2;
+	use common;
+	use step12_draw::*;
+	use step11_build::SoluceElmt;
+	use piece::{Piece,PieceVec};
+
+	fn near(a: (f32,f32),b: (f32,f32)) -> bool {
+		(a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01
+	}
+
+	#[test]
+	fn fit_transform_known() {
+		let (angle,translation) = (0.05f32,(10.0,-5.0));
+		let pairs = [(0.0,0.0),(30.0,0.0),(30.0,20.0)].iter()
+			.map(|&p| (p,apply_transform(p,angle,translation))).collect();
+		let (a,t) = fit_transform(&pairs);
+		assert!((a - angle).abs() < 0.0001);
+		assert!(near(t,translation));
+	}
+
+	#[test]
+	fn placement_2x2() {
+		//4 pieces of 40x40 pixels
+		let white = Rgba([255,255,255,255]);
+		let mut photo = RgbaImage::from_pixel(200,50,white);
+		for y in 5..45 {
+			for x in 5..45 {
+				photo.put_pixel(x,y,Rgba([255,0,0,255]));
+			}
+		}
+		let mut pieces: PieceVec = Vec::new();
+		for i in 0..4 {
+			pieces.push(Arc::new(RwLock::new(Piece::new(&photo,&white,(5,5,40,40),i))));
+		}
+
+		//3 straight pieces and the last one turned by atan(4/40)
+		for i in 0..3 {
+			let p = &mut pieces[i].write().unwrap().points;
+			p.top_left_corner = (12.0,12.0);
+			p.top_right_corner = (51.0,12.0);
+			p.bottom_right_corner = (51.0,51.0);
+			p.bottom_left_corner = (12.0,51.0);
+		}
+		{
+			let p = &mut pieces[3].write().unwrap().points;
+			p.top_left_corner = (14.0,10.0);
+			p.top_right_corner = (54.0,14.0);
+			p.bottom_right_corner = (50.0,54.0);
+			p.bottom_left_corner = (10.0,50.0);
+		}
+
+		//solution
+		let mut soluce = Array2::from_elem((2,2),SoluceElmt{piece_id:NONE,rotation:0});
+		for i in 0..4 {
+			soluce[(i % 2,i / 2)] = SoluceElmt{piece_id:i,rotation:0};
+		}
+
+		//check
+		let placements = compute_placement(&soluce,&pieces);
+		assert_eq!(placements.len(),4);
+		let get = |id: usize| placements.iter().find(|p| p.piece_id == id).unwrap().clone();
+		assert_eq!(get(0).angle,0.0);
+		assert!(near(get(0).translation,(-12.0,-12.0)));
+		assert_eq!(get(1).angle,0.0);
+		assert!(near(get(1).translation,(27.0,-12.0)));
+		assert!(near(get(2).translation,(-12.0,27.0)));
+		let last = get(3);
+		assert!((last.angle + (4.0f32).atan2(40.0)).abs() < 0.0001);
+		assert!((last.corners[0].1 - last.corners[1].1).abs() < 0.01);
+		assert!((last.corners[0].0 - last.corners[3].0).abs() < 0.01);
+		assert!((last.corners[0].0 - 39.0).abs() < 0.5 && (last.corners[0].1 - 39.0).abs() < 0.5);
+	}
+
+	#[test]
+	fn svg_export() {
+		//transform of a piece turned by a quarter then by 0.1 rad
+		let pl = Placement {
+			piece_id: 0,
+			rotation: 1,
+			cell: (0,0),
+			angle: 0.1,
+			translation: (30.0,20.0),
+			corners: [(0.0,0.0);4],
+		};
+		assert_eq!(build_svg_transform(&pl,(40,30),(10.0,5.0)),"translate(20.00,15.00) rotate(5.730) translate(29,0) rotate(90)");
+
+		//path of a square piece
+		let white = Rgba([255,255,255,255]);
+		let photo = RgbaImage::from_pixel(20,20,white);
+		let mut piece = Piece::new(&photo,&white,(0,0,20,20),0);
+		piece.mask = GrayImage::from_fn(20,20,|x,y| {
+			if x >= 5 && x < 15 && y >= 5 && y < 15 {
+				Luma([common::MASK_PIECE_PIXEL])
+			} else {
+				Luma([common::MASK_BACKGROUND])
+			}
+		});
+		assert_eq!(build_svg_path(&piece),"M5 5 L14 5 L14 14 L5 14 Z");
+	}
+}
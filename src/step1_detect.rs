@@ -11,6 +11,12 @@
 //load external
 extern crate image;
 
+//std
+use std::sync::{Arc,RwLock};
+
+//internal
+use piece::{Piece,PieceVec};
+
 ///Search first pixel which is not color of background. Starting from top left
 ///So fount the most top and most left pixel it found (top first).
 ///
@@ -167,6 +173,141 @@ pub fn paint_square(img:&mut image::RgbaImage,back:&image::Rgba<u8>,square:(u32,
 	}
 }
 
+/// Check if the object in the square touch the border of the photo, so it is probably not
+/// complete and should not be used.
+///
+/// **img**: image in which the object was found.
+/// **back**: background color to ignore
+/// **square**: rectangle surrounding the object.
+pub fn is_touching_border(img:&image::RgbaImage,back:&image::Rgba<u8>,square:(u32,u32,u32,u32)) -> bool {
+	//vars
+	let (w,h) = img.dimensions();
+	let (xmin,ymin,sx,sy) = square;
+	let xmax = (xmin+sx).min(w-1);
+	let ymax = (ymin+sy).min(h).saturating_sub(1);
+	let blue = image::Rgba([0 as u8,0 as u8,255 as u8,255 as u8]);
+	let green = image::Rgba([0 as u8,255 as u8,0 as u8,255 as u8]);
+	let is_object = |x:u32,y:u32| {
+		let color = img.get_pixel(x,y);
+		color != back && *color != blue && *color != green
+	};
+
+	//check the 4 borders
+	(ymin == 0 && (xmin..(xmax+1)).any(|x| is_object(x,0)))
+		|| (ymax == h-1 && (xmin..(xmax+1)).any(|x| is_object(x,h-1)))
+		|| (xmin == 0 && (ymin..(ymax+1)).any(|y| is_object(0,y)))
+		|| (xmax == w-1 && (ymin..(ymax+1)).any(|y| is_object(w-1,y)))
+}
+
+/// Count the pixels of the object on the border of the square which continue out of it, the
+/// piece extracted from the square then misses a part of the object.
+///
+/// **img**: image in which the object was found.
+/// **back**: background color to ignore
+/// **square**: rectangle used to extract the piece.
+pub fn count_clipped_pixels(img:&image::RgbaImage,back:&image::Rgba<u8>,square:(u32,u32,u32,u32)) -> u32 {
+	//vars
+	let (w,h) = img.dimensions();
+	let (xmin,ymin,sx,sy) = square;
+	let (xmax,ymax) = ((xmin+sx).min(w).saturating_sub(1),(ymin+sy).min(h).saturating_sub(1));
+	let blue = image::Rgba([0 as u8,0 as u8,255 as u8,255 as u8]);
+	let green = image::Rgba([0 as u8,255 as u8,0 as u8,255 as u8]);
+	let is_object = |x:u32,y:u32| {
+		let color = img.get_pixel(x,y);
+		color != back && *color != blue && *color != green
+	};
+
+	//check the pixels of the 4 borders against their neighbor out of the square
+	let mut cnt = 0;
+	for y in ymin..(ymax+1) {
+		for x in xmin..(xmax+1) {
+			if x != xmin && x != xmax && y != ymin && y != ymax {
+				continue;
+			}
+			let out = (x == xmin && x > 0 && is_object(x-1,y))
+				|| (x == xmax && x+1 < w && is_object(x+1,y))
+				|| (y == ymin && y > 0 && is_object(x,y-1))
+				|| (y == ymax && y+1 < h && is_object(x,y+1));
+			if out && is_object(x,y) {
+				cnt += 1;
+			}
+		}
+	}
+
+	cnt
+}
+
+/// Pieces extracted from a photo.
+pub struct Detection {
+	/// Pieces usable for the next steps.
+	pub pieces: PieceVec,
+	/// Rectangles of the objects touching the border of the photo which are ignored.
+	pub truncated: Vec<(u32,u32,u32,u32)>,
+}
+
+/// Search all the objects of the photo and extract them as pieces. The objects touching the
+/// border are not complete so they are only reported.
+///
+/// **img**: image in which to search, the objects found are painted.
+/// **back**: background color to ignore
+/// **photo**: index of the photo in the command line.
+pub fn detect_pieces(img:&mut image::RgbaImage,back:&image::Rgba<u8>,photo:u32) -> Detection {
+	let mut ret = Detection {
+		pieces: vec!(),
+		truncated: vec!(),
+	};
+
+	let mut first = (1,1);
+	while first != (0,0) {
+		first = find_first_non_bg_pixel(&img,&back);
+		println!("First black pixel : {:?}",first);
+
+		//fin square
+		let square = find_square_non_bg(&img,&back,first);
+		println!("Square : {:?}",square);
+
+		//extract into list
+		let (_,_,w,h) = square;
+		if w*h <= 600 {
+			println!("IGNORE, too small !");
+		} else if is_touching_border(&img,&back,square) {
+			println!("IGNORE, touching the border of the photo !");
+			ret.truncated.push(square);
+		} else {
+			//the square ends on the last column of the object, keep it in the piece
+			let (x,y,w,h) = square;
+			let crop = (x,y,w+1,h);
+			let clipped = count_clipped_pixels(img,&back,crop);
+			if clipped > 0 {
+				println!("WARNING : piece {} is cut by its square on {} pixels",ret.pieces.len(),clipped);
+			}
+			let mut p = Piece::new(img,&back,crop,ret.pieces.len() as u32);
+			p.photo = photo;
+			ret.pieces.push(Arc::new(RwLock::new(p)));
+		}
+
+		//draw for save
+		paint_square(img,&back,square);
+	}
+
+	ret
+}
+
+/// Draw the objects touching the border in red on the debug image.
+pub fn paint_truncated(img:&mut image::RgbaImage,truncated:&Vec<(u32,u32,u32,u32)>) {
+	let (w,h) = img.dimensions();
+	for &(xmin,ymin,sx,sy) in truncated.iter() {
+		for y in ymin..(ymin+sy).min(h) {
+			for x in xmin..(xmin+sx+1).min(w) {
+				let pix = img.get_pixel_mut(x,y);
+				if pix.data == [0,0,255,255] {
+					pix.data = [255,0,0,255];
+				}
+			}
+		}
+	}
+}
+
 ///Implement unit tests of the module
 #[cfg(test)]
 mod test {
@@ -210,4 +351,38 @@ mod test {
 		let ret = step1_detect::find_square_non_bg(&img,img.get_pixel(0,0),(85,43));
 		assert_eq!(ret,(85,43,142,174));
 	}
+
+	#[test]
+	fn is_touching_border() {
+		let back = image::Rgba([255,255,255,255]);
+		let mut img = image::RgbaImage::from_pixel(40,30,back);
+		for y in 10..20 {
+			for x in 0..10 {
+				img.put_pixel(x,y,image::Rgba([0,0,0,255]));
+				img.put_pixel(x+20,y,image::Rgba([0,0,0,255]));
+			}
+		}
+		assert_eq!(step1_detect::is_touching_border(&img,&back,(0,10,9,11)),true);
+		assert_eq!(step1_detect::is_touching_border(&img,&back,(20,10,9,11)),false);
+	}
+
+	#[test]
+	fn count_clipped_pixels() {
+		let back = image::Rgba([255,255,255,255]);
+		let mut img = image::RgbaImage::from_pixel(40,40,back);
+		for y in 10..30 {
+			for x in 10..30 {
+				img.put_pixel(x,y,image::Rgba([0,0,0,255]));
+			}
+		}
+
+		//the square ends on the last column of the object
+		let square = step1_detect::find_square_non_bg(&img,&back,(10,10));
+		assert_eq!(square,(10,10,19,21));
+		assert_eq!(step1_detect::count_clipped_pixels(&img,&back,square),20);
+		assert_eq!(step1_detect::count_clipped_pixels(&img,&back,(10,10,20,21)),0);
+
+		//cut on two sides
+		assert_eq!(step1_detect::count_clipped_pixels(&img,&back,(15,10,15,10)),24);
+	}
 }
\ No newline at end of file
@@ -0,0 +1,580 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// When two pieces touch on the table step1_detect extract them as a single object. We search
+/// the objects far larger than the median one and separate them with a watershed on the
+/// distance to the border : each piece has its own distance maximum and the flooding from
+/// those markers meets on the narrow contact zone. For the groups of pieces already assembled
+/// the dark seams between the pieces are removed before computing the distance so the flooding
+/// follows them, and the assembled sides are kept as known links.
+
+//load external
+extern crate image;
+
+//std
+use std::f32;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc,RwLock};
+
+//extern
+use image::{GrayImage,RgbaImage,Luma,Rgba};
+
+//internal
+use piece::{Piece,PieceVec,PieceMatch,PieceSideType,TOP,RIGHT,BOTTOM,LEFT};
+use step0_perspective::get_luminance;
+use common;
+
+/// Pixel waiting to be flooded, ordered by distance to the border.
+struct FloodPixel {
+	dist: f32,
+	pos: (u32,u32),
+	label: u8,
+}
+
+impl PartialEq for FloodPixel {
+	fn eq(&self,other: &Self) -> bool {
+		self.dist == other.dist
+	}
+}
+
+impl Eq for FloodPixel {}
+
+impl PartialOrd for FloodPixel {
+	fn partial_cmp(&self,other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for FloodPixel {
+	fn cmp(&self,other: &Self) -> Ordering {
+		self.dist.partial_cmp(&other.dist).unwrap_or(Ordering::Equal)
+	}
+}
+
+/// Count the pixels of the piece in the mask.
+pub fn calc_area(mask: &GrayImage) -> u32 {
+	mask.pixels().filter(|p| p.data[0] == common::MASK_PIECE_PIXEL).count() as u32
+}
+
+/// Median area of the pieces.
+fn calc_median_area(pieces: &PieceVec) -> u32 {
+	let mut areas: Vec<u32> = pieces.iter().map(|p| calc_area(&p.read().unwrap().mask)).collect();
+	areas.sort();
+	if areas.is_empty() {
+		0
+	} else {
+		areas[areas.len() / 2]
+	}
+}
+
+/// Compute the distance of each piece pixel to the background (chamfer approximation).
+pub fn compute_distance(mask: &GrayImage) -> Vec<f32> {
+	//init
+	let (w,h) = mask.dimensions();
+	let (w,h) = (w as usize,h as usize);
+	let mut dist: Vec<f32> = mask.pixels().map(|p| if p.data[0] == common::MASK_PIECE_PIXEL {f32::MAX} else {0.0}).collect();
+	let diag = 2.0f32.sqrt();
+
+	//forward
+	for y in 0..h {
+		for x in 0..w {
+			let mut d = dist[y * w + x];
+			if d == 0.0 {
+				continue;
+			}
+			d = d.min(if x > 0 {dist[y * w + x - 1] + 1.0} else {1.0});
+			d = d.min(if y > 0 {dist[(y - 1) * w + x] + 1.0} else {1.0});
+			d = d.min(if x > 0 && y > 0 {dist[(y - 1) * w + x - 1] + diag} else {1.0});
+			d = d.min(if x + 1 < w && y > 0 {dist[(y - 1) * w + x + 1] + diag} else {1.0});
+			dist[y * w + x] = d;
+		}
+	}
+
+	//backward
+	for y in (0..h).rev() {
+		for x in (0..w).rev() {
+			let mut d = dist[y * w + x];
+			if d == 0.0 {
+				continue;
+			}
+			d = d.min(if x + 1 < w {dist[y * w + x + 1] + 1.0} else {1.0});
+			d = d.min(if y + 1 < h {dist[(y + 1) * w + x] + 1.0} else {1.0});
+			d = d.min(if x + 1 < w && y + 1 < h {dist[(y + 1) * w + x + 1] + diag} else {1.0});
+			d = d.min(if x > 0 && y + 1 < h {dist[(y + 1) * w + x - 1] + diag} else {1.0});
+			dist[y * w + x] = d;
+		}
+	}
+
+	dist
+}
+
+/// Select the markers as the highest distances far enough from each other.
+fn find_markers(dist: &Vec<f32>,width: u32,count: usize,min_dist: f32) -> Vec<(u32,u32)> {
+	let mut order: Vec<usize> = (0..dist.len()).filter(|i| dist[*i] > 0.0).collect();
+	order.sort_by(|a,b| dist[*b].partial_cmp(&dist[*a]).unwrap());
+
+	let mut ret: Vec<(u32,u32)> = vec!();
+	for i in order {
+		let p = (i as u32 % width,i as u32 / width);
+		let far = ret.iter().all(|m| {
+			let (dx,dy) = (m.0 as f32 - p.0 as f32,m.1 as f32 - p.1 as f32);
+			(dx * dx + dy * dy).sqrt() > min_dist
+		});
+		if far {
+			ret.push(p);
+			if ret.len() == count {
+				break;
+			}
+		}
+	}
+	ret
+}
+
+/// Detect the thin dark lines between assembled pieces. A seam pixel is darker than the
+/// pixels on both sides in one direction, unlike the edges of the picture which are steps.
+pub fn find_seam_pixels(image: &RgbaImage,mask: &GrayImage) -> Vec<bool> {
+	//vars
+	let (w,h) = mask.dimensions();
+	let mut ret = vec![false;(w * h) as usize];
+	let lum = |x: i64,y: i64| -> Option<i32> {
+		if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+			None
+		} else {
+			Some(get_luminance(image.get_pixel(x as u32,y as u32)) as i32)
+		}
+	};
+	let d = common::SPLIT_SEAM_WIDTH as i64;
+
+	//check all directions
+	for y in 0..h {
+		for x in 0..w {
+			if mask.get_pixel(x,y).data[0] != common::MASK_PIECE_PIXEL {
+				continue;
+			}
+			let (xi,yi) = (x as i64,y as i64);
+			let cur = lum(xi,yi).unwrap();
+			for &(dx,dy) in [(1i64,0i64),(0,1),(1,1),(1,-1)].iter() {
+				if let (Some(a),Some(b)) = (lum(xi - d * dx,yi - d * dy),lum(xi + d * dx,yi + d * dy)) {
+					if cur + common::SPLIT_SEAM_CONTRAST < a.min(b) {
+						ret[(y * w + x) as usize] = true;
+					}
+				}
+			}
+		}
+	}
+
+	ret
+}
+
+/// Flood the piece from the markers following the distance from the highest one. Return the
+/// label of each pixel of the piece (0 for background or not reached).
+pub fn watershed(mask: &GrayImage,dist: &Vec<f32>,markers: &Vec<(u32,u32)>) -> Vec<u8> {
+	//init
+	let (w,h) = mask.dimensions();
+	let mut labels = vec![0u8;(w * h) as usize];
+	let mut heap = BinaryHeap::new();
+	for (i,m) in markers.iter().enumerate() {
+		let id = (m.1 * w + m.0) as usize;
+		labels[id] = i as u8 + 1;
+		heap.push(FloodPixel{dist: dist[id],pos: *m,label: i as u8 + 1});
+	}
+
+	//flood
+	while let Some(cur) = heap.pop() {
+		let (x,y) = cur.pos;
+		for &(dx,dy) in [(-1i64,0i64),(1,0),(0,-1),(0,1)].iter() {
+			let (nx,ny) = (x as i64 + dx,y as i64 + dy);
+			if nx < 0 || ny < 0 || nx >= w as i64 || ny >= h as i64 {
+				continue;
+			}
+			let id = (ny as u32 * w + nx as u32) as usize;
+			if labels[id] == 0 && mask.get_pixel(nx as u32,ny as u32).data[0] == common::MASK_PIECE_PIXEL {
+				labels[id] = cur.label;
+				heap.push(FloodPixel{dist: dist[id],pos: (nx as u32,ny as u32),label: cur.label});
+			}
+		}
+	}
+
+	labels
+}
+
+/// Remove the seam so the regions do not touch anymore. Return the length of the contact
+/// between each pair of labels.
+pub fn remove_seam(labels: &mut Vec<u8>,width: u32) -> Vec<((u8,u8),u32)> {
+	//search pixels touching another label
+	let h = labels.len() as u32 / width;
+	let mut seam = vec!();
+	let mut contacts: Vec<((u8,u8),u32)> = vec!();
+	for y in 0..h {
+		for x in 0..width {
+			let l = labels[(y * width + x) as usize];
+			if l == 0 {
+				continue;
+			}
+			let mut neighboors = vec!();
+			if x + 1 < width {
+				neighboors.push(labels[(y * width + x + 1) as usize]);
+			}
+			if y + 1 < h {
+				neighboors.push(labels[((y + 1) * width + x) as usize]);
+			}
+			for n in neighboors {
+				if n != 0 && n != l {
+					seam.push((y * width + x) as usize);
+					let key = (l.min(n),l.max(n));
+					match contacts.iter().position(|c| c.0 == key) {
+						Some(i) => contacts[i].1 += 1,
+						None => contacts.push((key,1)),
+					}
+				}
+			}
+		}
+	}
+
+	//remove
+	for id in seam {
+		labels[id] = 0;
+	}
+
+	contacts
+}
+
+/// One piece extracted from an object.
+struct SplitPart {
+	square: (u32,u32,u32,u32),
+	region: GrayImage,
+	center: (f32,f32),
+}
+
+/// Split one object into the given number of pieces. Return the parts in the photo coordinates,
+/// the pairs of parts assembled together and the seam pixels to draw them.
+fn split_piece(piece: &Piece,count: usize,median: u32) -> (Vec<SplitPart>,Vec<(usize,usize)>,Vec<(u32,u32)>) {
+	//the seams between assembled pieces are removed to get one distance maximum per piece
+	let (w,h) = piece.mask.dimensions();
+	let seam_pixels = find_seam_pixels(&piece.image,&piece.mask);
+	let mut inner = piece.mask.clone();
+	for (i,s) in seam_pixels.iter().enumerate() {
+		if *s {
+			inner.put_pixel(i as u32 % w,i as u32 / w,Luma([common::MASK_BACKGROUND]));
+		}
+	}
+
+	//flood
+	let dist = compute_distance(&inner);
+	let side = (median as f32).sqrt();
+	let markers = find_markers(&dist,w,count,side * common::SPLIT_MARKER_MIN_DIST);
+	let mut labels = watershed(&piece.mask,&dist,&markers);
+	let contacts = remove_seam(&mut labels,w);
+
+	//offset between the mask and the photo
+	let (x0,y0,pw,ph) = piece.position;
+	let (mx,my) = ((w - pw) / 2,(h - ph) / 2);
+
+	//build the regions
+	let mut parts = vec!();
+	let mut label_to_part = vec![None;markers.len() + 1];
+	for label in 1..(markers.len() as u8 + 1) {
+		//bounding box and center in the mask
+		let (mut xmin,mut ymin,mut xmax,mut ymax) = (u32::max_value(),u32::max_value(),0,0);
+		let mut sum = (0.0,0.0);
+		let mut cnt = 0;
+		for (i,l) in labels.iter().enumerate() {
+			if *l == label {
+				let (x,y) = (i as u32 % w,i as u32 / w);
+				xmin = xmin.min(x);
+				ymin = ymin.min(y);
+				xmax = xmax.max(x);
+				ymax = ymax.max(y);
+				sum = (sum.0 + x as f32,sum.1 + y as f32);
+				cnt += 1;
+			}
+		}
+		if xmin > xmax || xmin < mx || ymin < my {
+			continue;
+		}
+
+		//region in the square
+		let (rw,rh) = (xmax - xmin + 1,ymax - ymin + 1);
+		let mut region = GrayImage::new(rw,rh);
+		for y in 0..rh {
+			for x in 0..rw {
+				if labels[((ymin + y) * w + xmin + x) as usize] == label {
+					region.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+				}
+			}
+		}
+		label_to_part[label as usize] = Some(parts.len());
+		parts.push(SplitPart {
+			square: (x0 + xmin - mx,y0 + ymin - my,rw,rh),
+			region: region,
+			center: (x0 as f32 + sum.0 / cnt as f32 - mx as f32,y0 as f32 + sum.1 / cnt as f32 - my as f32),
+		});
+	}
+
+	//long contacts are assembled pieces, short ones are pieces touching by chance
+	let mut assembled = vec!();
+	for &((l1,l2),len) in contacts.iter() {
+		if len as f32 >= side * common::SPLIT_CLUSTER_SEAM_RATIO {
+			if let (Some(p1),Some(p2)) = (label_to_part[l1 as usize],label_to_part[l2 as usize]) {
+				assembled.push((p1,p2));
+			}
+		}
+	}
+
+	//seam in photo coordinates
+	let mut seam = vec!();
+	for (i,l) in labels.iter().enumerate() {
+		let (x,y) = (i as u32 % w,i as u32 / w);
+		if *l == 0 && piece.mask.get_pixel(x,y).data[0] == common::MASK_PIECE_PIXEL && x >= mx && y >= my {
+			seam.push((x0 + x - mx,y0 + y - my));
+		}
+	}
+
+	(parts,assembled,seam)
+}
+
+/// Search the objects too large to be a single piece and split them. The pieces are
+/// renumbered and the seams are painted in red on the debug picture. When the pieces were
+/// assembled we remember it into cluster_links.
+///
+/// **photo**: copy of the photo before detection (None if the pieces keep their own crop).
+/// **back**: background color.
+/// **pieces**: pieces found by step1_detect.
+/// **debug**: picture on which to draw the seams.
+pub fn split_touching_pieces(photo: Option<&RgbaImage>,back: &Rgba<u8>,pieces: &mut PieceVec,mut debug: Option<&mut RgbaImage>) {
+	//need some pieces to know the usual size
+	let median = calc_median_area(pieces);
+	if pieces.len() < common::SPLIT_MIN_PIECES || median == 0 {
+		return;
+	}
+
+	//loop on all
+	let mut ret: PieceVec = vec!();
+	for pp in pieces.iter() {
+		//check size
+		let (parts,assembled,photo_id) = {
+			let p = pp.read().unwrap();
+			let area = calc_area(&p.mask);
+			if area as f32 > common::SPLIT_AREA_RATIO * median as f32 {
+				let count = ((area as f32 / median as f32).round() as usize).max(2);
+				println!("Split piece {} (area {}, median {}) in {}",p.id,area,median,count);
+				let (parts,assembled,seam) = split_piece(&p,count,median);
+				if let Some(ref mut debug) = debug {
+					for (x,y) in seam {
+						debug.put_pixel(x,y,Rgba([255,0,0,255]));
+					}
+				}
+				let parts: Vec<(SplitPart,Option<RgbaImage>)> = parts.into_iter().map(|part| {
+					let source = p.crop_source(part.square);
+					(part,source)
+				}).collect();
+				(parts,assembled,p.photo)
+			} else {
+				(vec!(),vec!(),p.photo)
+			}
+		};
+
+		//keep as it is or replace by the parts
+		if parts.len() < 2 {
+			ret.push(Arc::clone(pp));
+		} else {
+			let base = ret.len();
+			let centers: Vec<(f32,f32)> = parts.iter().map(|p| p.0.center).collect();
+			for (part,source) in parts {
+				let mut piece = match source {
+					Some(source) => Piece::new_with_source(source,back,part.square,Some(part.region),0),
+					None => Piece::new_with_region(photo.expect("Need the photo to split the piece !"),back,part.square,Some(part.region),0),
+				};
+				piece.photo = photo_id;
+				ret.push(Arc::new(RwLock::new(piece)));
+			}
+			for (p1,p2) in assembled {
+				println!("Assembled pieces {} <-> {}",base + p1,base + p2);
+				let delta = (centers[p2].0 - centers[p1].0,centers[p2].1 - centers[p1].1);
+				ret[base + p1].write().unwrap().cluster_links.push((base + p2,delta));
+				ret[base + p2].write().unwrap().cluster_links.push((base + p1,(-delta.0,-delta.1)));
+			}
+		}
+	}
+
+	//renumber
+	for (i,p) in ret.iter().enumerate() {
+		p.write().unwrap().id = i as u32;
+	}
+	*pieces = ret;
+}
+
+/// Get the side of the rotated piece facing the given direction of the photo.
+fn get_side_facing(angle: u32,delta: (f32,f32)) -> usize {
+	//the piece image is turned by -angle
+	let a = (angle as f32).to_radians();
+	let (x,y) = (delta.0 * a.cos() + delta.1 * a.sin(),-delta.0 * a.sin() + delta.1 * a.cos());
+	if x.abs() > y.abs() {
+		if x > 0.0 {RIGHT} else {LEFT}
+	} else {
+		if y > 0.0 {BOTTOM} else {TOP}
+	}
+}
+
+/// Once the pieces are rotated we can convert the cluster links into known neighboors on
+/// each side, they are used as hard constraints by the matching.
+pub fn resolve_cluster_links(pieces: &PieceVec) {
+	//compute all
+	let mut links = vec!();
+	for (i,pp) in pieces.iter().enumerate() {
+		let p = pp.read().unwrap();
+		for &(other,delta) in p.cluster_links.iter() {
+			let side = get_side_facing(p.angle,delta);
+			let other_side = get_side_facing(pieces[other].read().unwrap().angle,(-delta.0,-delta.1));
+			links.push((i,side,other,other_side));
+		}
+	}
+
+	//apply
+	for (i,side,other,other_side) in links {
+		let mut p = pieces[i].write().unwrap();
+		if p.faces[side].mode == PieceSideType::Unknown || p.sides_compatible(side,&pieces[other].read().unwrap(),other_side) {
+			println!("Piece {} side {} is assembled with piece {} side {}",i,side,other,other_side);
+		} else {
+			println!("WARNING : assembled sides {}:{} and {}:{} have shapes which do not fit",i,side,other,other_side);
+		}
+		p.links[side] = Some(PieceMatch{
+			piece: other,
+			side: other_side,
+			angle: 0.0,
+			distance: 0.0,
+		});
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,RgbaImage,Luma,Rgba};
+	use std::sync::{Arc,RwLock};
+	use step2_split::*;
+	use piece::{Piece,PieceVec};
+	use common;
+
+	#[test]
+	fn watershed_two_squares() {
+		//two squares touching with a thin bridge
+		let mut mask = GrayImage::new(50,30);
+		for y in 5..25 {
+			for x in 3..23 {
+				mask.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+				mask.put_pixel(x + 24,y,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+		for y in 13..17 {
+			mask.put_pixel(23,y,Luma([common::MASK_PIECE_PIXEL]));
+		}
+
+		let dist = compute_distance(&mask);
+		let markers = find_markers(&dist,50,2,10.0);
+		assert_eq!(markers.len(),2);
+		let labels = watershed(&mask,&dist,&markers);
+		let left = labels[15 * 50 + 10];
+		let right = labels[15 * 50 + 35];
+		assert!(left != 0 && right != 0 && left != right);
+		assert!(labels.iter().filter(|l| **l == left).count() >= 390);
+		assert!(labels.iter().filter(|l| **l == right).count() >= 390);
+	}
+
+	#[test]
+	fn watershed_cluster_seam() {
+		//two squares assembled along a 4 pixels contact
+		let mut mask = GrayImage::new(50,30);
+		for y in 5..25 {
+			for x in 3..23 {
+				mask.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+				mask.put_pixel(x + 24,y,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+		for y in 13..17 {
+			for x in 23..27 {
+				mask.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+
+		//split and remove the seam
+		let dist = compute_distance(&mask);
+		let markers = find_markers(&dist,50,2,10.0);
+		assert_eq!(markers.len(),2);
+		let mut labels = watershed(&mask,&dist,&markers);
+		let contacts = remove_seam(&mut labels,50);
+		assert_eq!(contacts.len(),1);
+		assert!(contacts[0].1 >= 4);
+
+		//the two regions do not touch anymore
+		let left = labels[15 * 50 + 10];
+		let right = labels[15 * 50 + 35];
+		assert!(left != 0 && right != 0 && left != right);
+		for y in 0..30 {
+			for x in 0..49 {
+				let (l1,l2) = (labels[y * 50 + x],labels[y * 50 + x + 1]);
+				assert!(l1 == 0 || l2 == 0 || l1 == l2);
+			}
+		}
+	}
+
+	#[test]
+	fn seam_pixels() {
+		//two gray squares assembled with a 2 pixels dark seam on a dark table
+		let mut image = RgbaImage::from_pixel(50,30,Rgba([60,60,60,255]));
+		let mut mask = GrayImage::new(50,30);
+		for y in 5..25 {
+			for x in 3..47 {
+				let c = if x == 23 || x == 24 {40} else {200};
+				image.put_pixel(x,y,Rgba([c,c,c,255]));
+				mask.put_pixel(x,y,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+
+		//only the seam is found, not the outer edges
+		let seam = find_seam_pixels(&image,&mask);
+		for y in 0..30 {
+			for x in 0..50 {
+				let expect = (x == 23 || x == 24) && y >= 5 && y < 25;
+				assert_eq!(seam[y * 50 + x],expect,"{} {}",x,y);
+			}
+		}
+	}
+
+	#[test]
+	fn cluster_links() {
+		//piece 1 is on the right of piece 0 on the photo and was turned by 90 degrees
+		let white = Rgba([255,255,255,255]);
+		let photo = RgbaImage::from_pixel(60,30,white);
+		let mut pieces: PieceVec = Vec::new();
+		for i in 0..2 {
+			pieces.push(Arc::new(RwLock::new(Piece::new(&photo,&white,(i * 30,0,30,30),i))));
+		}
+		pieces[0].write().unwrap().cluster_links.push((1,(30.0,0.0)));
+		pieces[1].write().unwrap().cluster_links.push((0,(-30.0,0.0)));
+		pieces[1].write().unwrap().angle = 90;
+
+		//the right side of piece 0 is assembled with the bottom side of piece 1
+		resolve_cluster_links(&pieces);
+		let link0 = pieces[0].read().unwrap().links[RIGHT].clone().expect("Piece 0 not linked !");
+		let link1 = pieces[1].read().unwrap().links[BOTTOM].clone().expect("Piece 1 not linked !");
+		assert_eq!((link0.piece,link0.side),(1,BOTTOM));
+		assert_eq!((link1.piece,link1.side),(0,RIGHT));
+		for side in [TOP,BOTTOM,LEFT].iter() {
+			assert!(pieces[0].read().unwrap().links[*side].is_none());
+		}
+	}
+
+	#[test]
+	fn side_facing() {
+		assert_eq!(get_side_facing(0,(10.0,1.0)),RIGHT);
+		assert_eq!(get_side_facing(0,(1.0,-10.0)),TOP);
+		assert_eq!(get_side_facing(90,(10.0,1.0)),TOP);
+		assert_eq!(get_side_facing(180,(10.0,1.0)),LEFT);
+	}
+}
@@ -19,13 +19,20 @@ use common;
 use std::f32;
 use std::cmp;
 
+/// Half diagonal of the image, the lines must be this long to cross the whole image
+/// whatever their angle.
+fn calc_half_diagonal(img:&image::GrayImage) -> u32 {
+	let (w,h) = img.dimensions();
+	(((w*w + h*h) as f32).sqrt() / 2.0).ceil() as u32
+}
+
 /// Calculate the coordinate of a line turned from a given angle and at offset distance of the
 /// image center.
 fn calc_line_coord(img:&image::GrayImage,angle:u32,offset:u32) -> ((f32,f32),(f32,f32)) {
 	//middle of image
 	let (w,h) = img.dimensions();
 	let (xm,ym) = ((w/2) as f32,(h/2) as f32);
-	let ray = xm.max(ym);
+	let ray = calc_half_diagonal(img) as f32;
 
 	//compute angle in radian
 	let rad = (angle as f32).to_radians();
@@ -44,13 +51,13 @@ fn calc_line_coord(img:&image::GrayImage,angle:u32,offset:u32) -> ((f32,f32),(f3
 	((x0,y0),(x1,y1))
 }
 
-/// Draw the line limit for debugging.
-pub fn draw_limit_line(img:&mut image::GrayImage,angle:u32,offset:u32) {
+/// Draw the line limit for debugging on the overlay.
+pub fn draw_limit_line(overlay:&mut image::GrayImage,angle:u32,offset:u32) {
 	//coords
-	let (start,end) = calc_line_coord(&img,angle,offset);
+	let (start,end) = calc_line_coord(&overlay,angle,offset);
 
 	//draw line
-	imageproc::drawing::draw_line_segment_mut(img,start,end,image::Luma([common::MASK_SURROUND_RECT]));
+	imageproc::drawing::draw_line_segment_mut(overlay,start,end,image::Luma([common::OVERLAY_SURROUND_RECT]));
 }
 
 #[allow(dead_code)]
@@ -113,8 +120,7 @@ fn check_limit_line(img:&image::GrayImage,angle:u32,offset:u32) -> bool {
 /// interesting pixels.
 fn find_limit_offset(img:&image::GrayImage,angle:u32) -> u32 {
 	//get max offset
-	let (w,h) = img.dimensions();
-	let max = cmp::max(w,h)/2;
+	let max = calc_half_diagonal(img);
 
 	//loop
 	let mut ret = 0;
@@ -129,8 +135,8 @@ fn find_limit_offset(img:&image::GrayImage,angle:u32) -> u32 {
 	ret
 }
 
-/// Draw the best rectangle for debugging using the given angle.
-pub fn draw_best_rectangle(img:&mut image::GrayImage,angle:u32) {
+/// Draw on the overlay the best rectangle of the mask for debugging using the given angle.
+pub fn draw_best_rectangle(overlay:&mut image::GrayImage,img:&image::GrayImage,angle:u32) {
 	//axis 1
 	let offset1 = find_limit_offset(&img,angle);
 	let offset2 = find_limit_offset(&img,angle+180);
@@ -140,10 +146,10 @@ pub fn draw_best_rectangle(img:&mut image::GrayImage,angle:u32) {
 	let offset4 = find_limit_offset(&img,angle+90+180);
 
 	//draw
-	draw_limit_line(img,angle,offset1);
-	draw_limit_line(img,angle+180,offset2);
-	draw_limit_line(img,angle+90,offset3);
-	draw_limit_line(img,angle+90+180,offset4);
+	draw_limit_line(overlay,angle,offset1);
+	draw_limit_line(overlay,angle+180,offset2);
+	draw_limit_line(overlay,angle+90,offset3);
+	draw_limit_line(overlay,angle+90+180,offset4);
 }
 
 /// Find the best rectangle to rotate the piece by considering searching the
@@ -197,29 +203,144 @@ pub fn find_best_rectangle(img:&image::GrayImage) -> u32 {
 	angle_max
 }
 
-/// Finally appply the rotation to get the image in right position
-pub fn do_rotate_gray(img:& image::GrayImage,angle:u32) -> image::GrayImage {
-	//calc center
-	let (w,h) = img.dimensions();
-	let center = ((w / 2) as f32,(h / 2) as f32);
+/// Placement of the piece once rotated : the rotation is done around the centroid of the
+/// mask into a new canvas sized to the rotated piece.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct RotatedFrame {
+	/// Rotation angle in degrees.
+	pub angle: u32,
+	/// Center of rotation in the source image.
+	pub center: (f32,f32),
+	/// Position of the center in the new canvas.
+	pub new_center: (f32,f32),
+	/// Size of the new canvas.
+	pub size: (u32,u32),
+}
+
+impl RotatedFrame {
+	/// Position in the new canvas of a point of the source image.
+	fn forward(self: &Self,x: f32,y: f32) -> (f32,f32) {
+		let (s,c) = (-(self.angle as f32)).to_radians().sin_cos();
+		let (dx,dy) = (x - self.center.0,y - self.center.1);
+		(self.new_center.0 + dx * c - dy * s,self.new_center.1 + dx * s + dy * c)
+	}
+
+	/// Position in the source image of a point of the new canvas.
+	fn backward(self: &Self,x: f32,y: f32) -> (f32,f32) {
+		let (s,c) = (-(self.angle as f32)).to_radians().sin_cos();
+		let (dx,dy) = (x - self.new_center.0,y - self.new_center.1);
+		(self.center.0 + dx * c + dy * s,self.center.1 - dx * s + dy * c)
+	}
+}
+
+/// Compute the exact box surrounding the piece rotated around its centroid to build a canvas
+/// where no piece pixel is lost.
+pub fn calc_rotated_frame(mask:&image::GrayImage,angle:u32) -> RotatedFrame {
+	//centroid
+	let color = image::Luma([common::MASK_PIECE_PIXEL]);
+	let (w,h) = mask.dimensions();
+	let mut sum = (0.0,0.0);
+	let mut cnt = 0;
+	for (x,y,p) in mask.enumerate_pixels() {
+		if *p == color {
+			sum = (sum.0 + x as f32,sum.1 + y as f32);
+			cnt += 1;
+		}
+	}
+	if cnt == 0 {
+		return RotatedFrame{angle: angle,center: ((w / 2) as f32,(h / 2) as f32),new_center: ((w / 2) as f32,(h / 2) as f32),size: (w,h)};
+	}
+	let center = (sum.0 / cnt as f32,sum.1 / cnt as f32);
+
+	//box of the rotated pixels
+	let mut frame = RotatedFrame{angle: angle,center: center,new_center: (0.0,0.0),size: (0,0)};
+	let (mut xmin,mut ymin,mut xmax,mut ymax) = (f32::MAX,f32::MAX,f32::MIN,f32::MIN);
+	for (x,y,p) in mask.enumerate_pixels() {
+		if *p == color {
+			let (rx,ry) = frame.forward(x as f32,y as f32);
+			xmin = xmin.min(rx);
+			ymin = ymin.min(ry);
+			xmax = xmax.max(rx);
+			ymax = ymax.max(ry);
+		}
+	}
 
-	//angle in radian
-	let angle: f32 = (-(angle as f32)).to_radians();
+	//canvas with margins
+	let margin = common::ROTATE_MARGIN as f32;
+	frame.new_center = (margin - xmin.floor(),margin - ymin.floor());
+	frame.size = ((xmax.ceil() - xmin.floor()) as u32 + 1 + 2 * common::ROTATE_MARGIN,(ymax.ceil() - ymin.floor()) as u32 + 1 + 2 * common::ROTATE_MARGIN);
+	frame
+}
 
-	//rotate
+/// Finally appply the rotation to get the image in right position
+pub fn do_rotate_gray(img:& image::GrayImage,frame:&RotatedFrame) -> image::GrayImage {
 	//for mask we don't want interpolation to keep exact color for matchin
-	imageproc::affine::rotate(img,center,angle,imageproc::affine::Interpolation::Nearest)
+	let (w,h) = img.dimensions();
+	image::GrayImage::from_fn(frame.size.0,frame.size.1,|x,y| {
+		let (sx,sy) = frame.backward(x as f32,y as f32);
+		let (sx,sy) = (sx.round(),sy.round());
+		if sx >= 0.0 && sy >= 0.0 && sx < w as f32 && sy < h as f32 {
+			*img.get_pixel(sx as u32,sy as u32)
+		} else {
+			image::Luma([common::MASK_BACKGROUND])
+		}
+	})
 }
 
 /// Finally appply the rotation to get the image in right position
-pub fn do_rotate_rgba(img:& image::RgbaImage,angle:u32) -> image::RgbaImage {
-	//calc center
+pub fn do_rotate_rgba(img:& image::RgbaImage,frame:&RotatedFrame) -> image::RgbaImage {
+	//out of the source we repeat its border (the background) to not blend with black
 	let (w,h) = img.dimensions();
-	let center = ((w / 2) as f32,(h / 2) as f32);
+	let get = |x: i64,y: i64| {
+		let x = x.max(0).min(w as i64 - 1);
+		let y = y.max(0).min(h as i64 - 1);
+		img.get_pixel(x as u32,y as u32).data
+	};
+
+	//bilinear interpolation
+	image::RgbaImage::from_fn(frame.size.0,frame.size.1,|x,y| {
+		let (sx,sy) = frame.backward(x as f32,y as f32);
+		let (x0,y0) = (sx.floor(),sy.floor());
+		let (fx,fy) = (sx - x0,sy - y0);
+		let (x0,y0) = (x0 as i64,y0 as i64);
+		let (p00,p10,p01,p11) = (get(x0,y0),get(x0 + 1,y0),get(x0,y0 + 1),get(x0 + 1,y0 + 1));
+		let mut ret = [0u8;4];
+		for c in 0..4 {
+			let top = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+			let bottom = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+			ret[c] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+		}
+		image::Rgba(ret)
+	})
+}
 
-	//angle in radian
-	let angle: f32 = (-(angle as f32)).to_radians();
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,Luma};
+	use step3_rotate::*;
+	use common;
+
+	#[test]
+	fn rotate_without_clipping() {
+		//long bar filling the image
+		let mask = GrayImage::from_fn(120,20,|x,y| {
+			if x >= 2 && x < 118 && y >= 5 && y < 15 {
+				Luma([common::MASK_PIECE_PIXEL])
+			} else {
+				Luma([common::MASK_BACKGROUND])
+			}
+		});
+		let area = |img: &GrayImage| img.pixels().filter(|p| p.data[0] == common::MASK_PIECE_PIXEL).count() as f32;
+
+		for &angle in [0,45,90].iter() {
+			let frame = calc_rotated_frame(&mask,angle);
+			let rotated = do_rotate_gray(&mask,&frame);
+			let (w,h) = rotated.dimensions();
+			assert!(rotated.enumerate_pixels().all(|(x,y,p)| p.data[0] != common::MASK_PIECE_PIXEL || (x > 0 && y > 0 && x + 1 < w && y + 1 < h)));
+			assert!((area(&rotated) - area(&mask)).abs() / area(&mask) < 0.1);
+		}
 
-	//rotate
-	imageproc::affine::rotate(img,center,angle,imageproc::affine::Interpolation::Bilinear)
-}
\ No newline at end of file
+		let frame = calc_rotated_frame(&mask,90);
+		assert!(frame.size.0 <= 12 + 2 * common::ROTATE_MARGIN && frame.size.1 >= 116 + 2 * common::ROTATE_MARGIN);
+	}
+}
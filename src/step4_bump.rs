@@ -6,14 +6,14 @@
              LICENSE  : CeCILL-C
 *****************************************************/
 
-/// Detect bumps and holes arround the piece and make them another color, this will help to extract corners for the piece.
+/// Detect bumps and holes arround the piece and extract the bump pixels in a separate mask, this will help to extract corners for the piece.
 
 //load external
 extern crate image;
 
 //internal
 use common;
-use piece::{PieceSideInfos,PieceSideType};
+use piece::{PieceSideInfos,PieceSideType,TOP,RIGHT,BOTTOM,LEFT};
 
 //consts
 const BUMP_SEGMENTS: u32 = 20;
@@ -66,8 +66,8 @@ fn count_pixel_on_segment(img: &image::GrayImage,start:(u32,u32),step:(u32,u32),
     cnt
 }
 
-/// Check if a line is a bump and mask pixel if ture
-fn check_and_mask_bump(img: &mut image::GrayImage,start:(u32,u32),step:(u32,u32),size:u32) -> bool {
+/// Check if a line is a bump and move its pixels to the bump mask if true
+fn check_and_mask_bump(img: &mut image::GrayImage,bumps: &mut image::GrayImage,start:(u32,u32),step:(u32,u32),size:u32) -> bool {
     //storage
     let mut segs: Vec<u32> = vec!();
 
@@ -85,18 +85,16 @@ fn check_and_mask_bump(img: &mut image::GrayImage,start:(u32,u32),step:(u32,u32)
         cur += 1;
     }
 
-    //now search non empty (bump)
+    //now search the bumps, irregular cuts can have several ones separated by empty segments
     let mut cnt_bump = 0;
-    while cur < BUMP_SEGMENTS as usize && segs[cur] > 0 {
-        cnt_bump += 1;
-        cur += 1;
-    }
-
-
-    //now search end empty
     let mut cnt_empty_end = 0;
-    while cur < BUMP_SEGMENTS as usize && segs[cur] == 0 {
-        cnt_empty_end += 1;
+    while cur < BUMP_SEGMENTS as usize {
+        if segs[cur] > 0 {
+            cnt_bump += 1;
+            cnt_empty_end = 0;
+        } else {
+            cnt_empty_end += 1;
+        }
         cur += 1;
     }
 
@@ -104,19 +102,19 @@ fn check_and_mask_bump(img: &mut image::GrayImage,start:(u32,u32),step:(u32,u32)
 
     //check if is bump
     let threashold = BUMP_SEGMENTS / 6;
-    if cur >= BUMP_SEGMENTS as usize && cnt_empty_start >= threashold && cnt_bump >= 1 && cnt_bump < BUMP_SEGMENTS / 2 && cnt_empty_end >= threashold {
+    if cnt_empty_start >= threashold && cnt_bump >= 1 && cnt_bump < BUMP_SEGMENTS / 2 && cnt_empty_end >= threashold {
         //mark as bump
         ret = true;
 
-        //erase pixel
-        let color_exp = image::Luma([common::MASK_PIECE_PIXEL]);
-        let color_set = image::Luma([common::MASK_HIDDEN_BUMP]);
+        //move pixels
+        let color = image::Luma([common::MASK_PIECE_PIXEL]);
+        let back = image::Luma([common::MASK_BACKGROUND]);
         for i in 0..size {
             let x = start.0 + step.0 * i;
             let y = start.1 + step.1 * i;
-            let mut pixel = img.get_pixel_mut(x,y);
-            if *pixel == color_exp {
-                *pixel = color_set;
+            if *img.get_pixel(x,y) == color {
+                img.put_pixel(x,y,back);
+                bumps.put_pixel(x,y,color);
             }
         }
     }
@@ -124,8 +122,8 @@ fn check_and_mask_bump(img: &mut image::GrayImage,start:(u32,u32),step:(u32,u32)
     ret
 }
 
-/// Remove bump on one side
-fn remove_bump_one_side(img: &mut image::GrayImage,start:(u32,u32),step:(u32,u32),step_op:(i32,i32),size:u32,size_op:u32) -> PieceSideType {
+/// Remove bump on one side, return its type and the number of bump lines compared to the threshold.
+fn remove_bump_one_side(img: &mut image::GrayImage,bumps: &mut image::GrayImage,start:(u32,u32),step:(u32,u32),step_op:(i32,i32),size:u32,size_op:u32) -> (PieceSideType,f32) {
     //find first none empty line
     let color = image::Luma([common::MASK_PIECE_PIXEL]);
     let mut pos = 0;
@@ -149,7 +147,7 @@ fn remove_bump_one_side(img: &mut image::GrayImage,start:(u32,u32),step:(u32,u32
     //loop until not bump anymore
 	let mut cnt_bump_line = 0;
     for i in pos..size_op {
-        let has = check_and_mask_bump(img,((start.0 as i32+i as i32*step_op.0) as u32,(start.1 as i32+i as i32*step_op.1) as u32),step,size);
+        let has = check_and_mask_bump(img,bumps,((start.0 as i32+i as i32*step_op.0) as u32,(start.1 as i32+i as i32*step_op.1) as u32),step,size);
         if has {
             //println!("Has bump");
 			cnt_bump_line += 1;
@@ -158,28 +156,51 @@ fn remove_bump_one_side(img: &mut image::GrayImage,start:(u32,u32),step:(u32,u32
         }
     }
 
-	if cnt_bump_line >= size_op / BUMP_SEGMENTS {
-    	PieceSideType::Bump
+	//also return how far we are from the threshold
+	let threashold = size_op / BUMP_SEGMENTS;
+	let ratio = cnt_bump_line as f32 / threashold.max(1) as f32;
+	if cnt_bump_line >= threashold {
+    	(PieceSideType::Bump,ratio)
 	} else {
-		PieceSideType::Hole
+		(PieceSideType::Hole,ratio)
 	}
 }
 
-/// Remove bump on 4 sides
-pub fn remove_bumps(img: &mut image::GrayImage) -> PieceSideInfos {
+/// Remove bump on 4 sides. The mask is left untouched, the pixels of the bumps are returned
+/// in a separate mask.
+pub fn remove_bumps(mask: &image::GrayImage) -> (PieceSideInfos,image::GrayImage) {
 	//vars
-    let (w,h) = img.dimensions();
+    let (w,h) = mask.dimensions();
 	let mut infos = PieceSideInfos::new();
+	let mut img = mask.clone();
+	let mut bumps = image::GrayImage::from_pixel(w,h,image::Luma([common::MASK_BACKGROUND]));
 
 	//all side
-    infos.top = remove_bump_one_side(img,(0,0),(1,0),(0,1),w,h);
-    infos.left = remove_bump_one_side(img,(0,0),(0,1),(1,0),h,w);
-    infos.bottom = remove_bump_one_side(img,(0,h-1),(1,0),(0,-1),w,h);
-    infos.right = remove_bump_one_side(img,(w-1,0),(0,1),(-1,0),h,w);
+    let top = remove_bump_one_side(&mut img,&mut bumps,(0,0),(1,0),(0,1),w,h);
+    let left = remove_bump_one_side(&mut img,&mut bumps,(0,0),(0,1),(1,0),h,w);
+    let bottom = remove_bump_one_side(&mut img,&mut bumps,(0,h-1),(1,0),(0,-1),w,h);
+    let right = remove_bump_one_side(&mut img,&mut bumps,(w-1,0),(0,1),(-1,0),h,w);
+
+	//keep the evidence
+	for &(side,(mode,ratio)) in [(TOP,top),(LEFT,left),(BOTTOM,bottom),(RIGHT,right)].iter() {
+		infos.set(side,mode);
+		infos.evidence[side].bump_lines = ratio;
+		infos.update_confidence(side);
+	}
 
 	//debug
 	println!("Bump detected : {:?}",infos);
 
 	//ret
-	infos
+	(infos,bumps)
+}
+
+/// Draw the removed bumps on the overlay for debugging.
+pub fn draw_bumps(overlay: &mut image::GrayImage,bumps: &image::GrayImage) {
+	let color = image::Luma([common::MASK_PIECE_PIXEL]);
+	for (pixel,bump) in overlay.pixels_mut().zip(bumps.pixels()) {
+		if *bump == color {
+			*pixel = image::Luma([common::OVERLAY_HIDDEN_BUMP]);
+		}
+	}
 }
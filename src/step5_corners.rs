@@ -8,12 +8,18 @@
 
 /// Search the corners. This will be usefull to pre-match the sides
 /// before doing pixel matching with the two pictures.
+/// The corners are searched on the contour of the piece as the points turning by about 90
+/// degrees between two straight shoulders, then refined by intersecting the lines fitted on
+/// the shoulders. When it fails we fallback on the closest pixel to the corners of the
+/// surrounding rect.
 
 //load external
 extern crate image;
 
 //internal
 use common;
+use contour;
+use contour::Contour;
 use piece::{PiecePoints,PieceSideInfos,PieceSideType};
 
 //std
@@ -33,7 +39,6 @@ fn search_closer_point_on_line(img: &image::GrayImage,ref_point: (u32,u32),start
 	let (x0,y0) = (start.0 as i32,start.1 as i32);
 	let (rx0,ry0) = (ref_point.0 as i32,ref_point.1 as i32);
 	let color = image::Luma([common::MASK_PIECE_PIXEL]);
-	let color2 = image::Luma([common::MASK_HIDDEN_BUMP]);
 
 	//loop
 	for i in 0..steps {
@@ -49,7 +54,7 @@ fn search_closer_point_on_line(img: &image::GrayImage,ref_point: (u32,u32),start
 
 			//check pixel
 			let p = img.get_pixel(ux,uy);
-			if *p == color || *p == color2 {
+			if *p == color {
 				//calc distance
 				let dx = x - rx0;
 				let dy = y - ry0;
@@ -106,15 +111,14 @@ pub fn extract_surrounding_rect(img: &image::GrayImage) -> (u32,u32,u32,u32) {
 	let mut xmax = 0;
 	let mut ymin = u32::MAX;
 	let mut ymax = 0;
-	let color1 = image::Luma([common::MASK_PIECE_PIXEL]);
-	let color2 = image::Luma([common::MASK_HIDDEN_BUMP]);
+	let color = image::Luma([common::MASK_PIECE_PIXEL]);
 	let (w,h) = img.dimensions();
 
 	//loop on all pixels
 	for x in 0..w {
 		for y in 0..h {
 			let p = img.get_pixel(x,y);
-			if *p == color1 || *p == color2 {
+			if *p == color {
 				if x < xmin {
 					xmin = x;
 				}
@@ -135,35 +139,174 @@ pub fn extract_surrounding_rect(img: &image::GrayImage) -> (u32,u32,u32,u32) {
 	(xmin,ymin,xmax,ymax)
 }
 
-pub fn draw_point(img: &mut image::GrayImage,coord: (u32,u32)) {
+/// Convert a point found on the pixels, keeping the not found ones.
+pub fn to_point(coord: (u32,u32)) -> (f32,f32) {
+	if coord.0 == u32::MAX || coord.1 == u32::MAX {
+		(f32::MAX,f32::MAX)
+	} else {
+		(coord.0 as f32,coord.1 as f32)
+	}
+}
+
+fn to_f32(coord: (u32,u32)) -> (f32,f32) {
+	(coord.0 as f32,coord.1 as f32)
+}
+
+fn norm(v: (f32,f32)) -> f32 {
+	(v.0 * v.0 + v.1 * v.1).sqrt()
+}
+
+/// Mean distance of the contour points between the two indexes to the chord joining them.
+fn calc_straight_error(contour: &Contour,from: usize,span: usize) -> f32 {
+	let n = contour.len();
+	let a = to_f32(contour[from % n]);
+	let b = to_f32(contour[(from + span) % n]);
+	let (dx,dy) = (b.0 - a.0,b.1 - a.1);
+	let len = norm((dx,dy)).max(1.0);
+	let sum: f32 = (0..span).map(|k| {
+		let p = to_f32(contour[(from + k) % n]);
+		((p.0 - a.0) * dy - (p.1 - a.1) * dx).abs() / len
+	}).sum();
+	sum / span as f32
+}
+
+/// Score in [0,1] how much the contour point looks like a corner pointing to the given direction :
+/// turn of 90 degrees, straight shoulders on both sides.
+fn calc_corner_score(contour: &Contour,i: usize,span: usize,dir: (f32,f32)) -> f32 {
+	//points
+	let n = contour.len();
+	let a = to_f32(contour[(i + n - span) % n]);
+	let b = to_f32(contour[i]);
+	let c = to_f32(contour[(i + span) % n]);
+	let (u,v) = ((b.0 - a.0,b.1 - a.1),(c.0 - b.0,c.1 - b.1));
+	let (lu,lv) = (norm(u),norm(v));
+	if lu == 0.0 || lv == 0.0 {
+		return 0.0;
+	}
+
+	//turning angle, positive for the convex turns as the contour is clockwise
+	let turn = (u.0 * v.1 - u.1 * v.0).atan2(u.0 * v.0 + u.1 * v.1).to_degrees();
+	let angle_score = 1.0 - (turn - 90.0).abs() / 45.0;
+
+	//straight shoulders
+	let err = calc_straight_error(contour,i + n - span,span).max(calc_straight_error(contour,i,span));
+	let straight_score = 1.0 - err / common::CORNER_MAX_STRAIGHT_ERR;
+
+	//pointing outside in the expected direction
+	let out = (u.0 / lu - v.0 / lv,u.1 / lu - v.1 / lv);
+	let cos = (out.0 * dir.0 + out.1 * dir.1) / (norm(out) * norm(dir)).max(f32::EPSILON);
+	let dir_score = (cos - 0.5) / 0.5;
+
+	angle_score.max(0.0) * straight_score.max(0.0) * dir_score.max(0.0)
+}
+
+/// Fit a line on the points, return a point of the line and its direction.
+fn fit_line(points: &Vec<(f32,f32)>) -> ((f32,f32),(f32,f32)) {
+	let n = points.len() as f32;
+	let (mx,my) = points.iter().fold((0.0,0.0),|acc,p| (acc.0 + p.0 / n,acc.1 + p.1 / n));
+	let (mut sxx,mut syy,mut sxy) = (0.0,0.0,0.0);
+	for p in points.iter() {
+		sxx += (p.0 - mx) * (p.0 - mx);
+		syy += (p.1 - my) * (p.1 - my);
+		sxy += (p.0 - mx) * (p.1 - my);
+	}
+	let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+	((mx,my),(angle.cos(),angle.sin()))
+}
+
+/// Intersection of two lines, None if they are parallel.
+fn intersect_lines(l1: ((f32,f32),(f32,f32)),l2: ((f32,f32),(f32,f32))) -> Option<(f32,f32)> {
+	let (((x1,y1),(dx1,dy1)),((x2,y2),(dx2,dy2))) = (l1,l2);
+	let det = dx1 * dy2 - dy1 * dx2;
+	if det.abs() < 1e-3 {
+		None
+	} else {
+		let t = ((x2 - x1) * dy2 - (y2 - y1) * dx2) / det;
+		Some((x1 + t * dx1,y1 + t * dy1))
+	}
+}
+
+/// Move the corner at the intersection of the lines fitted on its two shoulders. The few points
+/// just next to the corner are ignored as the cut makes it a bit round.
+fn refine_corner(contour: &Contour,i: usize,span: usize) -> (f32,f32) {
+	let n = contour.len();
+	let skip = common::CORNER_REFINE_SKIP.min(span / 2);
+	let before: Vec<(f32,f32)> = (skip..(span + 1)).map(|k| to_f32(contour[(i + n - k) % n])).collect();
+	let after: Vec<(f32,f32)> = (skip..(span + 1)).map(|k| to_f32(contour[(i + k) % n])).collect();
+	let corner = to_f32(contour[i]);
+	match intersect_lines(fit_line(&before),fit_line(&after)) {
+		Some(p) if norm((p.0 - corner.0,p.1 - corner.1)) <= span as f32 => p,
+		_ => corner,
+	}
+}
+
+/// Search the 4 corners (top left, top right, bottom right, bottom left) from the curvature of
+/// the contour. Return the position and the confidence of the ones found.
+pub fn find_contour_corners(img: &image::GrayImage) -> [Option<((f32,f32),f32)>;4] {
+	//vars
+	let mut ret = [None;4];
+	let contour = contour::extract_contour(img);
+	let (xmin,ymin,xmax,ymax) = extract_surrounding_rect(img);
+	if contour.is_empty() {
+		return ret;
+	}
+	let span = (((xmax - xmin).min(ymax - ymin) as f32 * common::CORNER_SPAN_RATIO) as usize).max(common::CORNER_MIN_SPAN);
+	if contour.len() < 4 * span {
+		return ret;
+	}
+
+	//best point in each quarter of the piece
+	let center = ((xmin + xmax) as f32 / 2.0,(ymin + ymax) as f32 / 2.0);
+	let dirs = [(-1.0,-1.0),(1.0,-1.0),(1.0,1.0),(-1.0,1.0)];
+	for (k,dir) in dirs.iter().enumerate() {
+		let mut best = (0.0,0);
+		for (i,p) in contour.iter().enumerate() {
+			let (dx,dy) = (p.0 as f32 - center.0,p.1 as f32 - center.1);
+			if dx * dir.0 > 0.0 && dy * dir.1 > 0.0 {
+				let score = calc_corner_score(&contour,i,span,*dir);
+				if score > best.0 {
+					best = (score,i);
+				}
+			}
+		}
+		if best.0 >= common::CORNER_MIN_CONFIDENCE {
+			ret[k] = Some((refine_corner(&contour,best.1,span),best.0));
+		}
+	}
+
+	ret
+}
+
+/// Draw a point on the overlay, with a different color over the piece and over the background.
+pub fn draw_point(overlay: &mut image::GrayImage,img: &image::GrayImage,coord: (f32,f32)) {
 	//vars
-	let pinterest = image::Luma([common::MASK_POINTS_INTREST]);
-	let pback = image::Luma([common::MASK_POINTS_BACK]);
+	let pinterest = image::Luma([common::OVERLAY_POINTS_INTREST]);
+	let pback = image::Luma([common::OVERLAY_POINTS_BACK]);
 	let color = image::Luma([common::MASK_BACKGROUND]);
 
 	//draw
-	if coord.0 != u32::MAX && coord.1 != u32::MAX {
+	if coord.0 != f32::MAX && coord.1 != f32::MAX {
 		let nb:i32 = 5;
 		for x in -nb..nb {
 			for y in -nb..nb {
-				let x = (coord.0 as i32+x) as u32;
-				let y = (coord.1 as i32+y) as u32;
+				let x = (coord.0.round() as i32+x) as u32;
+				let y = (coord.1.round() as i32+y) as u32;
 				if *img.get_pixel(x,y) == color {
-					img.put_pixel(x,y,pback);
+					overlay.put_pixel(x,y,pback);
 				} else {
-					img.put_pixel(x,y,pinterest);
+					overlay.put_pixel(x,y,pinterest);
 				}
 			}
 		}
 	}
 }
 
-pub fn draw_corners(img: &mut image::GrayImage,points: &PiecePoints) {
+pub fn draw_corners(overlay: &mut image::GrayImage,img: &image::GrayImage,points: &PiecePoints) {
 	//point
-	draw_point(img,points.top_left_corner);
-	draw_point(img,points.top_right_corner);
-	draw_point(img,points.bottom_left_corner);
-	draw_point(img,points.bottom_right_corner);
+	draw_point(overlay,img,points.top_left_corner);
+	draw_point(overlay,img,points.top_right_corner);
+	draw_point(overlay,img,points.bottom_left_corner);
+	draw_point(overlay,img,points.bottom_right_corner);
 }
 
 fn get_bump_offset(typex: &PieceSideType, typey: &PieceSideType,w:u32) -> (u32,u32) {
@@ -195,22 +338,80 @@ pub fn extract_piece_points(img: &image::GrayImage,side_infos: &PieceSideInfos)
 
 	println!("Rect {:?}",(xmin,ymin,xmax,ymax));
 
-	//search them
-	let (ox,oy) = get_bump_offset(&side_infos.left,&side_infos.top,steps/4);
-	points.top_left_corner = search_corner(img,(xmin+ox,ymin+oy),(1,1),steps);
-
-	let (ox,oy) = get_bump_offset(&side_infos.right,&side_infos.top,steps/4);
-	points.top_right_corner = search_corner(img,(xmax-ox,ymin+oy),(-1,1),steps);
-
-	let (ox,oy) = get_bump_offset(&side_infos.left,&side_infos.bottom,steps/4);
-	points.bottom_left_corner = search_corner(img,(xmin+ox,ymax-oy),(1,-1),steps);
-
-	let (ox,oy) = get_bump_offset(&side_infos.right,&side_infos.bottom,steps/4);
-	points.bottom_right_corner = search_corner(img,(xmax-ox,ymax-oy),(-1,-1),steps);
+	//search them from the curvature of the contour
+	let found = find_contour_corners(img);
+
+	//fallback on the pixel closer to the corners of the rect
+	let mut corners = [(0.0,0.0);4];
+	for k in 0..4 {
+		corners[k] = match found[k] {
+			Some((coord,confidence)) => {
+				points.corner_confidence[k] = confidence;
+				coord
+			},
+			None => {
+				println!("Corner {} not found on the contour, use the surrounding rect",k);
+				let coord = match k {
+					0 => {
+						let (ox,oy) = get_bump_offset(&side_infos.left,&side_infos.top,steps/4);
+						search_corner(img,(xmin+ox,ymin+oy),(1,1),steps)
+					},
+					1 => {
+						let (ox,oy) = get_bump_offset(&side_infos.right,&side_infos.top,steps/4);
+						search_corner(img,(xmax-ox,ymin+oy),(-1,1),steps)
+					},
+					2 => {
+						let (ox,oy) = get_bump_offset(&side_infos.right,&side_infos.bottom,steps/4);
+						search_corner(img,(xmax-ox,ymax-oy),(-1,-1),steps)
+					},
+					_ => {
+						let (ox,oy) = get_bump_offset(&side_infos.left,&side_infos.bottom,steps/4);
+						search_corner(img,(xmin+ox,ymax-oy),(1,-1),steps)
+					},
+				};
+				to_point(coord)
+			},
+		};
+	}
+	points.top_left_corner = corners[0];
+	points.top_right_corner = corners[1];
+	points.bottom_right_corner = corners[2];
+	points.bottom_left_corner = corners[3];
 
 	//print
 	println!("Points : {:?}",points);	
 
 	//return
 	points
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,Luma};
+	use step5_corners::*;
+	use common;
+
+	#[test]
+	fn corners_from_contour() {
+		//square with a bump on top and a hole on the right
+		let mask = GrayImage::from_fn(160,160,|x,y| {
+			let (x,y) = (x as i32,y as i32);
+			let bump = (x - 70) * (x - 70) + (y - 20) * (y - 20) < 144;
+			let hole = (x - 119) * (x - 119) + (y - 70) * (y - 70) < 144;
+			let square = x >= 20 && x < 120 && y >= 20 && y < 120;
+			if (square || bump) && !hole {
+				Luma([common::MASK_PIECE_PIXEL])
+			} else {
+				Luma([common::MASK_BACKGROUND])
+			}
+		});
+
+		let found = find_contour_corners(&mask);
+		let expected = [(20.0,20.0),(119.0,20.0),(119.0,119.0),(20.0,119.0)];
+		for (f,e) in found.iter().zip(expected.iter()) {
+			let (coord,confidence) = f.expect("Corner not found !");
+			assert!(confidence > 0.5);
+			assert!((coord.0 - e.0).abs() <= 1.0 && (coord.1 - e.1).abs() <= 1.0,"{:?} != {:?}",coord,e);
+		}
+	}
+}
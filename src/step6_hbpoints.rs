@@ -8,17 +8,23 @@
 
 /// Search the bump/hole extrem points. This will be usefull to pre-match the sides
 /// before doing pixel matching with the two pictures.
+/// Each tab is fitted with a small model on the contour of its side (neck, circular head), its
+/// end point is taken on the fitted circle so a noisy pixel does not move it. The old scan of
+/// the pixels is kept as fallback when the fit fails.
 
 //load external
 extern crate image;
 
 //internal
 use common;
+use contour;
+use contour::Contour;
 use step5_corners;
-use piece::{PiecePoints,PieceSideInfos,PieceSideType};
+use piece::{PiecePoints,PieceSideInfos,PieceSideType,PieceTab,TOP,RIGHT,BOTTOM,LEFT};
 
 //std
 use std::u32;
+use std::f32;
 
 fn make_hole_scan(img: &image::GrayImage,start:(u32,u32),search:(i32,i32),search_steps:u32) -> ((u32,u32),u32) {
 	//vars
@@ -76,8 +82,8 @@ fn find_hole_point(img: &image::GrayImage,start:(u32,u32),step:(u32,u32),search:
 	coord
 }
 
-fn find_bump_point(img: &image::GrayImage,start:(u32,u32),step:(u32,u32),steps:u32) -> (u32,u32) {
-	let color = image::Luma([common::MASK_HIDDEN_BUMP]);
+fn find_bump_point(bumps: &image::GrayImage,start:(u32,u32),step:(u32,u32),steps:u32) -> (u32,u32) {
+	let color = image::Luma([common::MASK_PIECE_PIXEL]);
 	let mut found: Vec<(u32,u32)> = vec!();
 	let mut coord = (0,0);
 	
@@ -85,7 +91,7 @@ fn find_bump_point(img: &image::GrayImage,start:(u32,u32),step:(u32,u32),steps:u
 		let x = start.0 + i * step.0;
 		let y = start.1 + i * step.1;
 
-		if *img.get_pixel(x,y) == color {
+		if *bumps.get_pixel(x,y) == color {
 			found.push((x,y));
 		}
 	}
@@ -100,67 +106,430 @@ fn find_bump_point(img: &image::GrayImage,start:(u32,u32),step:(u32,u32),steps:u
 	coord
 }
 
-pub fn draw_corners(img: &mut image::GrayImage,points: &PiecePoints) {
+/// Frame of a side : origin on its first corner, unit vector along the side, unit normal
+/// pointing outside of the piece and length.
+fn calc_side_frame(c0: (f32,f32),c1: (f32,f32)) -> ((f32,f32),(f32,f32),(f32,f32),f32) {
+	let (dx,dy) = (c1.0 - c0.0,c1.1 - c0.1);
+	let len = (dx * dx + dy * dy).sqrt();
+	let u = (dx / len,dy / len);
+	//the contour is clockwise with y going down so outside is on the left
+	let n = (u.1,-u.0);
+	(c0,u,n,len)
+}
+
+/// Index of the contour point closest to the given one.
+fn find_closest_point(contour: &Contour,p: (f32,f32)) -> usize {
+	let dist = |c: &(u32,u32)| (c.0 as f32 - p.0).powi(2) + (c.1 as f32 - p.1).powi(2);
+	let mut best = 0;
+	for i in 1..contour.len() {
+		if dist(&contour[i]) < dist(&contour[best]) {
+			best = i;
+		}
+	}
+	best
+}
+
+/// Fit a circle on the points (algebraic least squares), return the center and radius.
+fn fit_circle(points: &[(f32,f32)]) -> Option<((f32,f32),f32)> {
+	//work around the mean to keep precision
+	let n = points.len() as f32;
+	if points.len() < 3 {
+		return None;
+	}
+	let (mx,my) = points.iter().fold((0.0,0.0),|acc,p| (acc.0 + p.0 / n,acc.1 + p.1 / n));
+
+	//normal equations of x²+y² + a.x + b.y + c = 0
+	let (mut sxx,mut sxy,mut syy,mut sxz,mut syz,mut sz) = (0.0,0.0,0.0,0.0,0.0,0.0);
+	for p in points.iter() {
+		let (x,y) = (p.0 - mx,p.1 - my);
+		let z = x * x + y * y;
+		sxx += x * x;
+		sxy += x * y;
+		syy += y * y;
+		sxz += x * z;
+		syz += y * z;
+		sz += z;
+	}
+
+	//the sums of x and y are 0 around the mean so c is decoupled
+	let det = sxx * syy - sxy * sxy;
+	if det.abs() < f32::EPSILON {
+		return None;
+	}
+	let a = (-sxz * syy + syz * sxy) / det;
+	let b = (-syz * sxx + sxz * sxy) / det;
+	let c = -sz / n;
+	let (cx,cy) = (-a / 2.0,-b / 2.0);
+	let r2 = cx * cx + cy * cy - c;
+	if r2 <= 0.0 {
+		None
+	} else {
+		Some(((cx + mx,cy + my),r2.sqrt()))
+	}
+}
+
+/// Position along the side where the profile goes under the given height when walking from
+/// the end of the tab in the given direction (interpolated between the two points).
+fn find_crossing(profile: &[(f32,f32)],from: usize,dir: i32,level: f32) -> Option<f32> {
+	let mut i = from as i32;
+	while i + dir >= 0 && ((i + dir) as usize) < profile.len() {
+		let (a,b) = (profile[i as usize],profile[(i + dir) as usize]);
+		if b.1 < level {
+			let ratio = (a.1 - level) / (a.1 - b.1);
+			return Some(a.0 + ratio * (b.0 - a.0));
+		}
+		i += dir;
+	}
+	None
+}
+
+/// Fit the tab model on the profile of a side, the points being (position along the side,
+/// height toward the end of the tab).
+fn fit_tab_profile(profile: &[(f32,f32)],len: f32,mode: PieceSideType) -> Option<PieceTab> {
+	//end of the tab
+	let (apex,depth) = profile.iter().enumerate().fold((0,f32::MIN),|acc,(i,p)| if p.1 > acc.1 {(i,p.1)} else {acc});
+	if depth < (len * common::TAB_MIN_DEPTH_RATIO).max(2.0) {
+		return None;
+	}
+
+	//head, we take the points of the top of the tab around the end
+	let level = depth * common::TAB_HEAD_LEVEL;
+	let mut first = apex;
+	while first > 0 && profile[first - 1].1 >= level {
+		first -= 1;
+	}
+	let mut last = apex;
+	while last + 1 < profile.len() && profile[last + 1].1 >= level {
+		last += 1;
+	}
+	let head = &profile[first..(last + 1)];
+	let (center,radius) = fit_circle(head)?;
+	let fit_error = head.iter().map(|p| ((p.0 - center.0).powi(2) + (p.1 - center.1).powi(2)).sqrt() - radius)
+		.map(|e| e.abs()).sum::<f32>() / head.len() as f32;
+
+	//neck, narrowest part under the head center
+	let min_level = depth * common::TAB_NECK_MIN_LEVEL;
+	let max_level = center.1.min(depth * common::TAB_HEAD_LEVEL).max(min_level);
+	let mut neck = (f32::MAX,0.0);
+	let mut cur = min_level;
+	while cur <= max_level {
+		if let (Some(t0),Some(t1)) = (find_crossing(profile,apex,-1,cur),find_crossing(profile,apex,1,cur)) {
+			if t1 - t0 < neck.0 {
+				neck = (t1 - t0,(t0 + t1) / 2.0);
+			}
+		}
+		cur += 1.0;
+	}
+	if neck.0 == f32::MAX {
+		return None;
+	}
+
+	Some(PieceTab {
+		mode: mode,
+		neck_pos: neck.1,
+		neck_width: neck.0,
+		head_center: center,
+		head_radius: radius,
+		depth: depth,
+		offset: center.0 - len / 2.0,
+		fit_error: fit_error,
+		point: (0.0,0.0),
+	})
+}
+
+/// Fit the tabs of the side going from corner c0 to corner c1 (clockwise order). The profile of
+/// the side is cut where it crosses the line joining the corners and each part going far enough
+/// from it is a bump (outside) or a hole (inside), so a side can have no tab or several ones at
+/// any place.
+fn fit_side_tabs(contour: &Contour,c0: (f32,f32),c1: (f32,f32)) -> Vec<PieceTab> {
+	//check
+	let mut tabs = vec!();
+	if c0.0 == f32::MAX || c1.0 == f32::MAX {
+		return tabs;
+	}
+
+	//frame
+	let (origin,u,n,len) = calc_side_frame(c0,c1);
+
+	//profile of the contour between the two corners
+	let cnt = contour.len();
+	let i0 = find_closest_point(contour,c0);
+	let i1 = find_closest_point(contour,c1);
+	let profile: Vec<(f32,f32)> = (0..((i1 + cnt - i0) % cnt + 1)).map(|k| {
+		let p = contour[(i0 + k) % cnt];
+		let (dx,dy) = (p.0 as f32 - origin.0,p.1 as f32 - origin.1);
+		(dx * u.0 + dy * u.1,dx * n.0 + dy * n.1)
+	}).collect();
+
+	//cut in parts on the same side of the line
+	let side_of = |h: f32| if h > 0.0 {1.0} else if h < 0.0 {-1.0} else {0.0};
+	let min_depth = (len * common::TAB_MIN_DEPTH_RATIO).max(2.0);
+	let mut start = 0;
+	while start < profile.len() {
+		//extend the part
+		let sign = side_of(profile[start].1);
+		let mut end = start;
+		while end + 1 < profile.len() && side_of(profile[end + 1].1) == sign {
+			end += 1;
+		}
+
+		//fit it when deep enough, keeping the points around to see the crossings
+		let depth = profile[start..(end + 1)].iter().fold(0.0,|acc: f32,p| acc.max(p.1.abs()));
+		if sign != 0.0 && depth >= min_depth {
+			let mode = if sign > 0.0 {PieceSideType::Bump} else {PieceSideType::Hole};
+			let part: Vec<(f32,f32)> = profile[start.saturating_sub(1)..(end + 2).min(profile.len())].iter().map(|p| (p.0,sign * p.1)).collect();
+			if let Some(mut tab) = fit_tab_profile(&part,len,mode) {
+				//place the end of the tab back on the piece
+				let (t,h) = (tab.head_center.0,sign * (tab.head_center.1 + tab.head_radius));
+				tab.point = (origin.0 + t * u.0 + h * n.0,origin.1 + t * u.1 + h * n.1);
+				tabs.push(tab);
+			}
+		}
+		start = end + 1;
+	}
+
+	tabs
+}
+
+/// Fit the tabs of the 4 sides (TOP,RIGHT,BOTTOM,LEFT) from the corners.
+pub fn fit_piece_tabs(img: &image::GrayImage,points: &PiecePoints) -> [Vec<PieceTab>;4] {
+	let contour = contour::extract_contour(img);
+	let mut tabs = [vec!(),vec!(),vec!(),vec!()];
+	if contour.is_empty() {
+		return tabs;
+	}
+	tabs[TOP] = fit_side_tabs(&contour,points.top_left_corner,points.top_right_corner);
+	tabs[RIGHT] = fit_side_tabs(&contour,points.top_right_corner,points.bottom_right_corner);
+	tabs[BOTTOM] = fit_side_tabs(&contour,points.bottom_right_corner,points.bottom_left_corner);
+	tabs[LEFT] = fit_side_tabs(&contour,points.bottom_left_corner,points.top_left_corner);
+	tabs
+}
+
+/// The deepest tab of the side, which gives its type.
+pub fn get_main_tab(tabs: &Vec<PieceTab>) -> Option<&PieceTab> {
+	tabs.iter().fold(None,|acc: Option<&PieceTab>,t| match acc {
+		Some(best) if best.depth >= t.depth => Some(best),
+		_ => Some(t),
+	})
+}
+
+pub fn draw_corners(overlay: &mut image::GrayImage,img: &image::GrayImage,points: &PiecePoints) {
 	//point
-	step5_corners::draw_point(img,points.top_shape);
-	step5_corners::draw_point(img,points.bottom_shape);
-	step5_corners::draw_point(img,points.left_shape);
-	step5_corners::draw_point(img,points.right_shape);
+	step5_corners::draw_point(overlay,img,points.top_shape);
+	step5_corners::draw_point(overlay,img,points.bottom_shape);
+	step5_corners::draw_point(overlay,img,points.left_shape);
+	step5_corners::draw_point(overlay,img,points.right_shape);
 }
 
-pub fn extract_piece_points(img: &image::GrayImage,points: &mut PiecePoints,side_infos: &PieceSideInfos) {
+/// Take the type of the side from its deepest tab and keep the depth of the deepest bump and
+/// hole to compute the confidence of this type.
+fn update_side_infos(side_infos: &mut PieceSideInfos,side: usize,tabs: &Vec<PieceTab>) {
+	if let Some(tab) = get_main_tab(tabs) {
+		side_infos.set(side,tab.mode);
+	}
+	let depth = |mode: PieceSideType| tabs.iter().filter(|t| t.mode == mode).fold(0.0,|acc: f32,t| acc.max(t.depth));
+	side_infos.evidence[side].bump_depth = depth(PieceSideType::Bump);
+	side_infos.evidence[side].hole_depth = depth(PieceSideType::Hole);
+	side_infos.update_confidence(side);
+}
+
+/// **bumps**: pixels of the bumps removed by step 4. Return the tabs fitted on each side, the
+/// type of the sides is updated from them.
+pub fn extract_piece_points(img: &image::GrayImage,bumps: &image::GrayImage,points: &mut PiecePoints,side_infos: &mut PieceSideInfos) -> [Vec<PieceTab>;4] {
 	//extract surrounding rect
 	let (xmin,ymin,xmax,ymax) = step5_corners::extract_surrounding_rect(img);
 	let (w,h) = ((xmax - xmin),(ymax-ymin));
 
+	//fit the tabs on the contour, the deepest one of each side gives its type and point
+	let tabs = fit_piece_tabs(img,points);
+	let mut shapes = [None;4];
+	for side in 0..4 {
+		shapes[side] = get_main_tab(&tabs[side]).map(|tab| tab.point);
+		update_side_infos(side_infos,side,&tabs[side]);
+	}
+
+	//fallback on the pixels for the sides without tab
 	//top
-	match side_infos.top {
-		PieceSideType::Bump =>	points.top_shape = find_bump_point(img,(xmin,ymin),(1,0),w),
-		PieceSideType::Hole => points.top_shape = find_hole_point(img,
-			(points.top_left_corner.0,ymin),
-			(1,0),(0,1),
-			points.top_right_corner.0 - points.top_left_corner.0,
-			h
-		),
-		PieceSideType::Unknown => panic!("Should not get Unknwon here !"),
+	if shapes[TOP].is_none() {
+		match side_infos.top {
+			PieceSideType::Bump =>	points.top_shape = step5_corners::to_point(find_bump_point(bumps,(xmin,ymin),(1,0),w)),
+			PieceSideType::Hole => points.top_shape = step5_corners::to_point(find_hole_point(img,
+				(points.top_left_corner.0 as u32,ymin),
+				(1,0),(0,1),
+				(points.top_right_corner.0 - points.top_left_corner.0) as u32,
+				h
+			)),
+			PieceSideType::Unknown => {},
+		}
 	}
 
 	//left
-	match side_infos.left {
-		PieceSideType::Bump =>	points.left_shape = find_bump_point(img,(xmin,ymin),(0,1),h),
-		PieceSideType::Hole => points.left_shape = find_hole_point(img,
-			(xmin,points.top_left_corner.1),
-			(0,1),(1,0),
-			points.bottom_left_corner.1 - points.top_left_corner.1,
-			w
-		),
-		PieceSideType::Unknown => panic!("Should not get Unknwon here !"),
+	if shapes[LEFT].is_none() {
+		match side_infos.left {
+			PieceSideType::Bump =>	points.left_shape = step5_corners::to_point(find_bump_point(bumps,(xmin,ymin),(0,1),h)),
+			PieceSideType::Hole => points.left_shape = step5_corners::to_point(find_hole_point(img,
+				(xmin,points.top_left_corner.1 as u32),
+				(0,1),(1,0),
+				(points.bottom_left_corner.1 - points.top_left_corner.1) as u32,
+				w
+			)),
+			PieceSideType::Unknown => {},
+		}
 	}
 
 	//right
-	match side_infos.right {
-		PieceSideType::Bump =>	points.right_shape = find_bump_point(img,(xmax,ymin),(0,1),h),
-		PieceSideType::Hole => points.right_shape = find_hole_point(img,
-			(xmax,points.top_right_corner.1),
-			(0,1),(-1,0),
-			points.bottom_right_corner.1 - points.top_right_corner.1,
-			w
-		),
-		PieceSideType::Unknown => panic!("Should not get Unknwon here !"),
+	if shapes[RIGHT].is_none() {
+		match side_infos.right {
+			PieceSideType::Bump =>	points.right_shape = step5_corners::to_point(find_bump_point(bumps,(xmax,ymin),(0,1),h)),
+			PieceSideType::Hole => points.right_shape = step5_corners::to_point(find_hole_point(img,
+				(xmax,points.top_right_corner.1 as u32),
+				(0,1),(-1,0),
+				(points.bottom_right_corner.1 - points.top_right_corner.1) as u32,
+				w
+			)),
+			PieceSideType::Unknown => {},
+		}
 	}
 
 	//bottom
-	match side_infos.bottom {
-		PieceSideType::Bump =>	points.bottom_shape = find_bump_point(img,(xmin,ymax),(1,0),w),
-		PieceSideType::Hole => points.bottom_shape = find_hole_point(img,
-			(points.bottom_left_corner.0,ymax),
-			(1,0),(0,-1),
-			points.bottom_right_corner.0 - points.bottom_left_corner.0,
-			h
-		),
-		PieceSideType::Unknown => panic!("Should not get Unknwon here !"),
+	if shapes[BOTTOM].is_none() {
+		match side_infos.bottom {
+			PieceSideType::Bump =>	points.bottom_shape = step5_corners::to_point(find_bump_point(bumps,(xmin,ymax),(1,0),w)),
+			PieceSideType::Hole => points.bottom_shape = step5_corners::to_point(find_hole_point(img,
+				(points.bottom_left_corner.0 as u32,ymax),
+				(1,0),(0,-1),
+				(points.bottom_right_corner.0 - points.bottom_left_corner.0) as u32,
+				h
+			)),
+			PieceSideType::Unknown => {},
+		}
 	}
 
+	//points of the tabs
+	points.top_shape = shapes[TOP].unwrap_or(points.top_shape);
+	points.right_shape = shapes[RIGHT].unwrap_or(points.right_shape);
+	points.bottom_shape = shapes[BOTTOM].unwrap_or(points.bottom_shape);
+	points.left_shape = shapes[LEFT].unwrap_or(points.left_shape);
+
 	//print
-	println!("Points : {:?}",points);	
-}
\ No newline at end of file
+	println!("Points : {:?}",points);
+	println!("Tabs : {:?}",tabs);
+
+	//ret
+	tabs
+}
+
+#[cfg(test)]
+mod test {
+	use image::{GrayImage,Luma};
+	use step6_hbpoints::*;
+	use piece::{PiecePoints,PieceSideInfos,PieceSideType,PieceTab,TOP,RIGHT,BOTTOM,LEFT};
+	use common;
+
+	#[test]
+	fn fit_tabs() {
+		//square with a bump on top and a hole on the right, both with a neck and a round head
+		let mask = GrayImage::from_fn(160,160,|x,y| {
+			let (x,y) = (x as i32,y as i32);
+			let square = x >= 20 && x < 120 && y >= 40 && y < 140;
+			let bump = (x >= 64 && x < 76 && y >= 25 && y < 45) || (x - 70) * (x - 70) + (y - 22) * (y - 22) <= 144;
+			let hole = (x >= 105 && x < 125 && y >= 84 && y < 96) || (x - 102) * (x - 102) + (y - 90) * (y - 90) <= 144;
+			if (square || bump) && !hole {
+				Luma([common::MASK_PIECE_PIXEL])
+			} else {
+				Luma([common::MASK_BACKGROUND])
+			}
+		});
+		let mut points = PiecePoints::new();
+		points.top_left_corner = (20.0,40.0);
+		points.top_right_corner = (119.0,40.0);
+		points.bottom_right_corner = (119.0,139.0);
+		points.bottom_left_corner = (20.0,139.0);
+
+		let tabs = fit_piece_tabs(&mask,&points);
+		assert!(tabs[BOTTOM].is_empty() && tabs[LEFT].is_empty());
+		let near = |a: f32,b: f32| (a - b).abs() <= 1.5;
+
+		let bump = *get_main_tab(&tabs[TOP]).expect("Bump not fitted !");
+		assert!(near(bump.depth,30.0) && near(bump.head_radius,12.0) && near(bump.neck_width,11.0),"{:?}",bump);
+		assert!(near(bump.head_center.0,50.0) && near(bump.head_center.1,18.0) && near(bump.offset,0.5),"{:?}",bump);
+		assert!(near(bump.point.0,70.0) && near(bump.point.1,10.0),"{:?}",bump);
+
+		let hole = *get_main_tab(&tabs[RIGHT]).expect("Hole not fitted !");
+		assert!(near(hole.depth,29.0) && near(hole.head_radius,12.0) && near(hole.neck_width,13.0),"{:?}",hole);
+		assert!(near(hole.neck_pos,50.0) && near(hole.offset,0.5),"{:?}",hole);
+		assert!(near(hole.point.0,90.0) && near(hole.point.1,90.0),"{:?}",hole);
+	}
+
+	#[test]
+	fn irregular_side() {
+		//square with a small bump on the left of the top side and a deeper hole on its right
+		let mask = GrayImage::from_fn(160,160,|x,y| {
+			let (x,y) = (x as i32,y as i32);
+			let square = x >= 20 && x < 120 && y >= 40 && y < 140;
+			let bump = (x >= 39 && x < 51 && y >= 28 && y < 45) || (x - 45) * (x - 45) + (y - 26) * (y - 26) <= 100;
+			let hole = (x >= 89 && x < 101 && y < 55) || (x - 95) * (x - 95) + (y - 58) * (y - 58) <= 144;
+			if (square || bump) && !hole {
+				Luma([common::MASK_PIECE_PIXEL])
+			} else {
+				Luma([common::MASK_BACKGROUND])
+			}
+		});
+		let mut points = PiecePoints::new();
+		points.top_left_corner = (20.0,40.0);
+		points.top_right_corner = (119.0,40.0);
+		points.bottom_right_corner = (119.0,139.0);
+		points.bottom_left_corner = (20.0,139.0);
+
+		let tabs = fit_piece_tabs(&mask,&points);
+		assert!(tabs[RIGHT].is_empty() && tabs[BOTTOM].is_empty() && tabs[LEFT].is_empty());
+		let modes: Vec<PieceSideType> = tabs[TOP].iter().map(|t| t.mode).collect();
+		assert_eq!(modes,vec!(PieceSideType::Bump,PieceSideType::Hole));
+		let near = |a: f32,b: f32| (a - b).abs() <= 1.5;
+		assert!(near(tabs[TOP][0].offset,-24.5) && near(tabs[TOP][0].depth,24.0),"{:?}",tabs[TOP][0]);
+		assert!(near(tabs[TOP][1].offset,25.5) && near(tabs[TOP][1].depth,30.0),"{:?}",tabs[TOP][1]);
+		assert_eq!(get_main_tab(&tabs[TOP]).unwrap().mode,PieceSideType::Hole);
+	}
+
+	#[test]
+	fn side_confidence() {
+		let tab = |mode: PieceSideType,depth: f32| PieceTab {
+			mode: mode,
+			neck_pos: 50.0,
+			neck_width: 12.0,
+			head_center: (50.0,-depth / 2.0),
+			head_radius: 12.0,
+			depth: depth,
+			offset: 0.0,
+			fit_error: 0.0,
+			point: (0.0,0.0),
+		};
+		let mut infos = PieceSideInfos::new();
+		let tabs = [
+			vec!(tab(PieceSideType::Bump,30.0)),
+			vec!(tab(PieceSideType::Hole,29.0)),
+			Vec::new(),
+			vec!(tab(PieceSideType::Bump,24.0),tab(PieceSideType::Hole,30.0)),
+		];
+		infos.set(BOTTOM,PieceSideType::Hole);
+		infos.evidence[TOP].bump_lines = 3.0;
+		infos.evidence[RIGHT].bump_lines = 2.0;
+		infos.evidence[BOTTOM].bump_lines = 0.4;
+		for side in 0..4 {
+			update_side_infos(&mut infos,side,&tabs[side]);
+		}
+
+		//step 4 agrees on the bump but saw a bump instead of the hole
+		assert_eq!(infos.confidence[TOP],1.0);
+		assert_eq!(infos.confidence[RIGHT],0.5);
+
+		//no tab, only step 4 is used
+		assert_eq!(infos.get(BOTTOM),PieceSideType::Hole);
+		assert!((infos.confidence[BOTTOM] - 0.6).abs() < 1e-5,"{:?}",infos);
+
+		//the bump competes with the hole
+		assert_eq!(infos.get(LEFT),PieceSideType::Hole);
+		assert!(infos.evidence[LEFT].bump_depth == 24.0 && infos.evidence[LEFT].hole_depth == 30.0);
+		assert!(infos.confidence[LEFT] < common::MATCH_MIN_SIDE_CONFIDENCE,"{:?}",infos);
+	}
+}
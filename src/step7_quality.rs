@@ -23,7 +23,7 @@ use std::io::Write;
 
 //internal
 use step5_corners;
-use piece::{Piece,PiecePoints,PieceSideType,PieceSideInfos};
+use piece::{Piece,PiecePoints,PieceSideType,PieceSideInfos,PieceTab,TOP,BOTTOM};
 use common::*;
 
 fn apply_cut(value: f32, cut1:f32, cut2:f32) -> u32 {
@@ -53,7 +53,7 @@ fn fix_bump_hole_sign(value:f32,t: &PieceSideType,sign:f32) -> f32 {
     match t {
         PieceSideType::Bump => {},
         PieceSideType::Hole => ret *= -1.0,
-        PieceSideType::Unknown => panic!("Should not append here !"),
+        PieceSideType::Unknown => ret = 0.0,
     }
 
     ret * sign
@@ -65,14 +65,14 @@ fn check_holes_bumps_topo(p: &PiecePoints, t: &PieceSideInfos, size: (u32,u32),
     let mut mark = 0;
 
     //extract
-    let left1 = 100.0 * (p.left_shape.0 as f32 - p.top_left_corner.0 as f32 ) / w;
-    let left2 = 100.0 * (p.left_shape.0 as f32  - p.bottom_left_corner.0 as f32 ) / w;
-    let right1 = 100.0 * (p.right_shape.0 as f32  - p.top_right_corner.0 as f32 ) / w;
-    let right2 = 100.0 * (p.right_shape.0 as f32  - p.bottom_right_corner.0 as f32 ) / w;
-    let top1 = 100.0 * (p.top_shape.1 as f32  - p.top_left_corner.1 as f32 ) / h;
-    let top2 = 100.0 * (p.top_shape.1 as f32  - p.top_right_corner.1 as f32 ) / h;
-    let bottom1 = 100.0 * (p.bottom_shape.1 as f32  - p.bottom_left_corner.1 as f32 ) / h;
-    let bottom2 = 100.0 * (p.bottom_shape.1 as f32  - p.bottom_right_corner.1 as f32 ) / h;
+    let left1 = 100.0 * (p.left_shape.0 - p.top_left_corner.0 ) / w;
+    let left2 = 100.0 * (p.left_shape.0 - p.bottom_left_corner.0 ) / w;
+    let right1 = 100.0 * (p.right_shape.0 - p.top_right_corner.0 ) / w;
+    let right2 = 100.0 * (p.right_shape.0 - p.bottom_right_corner.0 ) / w;
+    let top1 = 100.0 * (p.top_shape.1 - p.top_left_corner.1 ) / h;
+    let top2 = 100.0 * (p.top_shape.1 - p.top_right_corner.1 ) / h;
+    let bottom1 = 100.0 * (p.bottom_shape.1 - p.bottom_left_corner.1 ) / h;
+    let bottom2 = 100.0 * (p.bottom_shape.1  - p.bottom_right_corner.1 ) / h;
 
     //fix sign
     let left1 = fix_bump_hole_sign(left1,&t.left,1.0);
@@ -107,6 +107,31 @@ fn check_holes_bumps_topo(p: &PiecePoints, t: &PieceSideInfos, size: (u32,u32),
     mark
 }
 
+fn check_tabs(tabs: &[Vec<PieceTab>;4], size: (u32,u32), db: &mut Vec<f32>) -> u32 {
+    //vars
+    let (w,h) = (size.0 as f32,size.1 as f32);
+    let mut mark = 0;
+
+    //a tab well formed has a round head wider than its neck and above it, a single tab is also
+    //expected close to the middle of the side
+    for (side,tabs) in tabs.iter().enumerate() {
+        let len = if side == TOP || side == BOTTOM {w} else {h};
+        let offset = tabs.iter().fold(0.0,|acc: f32,t| acc.max(100.0 * t.offset.abs() / len));
+        let fit_error = tabs.iter().fold(0.0,|acc: f32,t| acc.max(t.fit_error));
+        db.push(tabs.len() as f32);
+        db.push(offset);
+        db.push(fit_error);
+        let well_formed = tabs.iter().all(|t| {
+            (t.head_center.0 - t.neck_pos).abs() <= t.head_radius && t.neck_width < 2.0 * t.head_radius
+        });
+        if !tabs.is_empty() && well_formed && fit_error <= QUALITY_TAB_MAX_FIT_ERROR && (tabs.len() > 1 || offset <= QUALITY_TAB_OFFSET_CUT) {
+            mark += 1;
+        }
+    }
+
+    mark
+}
+
 fn check_holes_bumps(p: &PiecePoints, size: (u32,u32), db: &mut Vec<f32>) -> u32 {
     //vars
     let (w,h) = size;
@@ -161,8 +186,12 @@ fn check_corners(p: &PiecePoints, size: (u32,u32), db: &mut Vec<f32>) -> u32 {
     mark += apply_cut(top,QUALITY_CORNER_CUT1,QUALITY_CORNER_CUT2);
     mark += apply_cut(bottom,QUALITY_CORNER_CUT1,QUALITY_CORNER_CUT2);
 
+    //the corners taken from the surrounding rect or with a weak curvature are less reliable
+    let weak = p.corner_confidence.iter().filter(|c| **c < QUALITY_CORNER_MIN_CONFIDENCE).count() as u32;
+    db.push(weak as f32);
+
     //ret
-    mark
+    mark.saturating_sub(weak)
 }
 
 //main entry point
@@ -181,11 +210,13 @@ pub fn calc_quality_mark(piece: &Piece, dump: i32) -> u32 {
     mark += check_corners(&piece.points,size,&mut db);
     mark += check_holes_bumps(&piece.points,size,&mut db);
     mark += check_holes_bumps_topo(&piece.points,&piece.side_infos,size,&mut db);
+    mark += check_tabs(&piece.tabs,size,&mut db);
 
     //add mark
     db.push(mark as f32);
 
-    //dump db into file
+    //dump db into file, columns : id, 4 corners, number of weak corners, 2 bump/hole alignments, 8 bump/hole positions,
+    //number of tabs, largest offset and fit error of the tabs of the 4 sides, mark
     if dump == 0 || dump == 7 {
         let base = format!("step-7-quality-{:05}.txt",piece.id);
         let mut file = File::create(base).unwrap();
@@ -193,4 +224,28 @@ pub fn calc_quality_mark(piece: &Piece, dump: i32) -> u32 {
     }
 
     mark
-}
\ No newline at end of file
+}
+
+///Implement unit tests of the module
+#[cfg(test)]
+mod test {
+    use step7_quality::*;
+
+    #[test]
+    fn weak_corners() {
+        let mut p = PiecePoints::new();
+        p.top_left_corner = (10.0,10.0);
+        p.top_right_corner = (109.0,10.0);
+        p.bottom_right_corner = (109.0,109.0);
+        p.bottom_left_corner = (10.0,109.0);
+        p.corner_confidence = [0.9;4];
+        let mut db = vec!();
+        assert_eq!(check_corners(&p,(100,100),&mut db),8);
+
+        //one corner from the fallback and one with a weak curvature
+        p.corner_confidence[1] = 0.0;
+        p.corner_confidence[3] = QUALITY_CORNER_MIN_CONFIDENCE / 2.0;
+        assert_eq!(check_corners(&p,(100,100),&mut db),6);
+        assert_eq!(db[db.len() - 1],2.0);
+    }
+}
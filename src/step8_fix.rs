@@ -14,77 +14,107 @@ extern crate imageproc;
 extern crate scoped_pool;
 
 //load std
-use std::path::Path;
-use std::sync::{Arc,Mutex,RwLock};
+use std::sync::{Arc,Mutex};
 
 //pool
 use scoped_pool::Pool;
 
-//load image
-use image::GenericImage;
-
 //local
 use piece;
 use scan;
+use pyramid;
 use common;
 
-pub fn fix_bad_quality(pool: &Pool,all: &piece::PieceVec,file:&str,dump:i32)
+/// **photos**: copy of the photos before the detection painted them (None if the pieces keep their own crop).
+/// **backgrounds**: background color of each photo.
+/// **coarse_size**: size of the downscaled pieces used to test the angles (0 for full resolution).
+pub fn fix_bad_quality(pool: &Pool,all: &piece::PieceVec,photos:&Vec<Option<image::RgbaImage>>,backgrounds:&Vec<image::Rgba<u8>>,coarse_size:u32,dump:i32)
 {
-	//reload image as default was modified for search
-	let mut img2 = image::open(&Path::new(&file)).unwrap();
-	let background = img2.get_pixel(0,0);
-	let rgba = Arc::new(RwLock::new(img2.as_mut_rgba8().unwrap()));
-
 	//loop on all and fix those with bad quality rotation
 	for pp in all.iter() {
 		//extract simple infos in one go and unlock
 		let mut quality: u32;
 		let mut id: u32;
 		let mut position: (u32,u32,u32,u32);
+		let region: Option<image::GrayImage>;
+		let source: Option<image::RgbaImage>;
+		let shape: Option<image::GrayImage>;
+		let photo: Option<&image::RgbaImage>;
+		let background: &image::Rgba<u8>;
 		{
 			let mut p = pp.write().unwrap();
 			quality = p.quality;
 			id = p.id;
 			position = p.position;
+			region = p.region.clone();
+			source = p.source.clone();
+			shape = p.shape.clone();
+			photo = photos[p.photo as usize].as_ref();
+			background = &backgrounds[p.photo as usize];
 		}
 		
 		//check threashold
 		if quality < common::FIX_MIN_QUALITY {
 			println!("Redo for {} => {}",id,quality);
-			let mut best = Arc::new(Mutex::new((0 as u32,0 as u32)));
+			let scores = Arc::new(Mutex::new(vec!()));
+
+			//the angles are tested on a coarse copy of the piece
+			let mut base = match source {
+				Some(source) => piece::Piece::new_with_source(source,background,position,region,id),
+				None => piece::Piece::new_with_region(photo.unwrap(),background,position,region,id),
+			};
+			if let Some(shape) = shape {
+				base.set_shape(shape);
+			}
+			let factor = pyramid::calc_factor(base.mask.dimensions(),coarse_size);
+			let base = &base;
 
 			//brut force all angles in parallel
 			pool.scoped(|scope| {
                 let steps = 90 / common::FIX_ROTATE_ANGLE_RESOLUTION;
 				for step in 0..steps {
                     let angle = step * common::FIX_ROTATE_ANGLE_RESOLUTION;
-					let rgba = Arc::clone(&rgba);
-					let best = Arc::clone(&best);
+					let scores = Arc::clone(&scores);
 					scope.execute(move || {
-						let r = rgba.read().unwrap();
-						let mut test = piece::Piece::new(&r,&background,position,id);
+						let mut test = base.downscale(factor);
 						test.angle = angle;
 						scan::scan_piece(&mut test, -1);
-
-						//compare with global progres
-						{
-							let mut best = best.lock().unwrap();
-							if test.quality > best.0 {
-								best.0 = test.quality;
-								best.1 = angle;
-							}
-						}
+						scores.lock().unwrap().push((test.quality,angle));
 					});
 				}
 			});
 
+			//best first, smaller angle first on equal quality
+			let mut candidates = scores.lock().unwrap().clone();
+			candidates.sort_by(|a,b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+			//the quality cuts are in pixels so re-score the best coarse angles at full resolution
+			if factor > 1 {
+				candidates.truncate(common::FIX_RESCORE_ANGLES);
+				let full = Arc::new(Mutex::new(vec!()));
+				pool.scoped(|scope| {
+					for (rank,&(_,angle)) in candidates.iter().enumerate() {
+						let full = Arc::clone(&full);
+						scope.execute(move || {
+							let mut test = base.scan_copy();
+							test.angle = angle;
+							scan::scan_piece(&mut test, -1);
+							full.lock().unwrap().push((test.quality,rank,angle));
+						});
+					}
+				});
+				let mut full = full.lock().unwrap().clone();
+				full.sort_by(|a,b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+				candidates = full.iter().map(|&(quality,_,angle)| (quality,angle)).collect();
+			}
+			let best = candidates[0];
+
 			//apply 
 			{
 				//rebuild final piece
-				let rgba = rgba.read().unwrap();
 				let mut f = pp.write().unwrap();
-				f.load(&rgba,&background);
-				f.angle = best.lock().unwrap().1;
+				f.load(photo,background);
+				f.angle = best.1;
 				scan::scan_piece(&mut f, -1);
 
 				//dump
@@ -0,0 +1,438 @@
+/*****************************************************
+             PROJECT  : rust-puzzle-solver
+             VERSION  : 0.1.0-dev
+             DATE     : 05/2018
+             AUTHOR   : Valat Sébastien
+             LICENSE  : CeCILL-C
+*****************************************************/
+
+/// For very large photos we do not load the full picture. The PNG file is streamed by tiles of
+/// a few rows, each tile sharing its first row with the last one of the previous tile so the
+/// objects crossing the limit are connected. The foreground is kept as runs of pixels labelled
+/// with a union-find which gives the objects. An object is closed as soon as a row does not touch
+/// it anymore so we only keep the runs of the open objects and of the kept pieces. A second read
+/// copies the crop of each object which is kept by the piece to be reloaded later. The peak memory
+/// is then bounded by one tile and the pieces themselves whatever the size of the photo.
+
+//load external
+extern crate image;
+
+//std
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc,RwLock};
+
+//extern
+use image::{ImageDecoder,ColorType,RgbaImage,GrayImage,Rgba,Luma};
+use image::png::PNGDecoder;
+
+//internal
+use piece::Piece;
+use background::BackgroundModel;
+use step1_detect::Detection;
+use common;
+
+/// Horizontal segment of foreground pixels on one row (x1 included).
+#[derive(Debug,Clone,Copy)]
+struct Run {
+	y: u32,
+	x0: u32,
+	x1: u32,
+	label: usize,
+}
+
+/// Object found in the photo.
+struct Component {
+	square: (u32,u32,u32,u32),
+	area: u32,
+	runs: Vec<Run>,
+}
+
+/// Connected components built row after row. The labels are slots reused once their
+/// component is closed or merged into another one.
+struct Labeling {
+	parent: Vec<usize>,
+	open: Vec<Option<Component>>,
+	free: Vec<usize>,
+	prev: Vec<Run>,
+}
+
+/// Read the rows of a PNG file one tile at a time.
+struct TileReader {
+	decoder: PNGDecoder<BufReader<File>>,
+	width: u32,
+	height: u32,
+	color: ColorType,
+	next: u32,
+	buffer: Vec<u8>,
+}
+
+impl Component {
+	/// Key to sort the objects in the order of the photo rows (first row, then first pixel on it).
+	fn get_order(self: &Self) -> (u32,u32) {
+		let y = self.square.1;
+		(y,self.runs.iter().filter(|r| r.y == y).map(|r| r.x0).min().unwrap_or(0))
+	}
+}
+
+impl Labeling {
+	fn new() -> Self {
+		Self {
+			parent: vec!(),
+			open: vec!(),
+			free: vec!(),
+			prev: vec!(),
+		}
+	}
+
+	/// Get the root of the label.
+	fn find(self: &mut Self,label: usize) -> usize {
+		let mut cur = label;
+		while self.parent[cur] != cur {
+			self.parent[cur] = self.parent[self.parent[cur]];
+			cur = self.parent[cur];
+		}
+		cur
+	}
+
+	/// Merge two labels, the component of the removed root is moved into the kept one.
+	/// Return the removed root if any.
+	fn union(self: &mut Self,a: usize,b: usize) -> Option<usize> {
+		let (ra,rb) = (self.find(a),self.find(b));
+		if ra == rb {
+			return None;
+		}
+		let (root,other) = (ra.min(rb),ra.max(rb));
+		self.parent[other] = root;
+		let mut from = self.open[other].take().unwrap();
+		let to = self.open[root].as_mut().unwrap();
+		let (x,y,w,h) = to.square;
+		let (fx,fy,fw,fh) = from.square;
+		let (xmin,ymin) = (x.min(fx),y.min(fy));
+		let (xmax,ymax) = ((x + w).max(fx + fw),(y + h).max(fy + fh));
+		to.square = (xmin,ymin,xmax - xmin,ymax - ymin);
+		to.area += from.area;
+		to.runs.append(&mut from.runs);
+		Some(other)
+	}
+
+	/// Open a new component with one run.
+	fn open_run(self: &mut Self,y: u32,x0: u32,x1: u32) -> Run {
+		let label = match self.free.pop() {
+			Some(label) => label,
+			None => {
+				self.parent.push(0);
+				self.open.push(None);
+				self.parent.len() - 1
+			}
+		};
+		let run = Run{y: y,x0: x0,x1: x1,label: label};
+		self.parent[label] = label;
+		self.open[label] = Some(Component{square: (x0,y,x1 - x0 + 1,1),area: x1 - x0 + 1,runs: vec!(run)});
+		run
+	}
+
+	/// Close the given components and free their slots, in the order of the photo.
+	fn close(self: &mut Self,labels: Vec<usize>) -> Vec<Component> {
+		let mut ret: Vec<Component> = labels.iter().map(|l| {
+			self.free.push(*l);
+			self.open[*l].take().unwrap()
+		}).collect();
+		ret.sort_by_key(|c| c.get_order());
+		ret
+	}
+
+	/// Add a row of the photo, connecting its runs with the ones of the previous row (8-connectivity).
+	/// Return the components closed by this row.
+	fn push_row(self: &mut Self,y: u32,foreground: &[bool]) -> Vec<Component> {
+		//extract runs
+		let mut cur = vec!();
+		let mut x = 0;
+		while x < foreground.len() {
+			if foreground[x] {
+				let start = x;
+				while x < foreground.len() && foreground[x] {
+					x += 1;
+				}
+				let run = self.open_run(y,start as u32,x as u32 - 1);
+				cur.push(run);
+			} else {
+				x += 1;
+			}
+		}
+
+		//connect with previous row
+		let prev = if self.prev.last().map(|r| r.y + 1 == y).unwrap_or(false) {self.prev.clone()} else {vec!()};
+		let mut merged = vec!();
+		for c in cur.iter() {
+			for p in prev.iter() {
+				if p.x0 > c.x1 + 1 {
+					break;
+				}
+				if p.x1 + 1 >= c.x0 {
+					merged.extend(self.union(c.label,p.label));
+				}
+			}
+		}
+
+		//point on the roots so the merged slots can be reused
+		for c in cur.iter_mut() {
+			c.label = self.find(c.label);
+		}
+		for l in merged {
+			self.parent[l] = l;
+			self.free.push(l);
+		}
+
+		//close the components not continued on this row
+		let mut closed: Vec<usize> = self.prev.iter().map(|r| r.label).filter(|l| self.open[*l].is_some() && !cur.iter().any(|c| c.label == *l)).collect();
+		closed.sort();
+		closed.dedup();
+		self.prev = cur;
+		self.close(closed)
+	}
+
+	/// Close all the remaining components at the end of the photo.
+	fn finish(self: &mut Self) -> Vec<Component> {
+		let mut labels: Vec<usize> = self.prev.iter().map(|r| r.label).collect();
+		labels.sort();
+		labels.dedup();
+		self.prev.clear();
+		self.close(labels)
+	}
+}
+
+impl TileReader {
+	fn open(file: &str) -> Self {
+		let ext = Path::new(file).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+		if ext != "png" {
+			panic!("Tiled mode only support PNG photos, got {} !",file);
+		}
+		let mut decoder = PNGDecoder::new(BufReader::new(File::open(file).unwrap()));
+		let (width,height) = decoder.dimensions().unwrap();
+		let color = decoder.colortype().unwrap();
+		let len = decoder.row_len().unwrap();
+		match color {
+			ColorType::Gray(8) | ColorType::GrayA(8) | ColorType::RGB(8) | ColorType::RGBA(8) => {},
+			_ => panic!("Unsupported color type {:?} for tiled mode !",color),
+		}
+		Self {
+			decoder: decoder,
+			width: width,
+			height: height,
+			color: color,
+			next: 0,
+			buffer: vec![0;len],
+		}
+	}
+
+	/// Read the next row in RGBA.
+	fn read_row(self: &mut Self) -> Vec<Rgba<u8>> {
+		self.decoder.read_scanline(&mut self.buffer).unwrap();
+		self.next += 1;
+		let b = &self.buffer;
+		(0..self.width as usize).map(|x| match self.color {
+			ColorType::Gray(_) => Rgba([b[x],b[x],b[x],255]),
+			ColorType::GrayA(_) => Rgba([b[2*x],b[2*x],b[2*x],b[2*x+1]]),
+			ColorType::RGB(_) => Rgba([b[3*x],b[3*x+1],b[3*x+2],255]),
+			_ => Rgba([b[4*x],b[4*x+1],b[4*x+2],b[4*x+3]]),
+		}).collect()
+	}
+
+	/// Read the next tile of rows, starting with the last row of the previous one. Return the
+	/// index of the first row and the rows, None at the end of the photo.
+	fn read_tile(self: &mut Self,rows: u32,last: Option<Vec<Rgba<u8>>>) -> Option<(u32,Vec<Vec<Rgba<u8>>>)> {
+		if self.next >= self.height {
+			return None;
+		}
+		let first = if last.is_some() {self.next - 1} else {self.next};
+		let mut tile: Vec<Vec<Rgba<u8>>> = last.into_iter().collect();
+		while (tile.len() as u32) < rows && self.next < self.height {
+			tile.push(self.read_row());
+		}
+		Some((first,tile))
+	}
+
+	/// Loop on all the tiles of the photo, the overlapping row is given only once.
+	fn for_each_row<F: FnMut(u32,&Vec<Rgba<u8>>)>(self: &mut Self,rows: u32,mut f: F) {
+		let mut last = None;
+		let mut done = 0;
+		while let Some((first,tile)) = self.read_tile(rows.max(2),last.take()) {
+			for (i,row) in tile.iter().enumerate() {
+				let y = first + i as u32;
+				if y >= done {
+					f(y,row);
+					done = y + 1;
+				}
+			}
+			last = tile.into_iter().last();
+		}
+	}
+}
+
+/// Estimate the background by streaming the borders of the photo.
+fn estimate_background(file: &str,auto: bool,rows: u32) -> BackgroundModel {
+	let mut reader = TileReader::open(file);
+	let (w,h) = (reader.width,reader.height);
+	if !auto {
+		return BackgroundModel {
+			color: reader.read_row()[0],
+			tolerance: 0,
+			ambiguous: 0.0,
+		};
+	}
+
+	//sample the border strips
+	let border = ((w.min(h) as f32 * common::BACKGROUND_BORDER_RATIO) as u32).max(1);
+	let total = (2 * border * w + 2 * border * h) as u64;
+	let step = (total / common::TILED_MAX_BORDER_SAMPLES as u64).max(1) as u32;
+	let mut pixels = vec!();
+	let mut cnt = 0;
+	reader.for_each_row(rows,|y,row| {
+		for (x,p) in row.iter().enumerate() {
+			let x = x as u32;
+			if x < border || y < border || x + border >= w || y + border >= h {
+				if cnt % step == 0 {
+					pixels.push(*p);
+				}
+				cnt += 1;
+			}
+		}
+	});
+	BackgroundModel::from_border_pixels(&pixels)
+}
+
+/// Search all the objects of the photo by streaming it in tiles and extract them as pieces
+/// keeping their own crop. Return the detection and the background model.
+///
+/// **file**: PNG photo.
+/// **auto**: estimate the background from the borders, otherwise take the top left pixel.
+/// **rows**: number of rows of the tiles.
+/// **photo**: index of the photo in the command line.
+pub fn detect_pieces_tiled(file: &str,auto: bool,rows: u32,photo: u32) -> (Detection,BackgroundModel) {
+	//background
+	let mut model = estimate_background(file,auto,rows);
+
+	//find objects
+	let mut reader = TileReader::open(file);
+	let (width,height) = (reader.width,reader.height);
+	let mut labeling = Labeling::new();
+	let (mut other,mut close) = (0,0);
+	let mut ret = Detection {
+		pieces: vec!(),
+		truncated: vec!(),
+	};
+	let mut kept = vec!();
+
+	//filter the objects as soon as they are closed so we only keep the pieces
+	let mut filter = |comps: Vec<Component>,truncated: &mut Vec<(u32,u32,u32,u32)>| {
+		for c in comps {
+			let (x,y,w,h) = c.square;
+			println!("Square : {:?}, area {}",c.square,c.area);
+			if w * h <= common::TILED_MIN_AREA {
+				println!("IGNORE, too small !");
+			} else if x == 0 || y == 0 || x + w == width || y + h == height {
+				println!("IGNORE, touching the border of the photo !");
+				truncated.push(c.square);
+			} else {
+				kept.push(c);
+			}
+		}
+	};
+	reader.for_each_row(rows,|y,row| {
+		let foreground: Vec<bool> = row.iter().map(|p| !model.is_background(p)).collect();
+		other += foreground.iter().filter(|f| **f).count();
+		close += row.iter().filter(|p| model.is_ambiguous(p)).count();
+		filter(labeling.push_row(y,&foreground),&mut ret.truncated);
+	});
+	filter(labeling.finish(),&mut ret.truncated);
+	model.ambiguous = if other == 0 {0.0} else {close as f32 / other as f32};
+
+	//keep the order of the photo for the ids
+	kept.sort_by_key(|c| c.get_order());
+
+	//extract crops
+	let mut crops: Vec<RgbaImage> = kept.iter().map(|c| RgbaImage::from_pixel(c.square.2,c.square.3,model.color)).collect();
+	let mut reader = TileReader::open(file);
+	reader.for_each_row(rows,|y,row| {
+		for (c,crop) in kept.iter().zip(crops.iter_mut()) {
+			let (x0,y0,w,h) = c.square;
+			if y < y0 || y >= y0 + h {
+				continue;
+			}
+			for x in 0..w {
+				let p = row[(x0 + x) as usize];
+				if !model.is_background(&p) {
+					crop.put_pixel(x,y - y0,p);
+				}
+			}
+		}
+	});
+
+	//build pieces
+	for (c,crop) in kept.into_iter().zip(crops.into_iter()) {
+		let (x0,y0,w,h) = c.square;
+		let mut region = GrayImage::new(w,h);
+		for r in c.runs.iter() {
+			for x in r.x0..(r.x1 + 1) {
+				region.put_pixel(x - x0,r.y - y0,Luma([common::MASK_PIECE_PIXEL]));
+			}
+		}
+		let mut p = Piece::new_with_source(crop,&model.color,c.square,Some(region),ret.pieces.len() as u32);
+		p.photo = photo;
+		ret.pieces.push(Arc::new(RwLock::new(p)));
+	}
+
+	(ret,model)
+}
+
+#[cfg(test)]
+mod test {
+	use tiled::*;
+
+	#[test]
+	fn labeling_merge_rows() {
+		//a U shape which is only connected on the last row and a diagonal touching by a corner
+		let rows = vec!(
+			"X...X..X...",
+			"X...X...X..",
+			"XXXXX....X.",
+		);
+		let mut labeling = Labeling::new();
+		let mut comps = vec!();
+		for (y,row) in rows.iter().enumerate() {
+			let foreground: Vec<bool> = row.chars().map(|c| c == 'X').collect();
+			comps.extend(labeling.push_row(y as u32,&foreground));
+		}
+		assert!(comps.is_empty());
+		comps.extend(labeling.finish());
+		assert_eq!(comps.len(),2);
+		assert_eq!(comps[0].square,(0,0,5,3));
+		assert_eq!(comps[0].area,9);
+		assert_eq!(comps[1].square,(7,0,3,3));
+		assert_eq!(comps[1].area,3);
+	}
+
+	#[test]
+	fn labeling_close_objects() {
+		//objects are closed as soon as a row does not continue them and the slots are reused
+		let mut labeling = Labeling::new();
+		let mut closed = vec!();
+		for y in 0..100 {
+			let foreground: Vec<bool> = (0..12).map(|x| (y % 4 != 3) && (x % 4 != 3)).collect();
+			let comps = labeling.push_row(y,&foreground);
+			if y % 4 == 3 {
+				assert_eq!(comps.len(),3);
+				assert_eq!(comps[0].square,(0,y - 3,3,3));
+				assert_eq!(comps[2].square,(8,y - 3,3,3));
+			} else {
+				assert!(comps.is_empty());
+			}
+			closed.extend(comps);
+		}
+		assert!(labeling.finish().is_empty());
+		assert_eq!(closed.len(),75);
+		assert!(labeling.open.len() <= 6);
+	}
+}